@@ -1,18 +1,18 @@
-use oci_registry_client::DockerRegistryClientV2;
+use oci_registry_client::{manifest::host_architecture, DockerRegistryClientV2};
 use std::{env, error::Error, fs::File, io::Write, path::Path};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     let mut args = env::args();
     let image = args.nth(1).unwrap_or("library/alpine".to_string());
-    let reference = args.nth(2).unwrap_or("latest".to_string());
-    let out_dir = args.nth(3).unwrap_or("/tmp".to_string());
+    let reference = args.next().unwrap_or("latest".to_string());
+    let out_dir = args.next().unwrap_or("/tmp".to_string());
 
-    let mut client = DockerRegistryClientV2::new(
+    let mut client = DockerRegistryClientV2::try_new(
         "registry.docker.io",
         "https://registry-1.docker.io",
         "https://auth.docker.io/token",
-    );
+    )?;
 
     match client.auth("repository", &image, "pull").await {
         Ok(token) => client.set_auth_token(Some(token)),
@@ -22,54 +22,50 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
     }
 
-    match client.manifest(&image, &reference).await {
-        Ok(manifest) => {
-            for layer in &manifest.layers {
-                println!("Downloading {} ...", layer.digest);
-                match File::create(Path::new(&out_dir).join(&layer.digest.to_string())) {
-                    Ok(mut out_file) => match client.blob(&image, &layer.digest).await {
-                        Ok(mut blob) => {
-                            loop {
-                                match blob.chunk().await {
-                                    Ok(Some(chunk)) => {
-                                        if let Err(err) = out_file.write_all(&chunk) {
-                                            eprintln!("failed to write layer; err={}", err);
-                                            std::process::exit(-1);
-                                        }
-                                    }
-                                    Ok(None) => break,
-                                    Err(err) => {
-                                        eprintln!("failed to download layer; err={}", err);
-                                        std::process::exit(-1);
-                                    }
-                                }
-                            }
+    let manifest = match client
+        .manifest_for_platform(&image, &reference, "linux", host_architecture())
+        .await
+    {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            eprintln!("failed to get manifest; err={}", err);
+            std::process::exit(-1);
+        }
+    };
+
+    for layer in manifest.unique_layers() {
+        println!("Downloading {} ...", layer.digest);
+        let mut out_file = match File::create(Path::new(&out_dir).join(layer.digest.to_string())) {
+            Ok(out_file) => out_file,
+            Err(err) => {
+                eprintln!("failed to create layer file; err={}", err);
+                std::process::exit(-1);
+            }
+        };
+        let mut blob = match client.blob(&image, &layer.digest).await {
+            Ok(blob) => blob,
+            Err(err) => {
+                eprintln!("failed to fetch layer blob; err={}", err);
+                std::process::exit(-1);
+            }
+        };
 
-                            let downloaded_digest = blob.digest();
-                            if downloaded_digest != layer.digest {
-                                eprintln!(
-                                    "invalid sha256 hash: expected \"{}\", got \"{}\"",
-                                    layer.digest, downloaded_digest
-                                );
-                                std::process::exit(-1);
-                            }
-                        }
-                        Err(err) => {
-                            eprintln!("failed to fetch layer blob; err={}", err);
-                            std::process::exit(-1);
-                        }
-                    },
-                    Err(err) => {
-                        eprintln!("failed to create layer file; err={}", err);
+        // The digest of the layer is verified once the last chunk is read.
+        loop {
+            match blob.chunk().await {
+                Ok(Some(chunk)) => {
+                    if let Err(err) = out_file.write_all(&chunk) {
+                        eprintln!("failed to write layer; err={}", err);
                         std::process::exit(-1);
                     }
                 }
+                Ok(None) => break,
+                Err(err) => {
+                    eprintln!("failed to download layer; err={}", err);
+                    std::process::exit(-1);
+                }
             }
         }
-        Err(err) => {
-            eprintln!("failed to get manifest; err={}", err);
-            std::process::exit(-1);
-        }
     }
 
     Ok(())
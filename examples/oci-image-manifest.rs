@@ -1,5 +1,4 @@
 use oci_registry_client::DockerRegistryClientV2;
-use serde_json;
 use std::env;
 use std::error::Error;
 
@@ -9,11 +8,11 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let image = args.nth(1).unwrap_or("library/alpine".to_string());
     let reference = args.next().unwrap_or("latest".to_string());
 
-    let mut client = DockerRegistryClientV2::new(
+    let mut client = DockerRegistryClientV2::try_new(
         "registry.docker.io",
         "https://registry-1.docker.io",
         "https://auth.docker.io/token",
-    );
+    )?;
 
     match client.auth("repository", &image, "pull").await {
         Ok(token) => client.set_auth_token(Some(token)),
@@ -0,0 +1,237 @@
+//! Layer analysis.
+//!
+//! A [`LayerAnalyzer`] inspects the files of layers, to extract package
+//! metadata for vulnerability scanners for instance. Every analyzer sees the
+//! entries of a layer in a single pass over its tar stream, results are
+//! cached by layer digest (and analyzer name) in the client
+//! [`AnalysisCache`], so layers shared by images are analyzed once.
+//!
+//! When a [content store](crate::store) is set, layers are read from it, or
+//! stored there as they are downloaded, so analyzing pulled images does not
+//! download their layers again.
+//!
+//! ```no_run
+//! use oci_registry_client::{
+//!     analyze::{LayerAnalyzer, LayerVisitor},
+//!     layer::TarEntry,
+//!     manifest::Digest,
+//!     DockerRegistryClientV2,
+//! };
+//! use std::sync::Arc;
+//!
+//! /// Collects the contents of the apk database.
+//! struct ApkPackages;
+//!
+//! impl LayerAnalyzer for ApkPackages {
+//!     fn name(&self) -> &str {
+//!         "apk"
+//!     }
+//!
+//!     fn wants_contents(&self, path: &str) -> bool {
+//!         path == "lib/apk/db/installed"
+//!     }
+//!
+//!     fn start(&self, _layer: &Digest) -> Box<dyn LayerVisitor> {
+//!         Box::new(ApkVisitor(None))
+//!     }
+//! }
+//!
+//! struct ApkVisitor(Option<String>);
+//!
+//! impl LayerVisitor for ApkVisitor {
+//!     fn entry(&mut self, entry: &TarEntry) {
+//!         if let Some(contents) = &entry.contents {
+//!             self.0 = Some(String::from_utf8_lossy(contents).into_owned());
+//!         }
+//!     }
+//!
+//!     fn finish(self: Box<Self>) -> serde_json::Value {
+//!         self.0.into()
+//!     }
+//! }
+//!
+//! # async fn example(client: DockerRegistryClientV2) -> Result<(), Box<dyn std::error::Error>> {
+//! let manifest = client.manifest("library/alpine", "3.19").await?;
+//! let analyzers: Vec<Arc<dyn LayerAnalyzer>> = vec![Arc::new(ApkPackages)];
+//! let results = client.analyze_layers("library/alpine", &manifest, &analyzers).await?;
+//! for (digest, results) in results {
+//!     println!("{}: {:?}", digest, results.get("apk"));
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    errors::ErrorResponse,
+    layer::{entries, Contents, TarEntry},
+    manifest::{Digest, Layer, Manifest},
+    reference::AsRepository,
+    DockerRegistryClientV2,
+};
+use futures_util::StreamExt;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::io::{AsyncBufRead, BufReader};
+
+/// Results of the analyzers run on a layer, keyed by analyzer name.
+pub type AnalysisResults = HashMap<String, serde_json::Value>;
+
+/// An analyzer of layer contents.
+pub trait LayerAnalyzer: Send + Sync {
+    /// Name of this analyzer, identifying its results.
+    fn name(&self) -> &str;
+
+    /// Returns `true` if the contents of the regular file at `path` should be
+    /// read and passed to [`LayerVisitor::entry`].
+    fn wants_contents(&self, path: &str) -> bool {
+        let _ = path;
+        false
+    }
+
+    /// Start the analysis of the layer identified by `layer`.
+    fn start(&self, layer: &Digest) -> Box<dyn LayerVisitor>;
+}
+
+/// The analysis of a single layer, see [`LayerAnalyzer::start`].
+pub trait LayerVisitor: Send {
+    /// Visit an entry of the layer, in archive order.
+    fn entry(&mut self, entry: &TarEntry);
+
+    /// Returns the result of the analysis, once every entry was visited.
+    fn finish(self: Box<Self>) -> serde_json::Value;
+}
+
+/// Cache of analysis results, keyed by layer digest and analyzer name.
+///
+/// Clones share the same entries.
+#[derive(Clone, Debug, Default)]
+pub struct AnalysisCache {
+    results: Arc<Mutex<HashMap<Digest, AnalysisResults>>>,
+}
+
+impl AnalysisCache {
+    /// Returns a new, empty, cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the cached result of `analyzer` for the layer `digest`.
+    pub fn get(&self, digest: &Digest, analyzer: &str) -> Option<serde_json::Value> {
+        let results = self.results.lock().unwrap();
+        results.get(digest)?.get(analyzer).cloned()
+    }
+
+    /// Store the result of `analyzer` for the layer `digest`.
+    pub fn insert(&self, digest: &Digest, analyzer: &str, result: serde_json::Value) {
+        let mut results = self.results.lock().unwrap();
+        results
+            .entry(digest.clone())
+            .or_default()
+            .insert(analyzer.to_owned(), result);
+    }
+}
+
+impl DockerRegistryClientV2 {
+    /// Set the cache of [`DockerRegistryClientV2::analyze_layer`] results.
+    ///
+    /// Defaults to a new cache per client.
+    pub fn set_analysis_cache(&mut self, cache: AnalysisCache) {
+        self.analysis_cache = cache;
+    }
+
+    /// Run `analyzers` on every layer of `manifest`.
+    ///
+    /// Returns the results keyed by layer digest.
+    pub async fn analyze_layers<I: AsRepository + ?Sized>(
+        &self,
+        image: &I,
+        manifest: &Manifest,
+        analyzers: &[Arc<dyn LayerAnalyzer>],
+    ) -> Result<HashMap<Digest, AnalysisResults>, ErrorResponse> {
+        let image = image.as_repository();
+        let mut results = HashMap::new();
+        for layer in &manifest.layers {
+            let layer_results = self.analyze_layer(image, layer, analyzers).await?;
+            results.insert(layer.digest.clone(), layer_results);
+        }
+        Ok(results)
+    }
+
+    /// Run `analyzers` on `layer`.
+    ///
+    /// Cached results are reused, the layer is only read if some analyzer
+    /// has no cached result for it.
+    pub async fn analyze_layer<I: AsRepository + ?Sized>(
+        &self,
+        image: &I,
+        layer: &Layer,
+        analyzers: &[Arc<dyn LayerAnalyzer>],
+    ) -> Result<AnalysisResults, ErrorResponse> {
+        let image = image.as_repository();
+        let digest = &layer.digest;
+        let mut results = AnalysisResults::new();
+        let mut pending = vec![];
+        for analyzer in analyzers {
+            match self.analysis_cache.get(digest, analyzer.name()) {
+                Some(result) => {
+                    results.insert(analyzer.name().to_owned(), result);
+                }
+                None => pending.push(analyzer.clone()),
+            }
+        }
+        if pending.is_empty() {
+            return Ok(results);
+        }
+
+        let mut visitors: Vec<_> = pending
+            .iter()
+            .map(|analyzer| analyzer.start(digest))
+            .collect();
+        let wanted = pending.clone();
+        let contents = Contents::Matching(Box::new(move |path| {
+            wanted.iter().any(|analyzer| analyzer.wants_contents(path))
+        }));
+
+        let entries = entries(self.layer_reader(image, layer).await?, contents).await?;
+        futures_util::pin_mut!(entries);
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            for visitor in visitors.iter_mut() {
+                visitor.entry(&entry);
+            }
+        }
+
+        for (analyzer, visitor) in pending.iter().zip(visitors) {
+            let result = visitor.finish();
+            self.analysis_cache
+                .insert(digest, analyzer.name(), result.clone());
+            results.insert(analyzer.name().to_owned(), result);
+        }
+
+        Ok(results)
+    }
+
+    /// Returns a reader of the blob of `layer`, from the content store when
+    /// one is set.
+    async fn layer_reader(
+        &self,
+        image: &str,
+        layer: &Layer,
+    ) -> Result<Box<dyn AsyncBufRead + Send + Unpin>, ErrorResponse> {
+        let path = match &self.content_store {
+            // Foreign layers are not stored by `store_blob`.
+            Some(_) if !layer.is_foreign() => Some(self.store_blob(image, &layer.digest).await?),
+            Some(store) if store.contains(&layer.digest).await => {
+                Some(store.blob_path(&layer.digest))
+            }
+            _ => None,
+        };
+
+        Ok(match path {
+            Some(path) => Box::new(BufReader::new(tokio::fs::File::open(path).await?)),
+            None => Box::new(self.layer_blob(image, layer).await?.into_reader()),
+        })
+    }
+}
@@ -0,0 +1,527 @@
+//! Docker archive (`docker save`) support.
+//!
+//! A docker archive is a tarball containing a `manifest.json` file that lists
+//! the images it contains, their tags, config and layer files.
+//!
+//! ```no_run
+//! use oci_registry_client::{archive::DockerArchive, DockerRegistryClientV2};
+//!
+//! # async fn example(client: DockerRegistryClientV2) -> Result<(), Box<dyn std::error::Error>> {
+//! let archive = DockerArchive::open("busybox.tar")?;
+//!
+//! for image in archive.images() {
+//!     for blob in image.blobs() {
+//!         client.push_blob("my/busybox", blob).await?;
+//!     }
+//!     client.push_manifest("my/busybox", "latest", &image.manifest()).await?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`DockerRegistryClientV2::pull_to_docker_archive`] writes a pulled image
+//! as a docker archive, to load it with `docker load` without a registry:
+//!
+//! ```no_run
+//! # use oci_registry_client::DockerRegistryClientV2;
+//! # async fn example(client: DockerRegistryClientV2) -> Result<(), Box<dyn std::error::Error>> {
+//! let mut file = tokio::fs::File::create("alpine.tar").await?;
+//! client
+//!     .pull_to_docker_archive("library/alpine", "3.19", &mut file)
+//!     .await?;
+//! // docker load -i alpine.tar
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    audit::Operation,
+    errors::ErrorResponse,
+    layer::GZIP_MAGIC,
+    manifest::{host_architecture, Digest, Manifest, MediaType},
+    push::BlobSource,
+    reference::{AsRepository, AsTagOrDigest},
+    registries, store, DockerRegistryClientV2,
+};
+use flate2::read::MultiGzDecoder;
+use sha2::{Digest as Sha256Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// Media type of the uncompressed layers stored in a docker archive.
+pub const MEDIA_TYPE_LAYER_TAR: &str = "application/vnd.docker.image.rootfs.diff.tar";
+
+/// An entry of the archive `manifest.json` file.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct ArchiveManifestEntry {
+    pub config: String,
+    #[serde(default)]
+    pub repo_tags: Option<Vec<String>>,
+    pub layers: Vec<String>,
+}
+
+/// An image read from a docker archive.
+#[derive(Clone, Debug)]
+pub struct ArchiveImage {
+    pub repo_tags: Vec<String>,
+    pub config: BlobSource,
+    pub layers: Vec<BlobSource>,
+}
+
+impl ArchiveImage {
+    /// Returns every blob referenced by this image (config first).
+    pub fn blobs(&self) -> impl Iterator<Item = &BlobSource> {
+        std::iter::once(&self.config).chain(self.layers.iter())
+    }
+
+    /// Returns an image manifest referencing the archive config and layers.
+    pub fn manifest(&self) -> Manifest {
+        Manifest {
+            schema_version: 2,
+            media_type: Some(MediaType::DockerManifest),
+            config: self.config.to_config(),
+            layers: self.layers.iter().map(BlobSource::to_layer).collect(),
+            artifact_type: None,
+            subject: None,
+            annotations: None,
+        }
+    }
+}
+
+/// A docker archive opened for reading.
+#[derive(Clone, Debug)]
+pub struct DockerArchive {
+    images: Vec<ArchiveImage>,
+    _spool: Option<Arc<SpoolDir>>,
+}
+
+/// Location of a regular file of the archive.
+struct ArchiveFile {
+    path: PathBuf,
+    offset: u64,
+    size: u64,
+    digest: Digest,
+}
+
+/// Files and links of an archive, and its `manifest.json` file.
+#[derive(Default)]
+struct Index {
+    files: HashMap<PathBuf, ArchiveFile>,
+    links: HashMap<PathBuf, PathBuf>,
+    manifest: Option<Vec<u8>>,
+}
+
+/// Temporary directory of the files of a spooled archive, removed when
+/// dropped.
+#[derive(Debug)]
+struct SpoolDir(PathBuf);
+
+impl Drop for SpoolDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+impl DockerArchive {
+    /// Open the docker archive at `path`, optionally gzip compressed.
+    ///
+    /// The archive is read once to index its files and compute their digests,
+    /// this function blocks. Blob contents are read from the archive when
+    /// pushed. A compressed archive is spooled to the temporary directory of
+    /// the system, see [`DockerArchive::from_reader`].
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ErrorResponse> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = File::open(&path)?;
+        let mut magic = [0u8; 2];
+        let compressed = file.read_exact(&mut magic).is_ok() && magic == GZIP_MAGIC;
+        file.seek(SeekFrom::Start(0))?;
+        if compressed {
+            return Self::from_reader(file, std::env::temp_dir());
+        }
+
+        let index = Index::read(file, |entry| {
+            let offset = entry.raw_file_position();
+            let (size, digest) = copy_hashed(entry, &mut io::sink())?;
+            Ok(ArchiveFile {
+                path: path.clone(),
+                offset,
+                size,
+                digest,
+            })
+        })?;
+        Self::from_index(index, None)
+    }
+
+    /// Read a docker archive, optionally gzip compressed, from `reader` (a
+    /// pipe from `docker save` for example).
+    ///
+    /// The files of the archive are written to a temporary directory created
+    /// in `spool_dir`, and read from there when pushed. The temporary
+    /// directory is removed once the archive (and its clones) are dropped,
+    /// keep it alive until its blobs are pushed. This function blocks.
+    ///
+    /// ```no_run
+    /// # use oci_registry_client::archive::DockerArchive;
+    /// # fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// // docker save busybox | gzip | my-tool
+    /// let archive = DockerArchive::from_reader(std::io::stdin(), std::env::temp_dir())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_reader<R: Read, P: AsRef<Path>>(
+        reader: R,
+        spool_dir: P,
+    ) -> Result<Self, ErrorResponse> {
+        let mut reader = BufReader::new(reader);
+        let compressed = reader.fill_buf()?.starts_with(&GZIP_MAGIC);
+        let reader: Box<dyn Read> = match compressed {
+            true => Box::new(MultiGzDecoder::new(reader)),
+            false => Box::new(reader),
+        };
+
+        let dir = spool_dir
+            .as_ref()
+            .join(format!("docker-archive-{}", store::unique_suffix()));
+        std::fs::create_dir_all(&dir)?;
+        let spool = SpoolDir(dir);
+
+        let tmp = spool.0.join("ingest.tmp");
+        let index = Index::read(reader, |entry| {
+            let (size, digest) = copy_hashed(entry, &mut File::create(&tmp)?)?;
+            let path = spool.0.join(&digest.hash);
+            std::fs::rename(&tmp, &path)?;
+            Ok(ArchiveFile {
+                path,
+                offset: 0,
+                size,
+                digest,
+            })
+        })?;
+        Self::from_index(index, Some(Arc::new(spool)))
+    }
+
+    fn from_index(index: Index, spool: Option<Arc<SpoolDir>>) -> Result<Self, ErrorResponse> {
+        let Index {
+            files,
+            links,
+            manifest,
+        } = index;
+        let manifest = manifest.ok_or_else(|| invalid_data("missing manifest.json"))?;
+        let entries: Vec<ArchiveManifestEntry> =
+            serde_json::from_slice(&manifest).map_err(|err| invalid_data(&err.to_string()))?;
+
+        let blob = |name: &str, media_type: MediaType| -> Result<BlobSource, ErrorResponse> {
+            let mut name = normalize(Path::new(name));
+            for _ in 0..16 {
+                match links.get(&name) {
+                    Some(target) => name = target.clone(),
+                    None => break,
+                }
+            }
+
+            let file = files
+                .get(&name)
+                .ok_or_else(|| invalid_data(&format!("missing file {}", name.display())))?;
+            Ok(BlobSource::from_file_range(
+                media_type,
+                file.digest.clone(),
+                file.path.clone(),
+                file.offset,
+                file.size,
+            ))
+        };
+
+        let images = entries
+            .iter()
+            .map(|entry| {
+                Ok(ArchiveImage {
+                    repo_tags: entry.repo_tags.clone().unwrap_or_default(),
+                    config: blob(&entry.config, MediaType::DockerImageConfig)?,
+                    layers: entry
+                        .layers
+                        .iter()
+                        .map(|layer| blob(layer, MediaType::DockerLayer))
+                        .collect::<Result<_, _>>()?,
+                })
+            })
+            .collect::<Result<_, ErrorResponse>>()?;
+
+        Ok(Self {
+            images,
+            _spool: spool,
+        })
+    }
+
+    /// Returns the images contained in this archive.
+    pub fn images(&self) -> &[ArchiveImage] {
+        &self.images
+    }
+}
+
+impl Index {
+    /// Index the entries of the tarball `reader`, storing regular files with
+    /// `store`.
+    fn read<R, F>(reader: R, mut store: F) -> Result<Self, ErrorResponse>
+    where
+        R: Read,
+        F: FnMut(&mut tar::Entry<'_, R>) -> io::Result<ArchiveFile>,
+    {
+        let mut archive = tar::Archive::new(reader);
+        let mut index = Self::default();
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let name = normalize(&entry.path()?);
+
+            match entry.header().entry_type() {
+                tar::EntryType::Symlink => {
+                    if let Some(target) = entry.link_name()? {
+                        let parent = name.parent().unwrap_or_else(|| Path::new(""));
+                        index
+                            .links
+                            .insert(name.clone(), normalize(&parent.join(target)));
+                    }
+                }
+                tar::EntryType::Link => {
+                    if let Some(target) = entry.link_name()? {
+                        index.links.insert(name.clone(), normalize(&target));
+                    }
+                }
+                tar::EntryType::Regular if name == Path::new("manifest.json") => {
+                    let mut contents = vec![];
+                    entry.read_to_end(&mut contents)?;
+                    index.manifest = Some(contents);
+                }
+                tar::EntryType::Regular => {
+                    let file = store(&mut entry)?;
+                    index.files.insert(name, file);
+                }
+                _ => {}
+            }
+        }
+        Ok(index)
+    }
+}
+
+/// Copy `reader` to `writer`, returning the number of bytes copied and their
+/// digest.
+fn copy_hashed<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> io::Result<(u64, Digest)> {
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.input(&buf[..n]);
+        writer.write_all(&buf[..n])?;
+        size += n as u64;
+    }
+    writer.flush()?;
+    Ok((size, Digest::from_sha256(hasher.result())))
+}
+
+impl DockerRegistryClientV2 {
+    /// Pull the image `reference` of `image` and write it to `writer` as a
+    /// docker archive, see [`crate::archive`].
+    ///
+    /// The manifest of the platform of the host (`linux`) is pulled if
+    /// `reference` is a manifest list. Layers are streamed to `writer` as
+    /// served by the registry (`docker load` decompresses them) and
+    /// verified according to the [`crate::verify::VerificationPolicy`] of
+    /// the client. The image is tagged `image:reference` when `reference`
+    /// is a tag, prefixed with the registry host unless it is Docker Hub.
+    ///
+    /// Returns the manifest of the image.
+    pub async fn pull_to_docker_archive<I: AsRepository + ?Sized, T: AsTagOrDigest + ?Sized, W>(
+        &self,
+        image: &I,
+        reference: &T,
+        writer: &mut W,
+    ) -> Result<Manifest, ErrorResponse>
+    where
+        W: AsyncWrite + Unpin + ?Sized,
+    {
+        let image = image.as_repository();
+        let reference = &*reference.as_tag_or_digest();
+        let operation = Operation::start("pull_to_docker_archive", image, reference);
+        let result = self
+            .write_docker_archive(&operation, image, reference, writer)
+            .await;
+        // Docker identifies images by the digest of their config.
+        operation.finish(result.as_ref().map(|manifest| &manifest.config.digest));
+        result
+    }
+
+    async fn write_docker_archive<W>(
+        &self,
+        operation: &Operation,
+        image: &str,
+        reference: &str,
+        writer: &mut W,
+    ) -> Result<Manifest, ErrorResponse>
+    where
+        W: AsyncWrite + Unpin + ?Sized,
+    {
+        let manifest = self
+            .manifest_for_platform(image, reference, "linux", host_architecture())
+            .await?;
+
+        let config = self
+            .layer_blob(image, &manifest.config)
+            .await?
+            .bytes_within(self.size_limits.max_config_size)
+            .await?;
+        let config_path = format!("{}.json", manifest.config.digest.hash);
+        write_entry(writer, &config_path, &config).await?;
+        operation.transferred(config.len() as u64);
+
+        let mut layers = vec![];
+        let mut written = HashSet::new();
+        for layer in &manifest.layers {
+            let dir = &layer.digest.hash;
+            layers.push(format!("{}/layer.tar", dir));
+            if !written.insert(&layer.digest) {
+                continue;
+            }
+
+            write_header(writer, &format!("{}/", dir), tar::EntryType::Directory, 0).await?;
+            let size = layer.size as u64;
+            write_header(
+                writer,
+                &format!("{}/layer.tar", dir),
+                tar::EntryType::Regular,
+                size,
+            )
+            .await?;
+            let mut blob = self.layer_blob(image, layer).await?;
+            let mut received = 0u64;
+            while let Some(chunk) = blob.chunk().await? {
+                received += chunk.len() as u64;
+                if received > size {
+                    break;
+                }
+                writer.write_all(&chunk).await?;
+            }
+            // The size of the entry is written first, content of another
+            // size would corrupt the archive.
+            if received != size {
+                return Err(ErrorResponse::size_mismatch(size, received));
+            }
+            write_padding(writer, size).await?;
+            operation.transferred(size);
+        }
+
+        let name = self.archive_name(image);
+        let tag = reference.parse::<Digest>().is_err().then_some(reference);
+        if let (Some(tag), Some(top)) = (tag, manifest.layers.last()) {
+            let repositories = HashMap::from([(&name, HashMap::from([(tag, &top.digest.hash)]))]);
+            write_entry(writer, "repositories", &serde_json::to_vec(&repositories)?).await?;
+        }
+        let entries = [ArchiveManifestEntry {
+            config: config_path,
+            repo_tags: tag.map(|tag| vec![format!("{}:{}", name, tag)]),
+            layers,
+        }];
+        write_entry(writer, "manifest.json", &serde_json::to_vec(&entries)?).await?;
+
+        // End of archive marker.
+        writer.write_all(&[0u8; 1024]).await?;
+        writer.flush().await?;
+        Ok(manifest)
+    }
+
+    /// Returns the name of `image` in docker archives, including the host
+    /// of the registry unless it is Docker Hub.
+    fn archive_name(&self, image: &str) -> String {
+        let host = reqwest::Url::parse(&self.api_url).ok().and_then(|url| {
+            let host = url.host_str()?.to_owned();
+            Some(match url.port() {
+                Some(port) => format!("{}:{}", host, port),
+                None => host,
+            })
+        });
+        match host {
+            Some(host) => match registries::well_known(&host) {
+                Some(known) if known.hosts[0] == "docker.io" => image.to_owned(),
+                Some(known) => format!("{}/{}", known.hosts[0], image),
+                None => format!("{}/{}", host, image),
+            },
+            None => image.to_owned(),
+        }
+    }
+}
+
+/// Write the tar header of an entry of the archive.
+async fn write_header<W>(
+    writer: &mut W,
+    path: &str,
+    entry_type: tar::EntryType,
+    size: u64,
+) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let mut header = tar::Header::new_ustar();
+    header.set_path(path)?;
+    header.set_entry_type(entry_type);
+    header.set_size(size);
+    header.set_mode(match entry_type {
+        tar::EntryType::Directory => 0o755,
+        _ => 0o644,
+    });
+    header.set_mtime(0);
+    header.set_cksum();
+    writer.write_all(header.as_bytes()).await
+}
+
+/// Pad an entry of `size` bytes to the tar block size.
+async fn write_padding<W>(writer: &mut W, size: u64) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let padding = (512 - size % 512) % 512;
+    writer.write_all(&[0u8; 512][..padding as usize]).await
+}
+
+/// Write a regular file of the archive.
+async fn write_entry<W>(writer: &mut W, path: &str, content: &[u8]) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let size = content.len() as u64;
+    write_header(writer, path, tar::EntryType::Regular, size).await?;
+    writer.write_all(content).await?;
+    write_padding(writer, size).await
+}
+
+/// Normalize an archive path, resolving `.` and `..` components.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::Normal(part) => normalized.push(part),
+            _ => {}
+        }
+    }
+    normalized
+}
+
+fn invalid_data(message: &str) -> ErrorResponse {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid docker archive: {}", message),
+    )
+    .into()
+}
@@ -0,0 +1,93 @@
+//! Build attestations.
+//!
+//! Docker buildx stores the provenance and SBOM attestations of an image in
+//! its index: each platform manifest gets an attestation manifest (platform
+//! `unknown/unknown`), annotated with the digest of the manifest it
+//! describes, whose layers are [in-toto](https://in-toto.io) statements.
+//!
+//! ```no_run
+//! # use oci_registry_client::DockerRegistryClientV2;
+//! # async fn example(client: DockerRegistryClientV2) -> Result<(), Box<dyn std::error::Error>> {
+//! let index = client.list_manifests("library/alpine", "latest").await?;
+//! for item in index.platform_manifests() {
+//!     for attestation in client.attestations("library/alpine", &index, &item.digest).await? {
+//!         println!("{}: {:?}", item.digest, attestation.predicate_type);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    errors::ErrorResponse,
+    manifest::{Digest, ManifestItem, ManifestList, ANNOTATION_REFERENCE_DIGEST},
+    reference::AsRepository,
+    DockerRegistryClientV2,
+};
+
+/// Annotation of attestation layers giving the type of their predicate.
+pub const ANNOTATION_PREDICATE_TYPE: &str = "in-toto.io/predicate-type";
+
+/// An in-toto statement attached to an image.
+#[derive(Clone, Debug)]
+pub struct Attestation {
+    /// Digest of the statement blob.
+    pub digest: Digest,
+    /// Type of the predicate (for instance `https://slsa.dev/provenance/v0.2`
+    /// or `https://spdx.dev/Document`), as annotated on the layer.
+    pub predicate_type: Option<String>,
+    /// The statement.
+    pub statement: serde_json::Value,
+}
+
+impl ManifestList {
+    /// Returns the attestation manifest entry describing the manifest
+    /// identified by `digest`.
+    pub fn attestation_manifest(&self, digest: &Digest) -> Option<&ManifestItem> {
+        let digest = digest.to_string();
+        self.manifests.iter().find(|item| {
+            item.is_attestation()
+                && item
+                    .annotations
+                    .as_ref()
+                    .and_then(|annotations| annotations.get(ANNOTATION_REFERENCE_DIGEST))
+                    == Some(&digest)
+        })
+    }
+}
+
+impl DockerRegistryClientV2 {
+    /// Returns the attestations of the platform manifest identified by
+    /// `digest`, listed in `index`.
+    ///
+    /// Returns an empty list if the manifest has no attestation manifest.
+    pub async fn attestations<I: AsRepository + ?Sized>(
+        &self,
+        image: &I,
+        index: &ManifestList,
+        digest: &Digest,
+    ) -> Result<Vec<Attestation>, ErrorResponse> {
+        let image = image.as_repository();
+        let item = match index.attestation_manifest(digest) {
+            Some(item) => item,
+            None => return Ok(vec![]),
+        };
+
+        let manifest = self.manifest(image, &item.digest.to_string()).await?;
+        let mut attestations = vec![];
+        for layer in &manifest.layers {
+            let statement = self.layer_blob(image, layer).await?.json().await?;
+            attestations.push(Attestation {
+                digest: layer.digest.clone(),
+                predicate_type: layer
+                    .annotations
+                    .as_ref()
+                    .and_then(|annotations| annotations.get(ANNOTATION_PREDICATE_TYPE))
+                    .cloned(),
+                statement,
+            });
+        }
+
+        Ok(attestations)
+    }
+}
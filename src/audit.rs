@@ -0,0 +1,89 @@
+//! Per-operation summary records.
+//!
+//! High-level operations (copy, push, pull, lock, root filesystem export and
+//! unpacking) emit a single record when they complete, with the repository,
+//! reference, resolved digest, bytes transferred, duration and cache hits.
+//! Operations on an image rather than a reference (docker archives, root
+//! filesystems) record the digest of its config. Records are emitted as `tracing`
+//! events (target `oci_registry_client::audit`) when the `tracing` feature is
+//! enabled.
+//!
+//! ```text
+//! INFO oci_registry_client::audit: operation completed operation="copy" repository=mirror/alpine reference=3.19 digest=sha256:... bytes=3402167 duration_ms=1840 cache_hits=1
+//! ```
+
+use crate::{errors::ErrorResponse, manifest::Digest};
+use std::{
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::Instant,
+};
+
+/// A running high-level operation.
+#[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+pub(crate) struct Operation {
+    name: &'static str,
+    repository: String,
+    reference: String,
+    started: Instant,
+    bytes: AtomicU64,
+    cache_hits: AtomicUsize,
+}
+
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+impl Operation {
+    pub(crate) fn start(name: &'static str, repository: &str, reference: &str) -> Self {
+        Self {
+            name,
+            repository: repository.to_owned(),
+            reference: reference.to_owned(),
+            started: Instant::now(),
+            bytes: AtomicU64::new(0),
+            cache_hits: AtomicUsize::new(0),
+        }
+    }
+
+    /// Record `bytes` transferred.
+    pub(crate) fn transferred(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Record a blob (or manifest) that did not need to be transferred.
+    pub(crate) fn cache_hit(&self) {
+        self.cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Emit the summary record of this operation.
+    pub(crate) fn finish(self, outcome: Result<&Digest, &ErrorResponse>) {
+        #[cfg(feature = "tracing")]
+        {
+            let bytes = self.bytes.load(Ordering::Relaxed);
+            let cache_hits = self.cache_hits.load(Ordering::Relaxed);
+            let duration_ms = self.started.elapsed().as_millis() as u64;
+
+            match outcome {
+                Ok(digest) => tracing::info!(
+                    target: "oci_registry_client::audit",
+                    operation = self.name,
+                    repository = %self.repository,
+                    reference = %self.reference,
+                    digest = %digest,
+                    bytes,
+                    duration_ms,
+                    cache_hits,
+                    "operation completed"
+                ),
+                Err(err) => tracing::warn!(
+                    target: "oci_registry_client::audit",
+                    operation = self.name,
+                    repository = %self.repository,
+                    reference = %self.reference,
+                    bytes,
+                    duration_ms,
+                    cache_hits,
+                    error = %err,
+                    "operation failed"
+                ),
+            }
+        }
+    }
+}
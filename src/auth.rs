@@ -0,0 +1,574 @@
+//! Authentication tokens, challenges and caching.
+
+use crate::{errors::ErrorResponse, store};
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, SystemTime},
+};
+
+/// Tokens are considered expired slightly before the registry says so, to
+/// avoid using a token that expires while a request is in flight.
+const EXPIRY_LEEWAY: Duration = Duration::from_secs(10);
+
+/// Lifetime of a token when the token endpoint does not announce one.
+const DEFAULT_EXPIRES_IN: Duration = Duration::from_secs(60);
+
+/// OAuth 2.0 token.
+///
+/// Token endpoints return the token either as `token` or `access_token` (or
+/// both), both forms are accepted.
+///
+/// ```
+/// # use oci_registry_client::AuthToken;
+/// let token = AuthToken::from("my-token");
+/// assert_eq!(token.token(), "my-token");
+/// assert!(token.expires_at().is_none());
+/// ```
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(try_from = "TokenResponse")]
+pub struct AuthToken {
+    access_token: String,
+    expires_in: Option<Duration>,
+    issued_at: Option<String>,
+    refresh_token: Option<String>,
+    received_at: SystemTime,
+}
+
+impl AuthToken {
+    /// Returns a token from a raw bearer token string, without expiry.
+    pub fn new<T: Into<String>>(token: T) -> Self {
+        Self {
+            access_token: token.into(),
+            expires_in: None,
+            issued_at: None,
+            refresh_token: None,
+            received_at: SystemTime::now(),
+        }
+    }
+
+    /// Returns the bearer token string.
+    pub fn token(&self) -> &str {
+        &self.access_token
+    }
+
+    /// Returns the time this token was issued, as reported by the token
+    /// endpoint (RFC 3339).
+    pub fn issued_at(&self) -> Option<&str> {
+        self.issued_at.as_deref()
+    }
+
+    /// Returns the refresh token issued with this token, if an offline token
+    /// was requested (see [`TokenRequestOptions::offline_token`]).
+    pub fn refresh_token(&self) -> Option<&str> {
+        self.refresh_token.as_deref()
+    }
+
+    /// Returns the time this token expires, or `None` if it does not expire.
+    ///
+    /// The expiry is computed from the time the token was received.
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.expires_in
+            .map(|expires_in| self.received_at + expires_in)
+    }
+
+    /// Returns `true` if this token is expired (or about to expire).
+    pub fn is_expired(&self) -> bool {
+        match self.expires_at() {
+            Some(expires_at) => SystemTime::now() + EXPIRY_LEEWAY >= expires_at,
+            None => false,
+        }
+    }
+}
+
+impl From<String> for AuthToken {
+    fn from(token: String) -> Self {
+        Self::new(token)
+    }
+}
+
+impl From<&str> for AuthToken {
+    fn from(token: &str) -> Self {
+        Self::new(token)
+    }
+}
+
+/// Token endpoint response body.
+#[derive(serde::Deserialize)]
+struct TokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+    expires_in: Option<u64>,
+    issued_at: Option<String>,
+    refresh_token: Option<String>,
+}
+
+impl TryFrom<TokenResponse> for AuthToken {
+    type Error = &'static str;
+
+    fn try_from(response: TokenResponse) -> Result<Self, Self::Error> {
+        Ok(Self {
+            access_token: response
+                .access_token
+                .or(response.token)
+                .ok_or("missing field `token` or `access_token`")?,
+            expires_in: Some(
+                response
+                    .expires_in
+                    .map(Duration::from_secs)
+                    .unwrap_or(DEFAULT_EXPIRES_IN),
+            ),
+            issued_at: response.issued_at,
+            refresh_token: response.refresh_token,
+            received_at: SystemTime::now(),
+        })
+    }
+}
+
+/// Username and password (or personal access token) presented to the token
+/// endpoint with HTTP basic authentication.
+///
+/// The password is not printed by `Debug`.
+///
+/// ```
+/// # use oci_registry_client::auth::Credentials;
+/// let credentials = Credentials::new("ci", "s3cr3t");
+/// assert!(!format!("{:?}", credentials).contains("s3cr3t"));
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl Credentials {
+    pub fn new<T: Into<String>>(username: T, password: T) -> Self {
+        Self {
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Resource scope of an access token (example: `repository:library/ubuntu:pull`).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Scope {
+    pub r#type: String,
+    pub name: String,
+    pub actions: String,
+}
+
+impl Scope {
+    /// Returns a `repository` scope for `name` with `actions` (example: "pull").
+    pub fn repository<T: Into<String>>(name: T, actions: T) -> Self {
+        Self {
+            r#type: "repository".to_owned(),
+            name: name.into(),
+            actions: actions.into(),
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.r#type, self.name, self.actions)
+    }
+}
+
+type ScopeFormat = Arc<dyn Fn(&Scope) -> String + Send + Sync>;
+
+/// Customizes the requests sent to the token endpoint, for token servers
+/// that expect non-standard scopes or extra parameters.
+///
+/// ```
+/// # use oci_registry_client::{auth::TokenRequestOptions, DockerRegistryClientV2};
+/// # let mut client = DockerRegistryClientV2::try_new("gitlab", "https://registry.example.com", "https://gitlab.example.com/jwt/auth").unwrap();
+/// client.set_token_request_options(
+///     TokenRequestOptions::new()
+///         .scope_format(|scope| format!("{}:{}:{}", scope.r#type, scope.name, "*"))
+///         .param("account", "ci"),
+/// );
+/// ```
+#[derive(Clone, Default)]
+pub struct TokenRequestOptions {
+    scope_format: Option<ScopeFormat>,
+    params: Vec<(String, String)>,
+    client_id: Option<String>,
+    offline_token: bool,
+}
+
+impl TokenRequestOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Format scopes with `format` instead of `type:name:actions`.
+    pub fn scope_format<F>(mut self, format: F) -> Self
+    where
+        F: Fn(&Scope) -> String + Send + Sync + 'static,
+    {
+        self.scope_format = Some(Arc::new(format));
+        self
+    }
+
+    /// Add the `key=value` query parameter to token requests.
+    pub fn param<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.params.push((key.into(), value.into()));
+        self
+    }
+
+    /// Identify this client with `client_id` to the token endpoint.
+    pub fn client_id<T: Into<String>>(mut self, client_id: T) -> Self {
+        self.client_id = Some(client_id.into());
+        self
+    }
+
+    /// Request a refresh token (`offline_token=true`) together with access
+    /// tokens.
+    ///
+    /// Refresh tokens are handed to [`TokenProvider::store_refresh_token`]
+    /// and used for later token requests instead of credentials.
+    pub fn offline_token(mut self, offline_token: bool) -> Self {
+        self.offline_token = offline_token;
+        self
+    }
+
+    /// Returns the value of the `scope` parameter for `scope`.
+    pub fn format_scope(&self, scope: &Scope) -> String {
+        match &self.scope_format {
+            Some(format) => format(scope),
+            None => scope.to_string(),
+        }
+    }
+
+    /// Returns the extra query parameters of token requests, including
+    /// `client_id` and `offline_token`.
+    pub fn params(&self) -> Vec<(&str, &str)> {
+        let mut params: Vec<_> = self
+            .params
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+        if let Some(client_id) = &self.client_id {
+            params.push(("client_id", client_id));
+        }
+        if self.offline_token {
+            params.push(("offline_token", "true"));
+        }
+        params
+    }
+}
+
+impl fmt::Debug for TokenRequestOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenRequestOptions")
+            .field("scope_format", &self.scope_format.as_ref().map(|_| ".."))
+            .field("params", &self.params)
+            .field("client_id", &self.client_id)
+            .field("offline_token", &self.offline_token)
+            .finish()
+    }
+}
+
+/// Source of bearer tokens consulted by the client before each request.
+///
+/// Implement this trait to plug in custom authentication backends (Vault,
+/// Kubernetes secrets, ...).
+///
+/// ```
+/// use oci_registry_client::auth::{Scope, TokenProvider};
+/// use oci_registry_client::errors::ErrorResponse;
+///
+/// struct StaticToken(String);
+///
+/// #[async_trait::async_trait]
+/// impl TokenProvider for StaticToken {
+///     async fn token(&self, _scope: Option<&Scope>) -> Result<Option<String>, ErrorResponse> {
+///         Ok(Some(self.0.clone()))
+///     }
+/// }
+/// ```
+#[async_trait::async_trait]
+pub trait TokenProvider: Send + Sync {
+    /// Returns a bearer token for `scope`, or `None` to fall back to the
+    /// client token.
+    ///
+    /// `scope` is `None` for registry-wide endpoints (example: `/v2/`).
+    async fn token(&self, scope: Option<&Scope>) -> Result<Option<String>, ErrorResponse>;
+
+    /// Returns the refresh token stored for `service`, used to request
+    /// access tokens with the OAuth2 `refresh_token` grant.
+    async fn refresh_token(&self, _service: &str) -> Result<Option<String>, ErrorResponse> {
+        Ok(None)
+    }
+
+    /// Persist `refresh_token`, issued by the token endpoint of `service`.
+    async fn store_refresh_token(
+        &self,
+        _service: &str,
+        _refresh_token: &str,
+    ) -> Result<(), ErrorResponse> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for dyn TokenProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TokenProvider")
+    }
+}
+
+/// In-memory cache of [`AuthToken`]s keyed by service and scope.
+///
+/// Clones share the same underlying storage.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TokenCache {
+    tokens: Arc<Mutex<HashMap<(String, String), AuthToken>>>,
+}
+
+impl TokenCache {
+    /// Returns a cached token for `service` and `scope` if it is still valid.
+    pub fn get(&self, service: &str, scope: &str) -> Option<AuthToken> {
+        let mut tokens = self.tokens.lock().unwrap();
+        let key = (service.to_owned(), scope.to_owned());
+
+        match tokens.get(&key) {
+            Some(token) if !token.is_expired() => Some(token.clone()),
+            Some(_) => {
+                tokens.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Returns the service, scope and token of every cached entry, expired
+    /// ones included.
+    pub fn entries(&self) -> Vec<(String, String, AuthToken)> {
+        self.tokens
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|((service, scope), token)| (service.clone(), scope.clone(), token.clone()))
+            .collect()
+    }
+
+    /// Store `token` for `service` and `scope`.
+    pub fn insert(&self, service: &str, scope: &str, token: AuthToken) {
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert((service.to_owned(), scope.to_owned()), token);
+    }
+}
+
+/// A parsed `WWW-Authenticate` challenge.
+///
+/// ```
+/// # use oci_registry_client::auth::Challenge;
+/// let challenge = Challenge::parse(
+///     r#"Bearer realm="https://auth.docker.io/token",service="registry.docker.io""#,
+/// ).unwrap();
+/// assert_eq!(challenge.scheme, "Bearer");
+/// assert_eq!(challenge.realm.as_deref(), Some("https://auth.docker.io/token"));
+/// assert_eq!(challenge.service.as_deref(), Some("registry.docker.io"));
+/// assert_eq!(
+///     challenge.to_string(),
+///     r#"Bearer realm="https://auth.docker.io/token",service="registry.docker.io""#,
+/// );
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Challenge {
+    pub scheme: String,
+    pub realm: Option<String>,
+    pub service: Option<String>,
+    pub scope: Option<String>,
+    pub error: Option<String>,
+}
+
+impl Challenge {
+    /// Parse the value of a `WWW-Authenticate` header.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let (scheme, mut rest) = match value.find(' ') {
+            Some(idx) => (&value[..idx], value[idx..].trim_start()),
+            None => (value, ""),
+        };
+        if scheme.is_empty() {
+            return None;
+        }
+
+        let mut challenge = Challenge {
+            scheme: scheme.to_owned(),
+            ..Default::default()
+        };
+
+        while !rest.is_empty() {
+            let eq = rest.find('=')?;
+            let key = rest[..eq].trim().to_ascii_lowercase();
+            rest = rest[eq + 1..].trim_start();
+
+            let mut param = String::new();
+            if let Some(quoted) = rest.strip_prefix('"') {
+                let mut chars = quoted.char_indices();
+                let mut end = None;
+                while let Some((idx, c)) = chars.next() {
+                    match c {
+                        '\\' => {
+                            if let Some((_, escaped)) = chars.next() {
+                                param.push(escaped);
+                            }
+                        }
+                        '"' => {
+                            end = Some(idx);
+                            break;
+                        }
+                        c => param.push(c),
+                    }
+                }
+                rest = &quoted[end? + 1..];
+            } else {
+                let end = rest.find(',').unwrap_or(rest.len());
+                param.push_str(rest[..end].trim());
+                rest = &rest[end..];
+            }
+            rest = rest.trim_start().trim_start_matches(',').trim_start();
+
+            match key.as_str() {
+                "realm" => challenge.realm = Some(param),
+                "service" => challenge.service = Some(param),
+                "scope" => challenge.scope = Some(param),
+                "error" => challenge.error = Some(param),
+                _ => {}
+            }
+        }
+
+        Some(challenge)
+    }
+}
+
+impl fmt::Display for Challenge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.scheme)?;
+        let params = [
+            ("realm", &self.realm),
+            ("service", &self.service),
+            ("scope", &self.scope),
+            ("error", &self.error),
+        ];
+        let mut separator = " ";
+        for (key, value) in params {
+            if let Some(value) = value {
+                write!(f, "{}{}=\"{}\"", separator, key, value)?;
+                separator = ",";
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Token endpoint announced by a registry in its authentication challenge.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AuthEndpoint {
+    pub realm: String,
+    pub service: String,
+}
+
+impl AuthEndpoint {
+    /// Returns the endpoint described by a Bearer `challenge`, if any.
+    pub fn from_challenge(challenge: &Challenge) -> Option<Self> {
+        if !challenge.scheme.eq_ignore_ascii_case("bearer") {
+            return None;
+        }
+
+        Some(Self {
+            realm: challenge.realm.clone()?,
+            service: challenge.service.clone().unwrap_or_default(),
+        })
+    }
+}
+
+/// Cache of resolved [`AuthEndpoint`]s keyed by registry API URL.
+///
+/// By default all clients in a process share [`EndpointCache::shared`]. Use
+/// [`EndpointCache::with_file`] to also persist entries across processes.
+/// A `None` entry records a registry that does not require authentication,
+/// such entries are only kept in memory.
+#[derive(Clone, Debug, Default)]
+pub struct EndpointCache {
+    endpoints: Arc<Mutex<HashMap<String, Option<AuthEndpoint>>>>,
+    path: Option<PathBuf>,
+}
+
+impl EndpointCache {
+    /// Returns a new, empty, in-memory cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the process-wide cache.
+    pub fn shared() -> Self {
+        static SHARED: OnceLock<EndpointCache> = OnceLock::new();
+        SHARED.get_or_init(EndpointCache::new).clone()
+    }
+
+    /// Returns a cache backed by the JSON file at `path`.
+    ///
+    /// Existing entries are loaded from `path` (a missing or unreadable file
+    /// starts an empty cache) and every new endpoint is written back to it,
+    /// merged with the entries written by other processes meanwhile. The
+    /// file is replaced atomically, failures to write it are ignored, the
+    /// in-memory cache is still updated.
+    pub async fn with_file<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let endpoints = load_endpoints(&path)
+            .await
+            .into_iter()
+            .map(|(registry, endpoint)| (registry, Some(endpoint)))
+            .collect();
+
+        Self {
+            endpoints: Arc::new(Mutex::new(endpoints)),
+            path: Some(path),
+        }
+    }
+
+    pub(crate) fn get(&self, registry: &str) -> Option<Option<AuthEndpoint>> {
+        self.endpoints.lock().unwrap().get(registry).cloned()
+    }
+
+    pub(crate) async fn insert(&self, registry: &str, endpoint: Option<AuthEndpoint>) {
+        self.endpoints
+            .lock()
+            .unwrap()
+            .insert(registry.to_owned(), endpoint.clone());
+
+        if let (Some(path), Some(endpoint)) = (&self.path, endpoint) {
+            let mut persisted = load_endpoints(path).await;
+            persisted.insert(registry.to_owned(), endpoint);
+            if let Ok(contents) = serde_json::to_vec_pretty(&persisted) {
+                let _ = store::write_atomic(path, &contents).await;
+            }
+        }
+    }
+}
+
+/// Returns the endpoints persisted in the cache file at `path`.
+async fn load_endpoints(path: &Path) -> HashMap<String, AuthEndpoint> {
+    tokio::fs::read(path)
+        .await
+        .ok()
+        .and_then(|contents| serde_json::from_slice(&contents).ok())
+        .unwrap_or_default()
+}
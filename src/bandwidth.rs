@@ -0,0 +1,124 @@
+//! Bandwidth limits.
+//!
+//! A [`BandwidthLimiter`] paces blob transfers to a number of bytes per
+//! second. Limits set with [`DockerRegistryClientV2::set_bandwidth_limits`]
+//! are shared by all transfers of a client (and its clones), a limiter can
+//! also be applied to a single download with [`Blob::limit_rate`].
+//!
+//! ```
+//! # use oci_registry_client::{bandwidth::BandwidthLimits, DockerRegistryClientV2};
+//! # let mut client = DockerRegistryClientV2::try_new("registry.docker.io", "https://registry-1.docker.io", "https://auth.docker.io/token").unwrap();
+//! client.set_bandwidth_limits(BandwidthLimits {
+//!     download: Some(10 * 1024 * 1024),
+//!     upload: Some(2 * 1024 * 1024),
+//! });
+//! ```
+//!
+//! [`Blob::limit_rate`]: crate::blob::Blob::limit_rate
+
+use crate::DockerRegistryClientV2;
+use bytes::Bytes;
+use futures_util::{
+    stream::{self, BoxStream},
+    Stream, StreamExt,
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::Instant;
+
+/// Transfers are paced by slices of at most this size.
+const SLICE_SIZE: usize = 64 * 1024;
+
+/// Bandwidth limits of a client, in bytes per second, `None` for no limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BandwidthLimits {
+    /// Limit of blob downloads.
+    pub download: Option<u64>,
+    /// Limit of blob uploads.
+    pub upload: Option<u64>,
+}
+
+/// Limits the rate of the transfers it is applied to, clones share the
+/// limit.
+#[derive(Clone, Debug)]
+pub struct BandwidthLimiter {
+    bytes_per_second: u64,
+    next_slice: Arc<Mutex<Option<Instant>>>,
+}
+
+impl BandwidthLimiter {
+    /// Returns a limiter of `bytes_per_second`.
+    pub fn new(bytes_per_second: u64) -> Self {
+        Self {
+            bytes_per_second: bytes_per_second.max(1),
+            next_slice: Arc::default(),
+        }
+    }
+
+    /// Wait until `len` more bytes can be transferred.
+    async fn consume(&self, len: usize) {
+        let duration = Duration::from_secs_f64(len as f64 / self.bytes_per_second as f64);
+        let wait = {
+            let mut next_slice = self.next_slice.lock().unwrap();
+            let now = Instant::now();
+            let at = next_slice.map_or(now, |next| next.max(now));
+            *next_slice = Some(at + duration);
+            at - now
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Returns `chunks` paced to the rate of this limiter.
+    pub(crate) fn throttle<S, E>(&self, chunks: S) -> BoxStream<'static, Result<Bytes, E>>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+        E: Send + 'static,
+    {
+        let limiter = self.clone();
+        chunks
+            .flat_map(|chunk| {
+                let slices: Vec<Result<Bytes, E>> = match chunk {
+                    Ok(chunk) => (0..chunk.len())
+                        .step_by(SLICE_SIZE)
+                        .map(|start| Ok(chunk.slice(start..(start + SLICE_SIZE).min(chunk.len()))))
+                        .collect(),
+                    Err(err) => vec![Err(err)],
+                };
+                stream::iter(slices)
+            })
+            .then(move |slice| {
+                let limiter = limiter.clone();
+                async move {
+                    if let Ok(slice) = &slice {
+                        limiter.consume(slice.len()).await;
+                    }
+                    slice
+                }
+            })
+            .boxed()
+    }
+}
+
+/// Limiters of the transfers of a client.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Bandwidth {
+    pub(crate) download: Option<BandwidthLimiter>,
+    pub(crate) upload: Option<BandwidthLimiter>,
+}
+
+impl DockerRegistryClientV2 {
+    /// Set the bandwidth limits of blob downloads and uploads, see
+    /// [`crate::bandwidth`].
+    ///
+    /// Clones of the client made after this call share the limits.
+    pub fn set_bandwidth_limits(&mut self, limits: BandwidthLimits) {
+        self.bandwidth = Bandwidth {
+            download: limits.download.map(BandwidthLimiter::new),
+            upload: limits.upload.map(BandwidthLimiter::new),
+        };
+    }
+}
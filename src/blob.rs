@@ -13,20 +13,56 @@
 //! }
 //! ```
 
-use crate::errors::ErrorResponse;
+use crate::bandwidth::BandwidthLimiter;
+use crate::errors::{ErrorContext, ErrorResponse};
+#[cfg(feature = "gzip")]
+use crate::layer;
 use crate::manifest::Digest;
-use bytes::Bytes;
+use crate::scheduler::Permit;
+use crate::timeout::{self, TimeoutPhase};
+use crate::verify::VerificationPolicy;
+use bytes::{Bytes, BytesMut};
+use futures_util::{future::BoxFuture, stream, stream::BoxStream, StreamExt};
 use reqwest;
-#[cfg(feature = "sha256")]
 use sha2::{Digest as Sha256Digest, Sha256};
+use std::{io, time::Duration};
+#[cfg(feature = "gzip")]
+use std::{
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+#[cfg(feature = "gzip")]
+use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+#[cfg(feature = "gzip")]
+use tokio_util::io::StreamReader;
+#[cfg(feature = "tar")]
+use {std::path::Path, tokio_util::io::SyncIoBridge};
+
+/// Size of the chunks of decompressed contents.
+#[cfg(feature = "gzip")]
+const DECOMPRESSED_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Maximum number of bytes allocated upfront by [`Blob::bytes`].
+const MAX_PREALLOCATION: usize = 1024 * 1024;
+
+pub(crate) type Chunks = BoxStream<'static, Result<Bytes, ErrorResponse>>;
+
+/// Reissues the download of a blob from a byte offset, `None` if the
+/// registry cannot serve the remaining bytes only.
+pub(crate) type ResumeFrom =
+    Box<dyn Fn(u64) -> BoxFuture<'static, Result<Option<Blob>, ErrorResponse>> + Send>;
 
 /// Blob represents a downloaded content in a Image Registry.
 pub struct Blob {
-    response: reqwest::Response,
+    chunks: Chunks,
     len: Option<usize>,
     content_type: Option<String>,
-    #[cfg(feature = "sha256")]
+    url: Option<reqwest::Url>,
+    received: u64,
+    expected: Option<(Digest, VerificationPolicy)>,
     hasher: Sha256,
+    context: Option<ErrorContext>,
 }
 
 impl Blob {
@@ -36,6 +72,18 @@ impl Blob {
         self.len
     }
 
+    /// Returns the URL the contents are downloaded from, after redirects
+    /// (see [`crate::redirect`]), `None` for contents not downloaded.
+    pub fn url(&self) -> Option<&reqwest::Url> {
+        self.url.as_ref()
+    }
+
+    /// Returns the number of bytes read so far, to report the progress of a
+    /// download against [`Blob::len`].
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
     /// Returns the content type of this blob (example:
     /// Some("application/vnd.docker.image.rootfs.foreign.diff.tar.gzip"))
     pub fn content_type(&self) -> &Option<String> {
@@ -43,25 +91,459 @@ impl Blob {
     }
 
     /// Stream a chunk of the blob contents.
+    ///
+    /// If verification is enabled (see [`Blob::verify`]), the last call
+    /// (returning `None`) fails when the contents do not match the expected
+    /// size or digest.
     pub async fn chunk(&mut self) -> Result<Option<Bytes>, ErrorResponse> {
-        match self.response.chunk().await {
-            Ok(Some(chunk)) => {
-                #[cfg(feature = "sha256")]
+        match (self.next_chunk().await, &self.context) {
+            (Err(err), Some(context)) => Err(err.with_context(context.clone())),
+            (chunk, _) => chunk,
+        }
+    }
+
+    /// Stream a chunk, see [`Blob::chunk`].
+    async fn next_chunk(&mut self) -> Result<Option<Bytes>, ErrorResponse> {
+        match self.chunks.next().await {
+            Some(Ok(chunk)) => {
                 self.hasher.input(&chunk);
+                self.received += chunk.len() as u64;
                 Ok(Some(chunk))
             }
-            Ok(None) => Ok(None),
-            Err(err) => Err(ErrorResponse::RequestError(err)),
+            None => {
+                if let Some((expected, policy)) = self.expected.take() {
+                    policy.check_size(self.len.map(|len| len as u64), self.received)?;
+                    policy
+                        .check_digest(
+                            &expected,
+                            &Digest::from_sha256(self.hasher.clone().result()),
+                        )
+                        .map_err(|err| err.with_url(self.url.as_ref()))?;
+                }
+                Ok(None)
+            }
+            Some(Err(err)) => Err(err),
+        }
+    }
+
+    /// Pace the download of the contents to the rate of `limiter`, see
+    /// [`crate::bandwidth`].
+    pub fn limit_rate(mut self, limiter: &BandwidthLimiter) -> Self {
+        self.chunks = limiter.throttle(self.chunks);
+        self
+    }
+
+    /// Coalesce the chunks received from the network into chunks of at least
+    /// `size` bytes (except the last one), to avoid small writes.
+    ///
+    /// Chunks already larger than `size` are returned as is.
+    ///
+    /// ```no_run
+    /// # use oci_registry_client::{manifest::Digest, DockerRegistryClientV2};
+    /// # async fn example(client: DockerRegistryClientV2, digest: Digest) -> Result<(), Box<dyn std::error::Error>> {
+    /// let mut blob = client
+    ///     .blob("library/alpine", &digest)
+    ///     .await?
+    ///     .chunk_size(1024 * 1024);
+    /// while let Some(chunk) = blob.chunk().await? {
+    ///     // ...
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn chunk_size(mut self, size: usize) -> Self {
+        let state = (self.chunks, BytesMut::new());
+        self.chunks = stream::unfold(Some(state), move |state| async move {
+            let (mut chunks, mut buffer) = state?;
+            loop {
+                match chunks.next().await {
+                    Some(Ok(chunk)) if buffer.is_empty() && chunk.len() >= size => {
+                        return Some((Ok(chunk), Some((chunks, buffer))));
+                    }
+                    Some(Ok(chunk)) => {
+                        buffer.extend_from_slice(&chunk);
+                        if buffer.len() >= size {
+                            let chunk = buffer.split().freeze();
+                            return Some((Ok(chunk), Some((chunks, buffer))));
+                        }
+                    }
+                    Some(Err(err)) => return Some((Err(err), None)),
+                    None if buffer.is_empty() => return None,
+                    None => return Some((Ok(buffer.freeze()), None)),
+                }
+            }
+        })
+        .boxed();
+        self
+    }
+
+    /// Call `on_progress` with the number of bytes received so far and the
+    /// total length of the blob (if known) every time a chunk is received.
+    ///
+    /// Progress is reported however the contents are consumed (chunks,
+    /// decompression, unpacking).
+    ///
+    /// ```no_run
+    /// # use oci_registry_client::{manifest::Digest, DockerRegistryClientV2};
+    /// # async fn example(client: DockerRegistryClientV2, digest: Digest) -> Result<(), Box<dyn std::error::Error>> {
+    /// let blob = client
+    ///     .blob("library/alpine", &digest)
+    ///     .await?
+    ///     .on_progress(|received, total| println!("{}/{:?} bytes", received, total));
+    /// blob.finish().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn on_progress<F>(mut self, mut on_progress: F) -> Self
+    where
+        F: FnMut(u64, Option<u64>) + Send + 'static,
+    {
+        let total = self.len.map(|len| len as u64);
+        let mut received = self.received;
+        self.chunks = self
+            .chunks
+            .map(move |chunk| {
+                if let Ok(chunk) = &chunk {
+                    received += chunk.len() as u64;
+                    on_progress(received, total);
+                }
+                chunk
+            })
+            .boxed();
+        self
+    }
+
+    /// Check the contents against `expected` once fully read, according to
+    /// `policy`.
+    ///
+    /// Blobs returned by [`crate::DockerRegistryClientV2::blob`] are
+    /// verified with the policy of the client.
+    pub fn verify(mut self, expected: Digest, policy: VerificationPolicy) -> Self {
+        self.expected = Some((expected, policy));
+        self
+    }
+
+    /// Read the remaining contents and return the digest of the blob,
+    /// failing if it does not match the expected digest (see
+    /// [`Blob::verify`]).
+    pub async fn finish(mut self) -> Result<Digest, ErrorResponse> {
+        while self.chunk().await?.is_some() {}
+        Ok(self.digest())
+    }
+
+    /// Stream the remaining contents into `writer` and return the digest of
+    /// the blob, failing if it does not match the expected digest (see
+    /// [`Blob::verify`]).
+    ///
+    /// `writer` is flushed but not shut down, content written before a
+    /// verification failure is not retracted.
+    ///
+    /// ```no_run
+    /// # use oci_registry_client::{manifest::Digest, DockerRegistryClientV2};
+    /// # async fn example(client: DockerRegistryClientV2, digest: Digest) -> Result<(), Box<dyn std::error::Error>> {
+    /// let mut file = tokio::fs::File::create("/tmp/blob").await?;
+    /// let mut blob = client.blob("library/alpine", &digest).await?;
+    /// let written = blob.write_to(&mut file).await?;
+    /// assert_eq!(written, digest);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn write_to<W>(&mut self, writer: &mut W) -> Result<Digest, ErrorResponse>
+    where
+        W: AsyncWrite + Unpin + ?Sized,
+    {
+        while let Some(chunk) = self.chunk().await? {
+            writer.write_all(&chunk).await?;
+        }
+        writer.flush().await?;
+        Ok(Digest::from_sha256(self.hasher.clone().result()))
+    }
+
+    /// Read the remaining contents in memory, failing if they do not match
+    /// the expected digest (see [`Blob::verify`]).
+    ///
+    /// Meant for small blobs (configs, signatures, SBOMs), large blobs
+    /// should be streamed with [`Blob::chunk`].
+    ///
+    /// ```no_run
+    /// # use oci_registry_client::{manifest::Digest, DockerRegistryClientV2};
+    /// # async fn example(client: DockerRegistryClientV2, digest: Digest) -> Result<(), Box<dyn std::error::Error>> {
+    /// let sbom: serde_json::Value = client.blob("library/alpine", &digest).await?.json().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn bytes(self) -> Result<Bytes, ErrorResponse> {
+        self.read_to_end(None).await
+    }
+
+    /// Read the remaining contents in memory, failing if they are larger
+    /// than `limit` bytes, see [`Blob::bytes`].
+    #[cfg(feature = "tar")]
+    pub(crate) async fn bytes_within(self, limit: u64) -> Result<Bytes, ErrorResponse> {
+        self.read_to_end(Some(limit)).await
+    }
+
+    async fn read_to_end(mut self, limit: Option<u64>) -> Result<Bytes, ErrorResponse> {
+        let too_large = |size: u64| limit.filter(|limit| size > *limit);
+        if let Some(limit) = too_large(self.len.unwrap_or_default() as u64) {
+            return Err(ErrorResponse::body_too_large(limit));
+        }
+
+        // The announced length is not trusted, only so much is allocated
+        // upfront.
+        let capacity = self.len.unwrap_or_default().min(MAX_PREALLOCATION);
+        let mut content = BytesMut::with_capacity(capacity);
+        while let Some(chunk) = self.chunk().await? {
+            if let Some(limit) = too_large((content.len() + chunk.len()) as u64) {
+                return Err(ErrorResponse::body_too_large(limit));
+            }
+            content.extend_from_slice(&chunk);
+        }
+        Ok(content.freeze())
+    }
+
+    /// Read the remaining contents in memory as UTF-8 text, see
+    /// [`Blob::bytes`].
+    pub async fn text(self) -> Result<String, ErrorResponse> {
+        let content = self.bytes().await?;
+        String::from_utf8(content.to_vec())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err).into())
+    }
+
+    /// Read the remaining contents in memory and deserialize them from JSON,
+    /// see [`Blob::bytes`].
+    pub async fn json<T: serde::de::DeserializeOwned>(self) -> Result<T, ErrorResponse> {
+        let content = self.bytes().await?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+
+    /// Returns a reader of the decompressed contents of this blob, for gzip
+    /// compressed layers. Uncompressed contents are returned as is.
+    ///
+    /// The compressed contents are still verified (see [`Blob::verify`]),
+    /// the digest of the decompressed contents (the `diff_id` of a layer) is
+    /// computed as they are read.
+    ///
+    /// ```no_run
+    /// # use oci_registry_client::{manifest::Manifest, DockerRegistryClientV2};
+    /// # async fn example(client: DockerRegistryClientV2, manifest: Manifest) -> Result<(), Box<dyn std::error::Error>> {
+    /// let layer = &manifest.layers[0];
+    /// let mut tar = client.blob("library/alpine", &layer.digest).await?.decompress().await?;
+    /// while let Some(chunk) = tar.chunk().await? {
+    ///     // ...
+    /// }
+    /// println!("diff_id: {}", tar.diff_id());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "gzip")]
+    pub async fn decompress(self) -> Result<DecompressedBlob, ErrorResponse> {
+        let reader = layer::decompress(self.into_reader())
+            .await
+            .map_err(into_error_response)?;
+
+        Ok(DecompressedBlob {
+            reader,
+            buffer: BytesMut::new(),
+            expected: None,
+            hasher: Sha256::new(),
+        })
+    }
+
+    /// Decompress and unpack this layer into the directory `path`, see
+    /// [`DecompressedBlob::untar_to`].
+    ///
+    /// ```no_run
+    /// # use oci_registry_client::{manifest::Layer, DockerRegistryClientV2};
+    /// # async fn example(client: DockerRegistryClientV2, layer: Layer) -> Result<(), Box<dyn std::error::Error>> {
+    /// let blob = client.blob("library/alpine", &layer.digest).await?;
+    /// blob.untar_to("/tmp/alpine").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "tar")]
+    pub async fn untar_to<P: AsRef<Path>>(self, path: P) -> Result<(), ErrorResponse> {
+        self.decompress().await?.untar_to(path).await
+    }
+
+    /// Returns the remaining blob contents as a reader, failing at the end
+    /// of the contents if they do not match the expected digest (see
+    /// [`Blob::verify`]).
+    #[cfg(feature = "gzip")]
+    pub(crate) fn into_reader(self) -> impl tokio::io::AsyncBufRead + Send + Unpin + 'static {
+        let chunks = stream::unfold(self, |mut blob| async move {
+            match blob.chunk().await {
+                Ok(Some(chunk)) => Some((Ok(chunk), blob)),
+                Ok(None) => None,
+                Err(err) => Some((Err(io::Error::other(err)), blob)),
+            }
+        });
+        StreamReader::new(Box::pin(chunks))
+    }
+
+    /// Returns the remaining blob contents as a request body, to forward
+    /// them to another registry.
+    ///
+    /// The body fails once all content has been read if it does not match
+    /// `expected` according to `policy`, aborting the upload. It is paced by
+    /// `limiter`, if any.
+    pub(crate) fn into_verified_body(
+        self,
+        expected: Digest,
+        policy: VerificationPolicy,
+        limiter: Option<&BandwidthLimiter>,
+    ) -> reqwest::Body {
+        let len = self.len.map(|len| len as u64);
+        let url = self.url;
+        let state = Some((self.chunks, self.hasher, 0u64));
+
+        let stream = stream::unfold(state, move |state| {
+            let expected = expected.clone();
+            let url = url.clone();
+            async move {
+                let (mut chunks, mut hasher, received) = state?;
+                match chunks.next().await {
+                    Some(Ok(chunk)) => {
+                        hasher.input(&chunk);
+                        let received = received + chunk.len() as u64;
+                        Some((Ok(chunk), Some((chunks, hasher, received))))
+                    }
+                    Some(Err(err)) => Some((Err(io::Error::other(err)), None)),
+                    None => {
+                        let actual = Digest::from_sha256(hasher.result());
+                        policy
+                            .check_size(len, received)
+                            .and_then(|_| policy.check_digest(&expected, &actual))
+                            .err()
+                            .map(|err| {
+                                let err = err.with_url(url.as_ref());
+                                let err =
+                                    io::Error::new(io::ErrorKind::InvalidData, err.to_string());
+                                (Err(err), None)
+                            })
+                    }
+                }
+            }
+        });
+        match limiter {
+            Some(limiter) => reqwest::Body::wrap_stream(limiter.throttle(stream)),
+            None => reqwest::Body::wrap_stream(stream),
         }
     }
 
     /// Returns the sha256 hash of the downloaded content.
-    #[cfg(feature = "sha256")]
     pub fn digest(self) -> Digest {
         Digest::from_sha256(self.hasher.result())
     }
 }
 
+/// Decompressed contents of a [`Blob`], see [`Blob::decompress`].
+#[cfg(feature = "gzip")]
+pub struct DecompressedBlob {
+    reader: layer::Reader,
+    buffer: BytesMut,
+    expected: Option<(Digest, VerificationPolicy)>,
+    hasher: Sha256,
+}
+
+#[cfg(feature = "gzip")]
+impl DecompressedBlob {
+    /// Read a chunk of the decompressed contents.
+    ///
+    /// The last call (returning `None`) fails if the compressed contents do
+    /// not match their digest, or if the decompressed contents do not match
+    /// the expected `diff_id` (see [`DecompressedBlob::verify`]).
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, ErrorResponse> {
+        let mut buffer = std::mem::take(&mut self.buffer);
+        buffer.reserve(DECOMPRESSED_CHUNK_SIZE);
+        let read = self.read_buf(&mut buffer).await;
+        let chunk = buffer.split().freeze();
+        self.buffer = buffer;
+
+        match read.map_err(into_error_response)? {
+            0 => Ok(None),
+            _ => Ok(Some(chunk)),
+        }
+    }
+
+    /// Unpack the decompressed tar stream into the directory `path`,
+    /// creating it if needed.
+    ///
+    /// Entries are unpacked as is, whiteout files are not applied (see
+    /// [`crate::rootfs`] to apply a layer stack). Entries that would be written outside of `path`
+    /// (`..` components, paths through symlinks leading out of it) are
+    /// skipped, absolute paths are unpacked relative to `path`.
+    /// The contents are read to the end, so they are verified even if the
+    /// tar stream has trailing data.
+    #[cfg(feature = "tar")]
+    pub async fn untar_to<P: AsRef<Path>>(self, path: P) -> Result<(), ErrorResponse> {
+        let path = path.as_ref().to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            let mut archive = tar::Archive::new(SyncIoBridge::new(self));
+            archive.set_preserve_permissions(true);
+            std::fs::create_dir_all(&path)?;
+            archive.unpack(&path)?;
+            io::copy(&mut archive.into_inner(), &mut io::sink())?;
+            Ok(())
+        })
+        .await
+        .map_err(io::Error::other)?
+        .map_err(into_error_response)
+    }
+
+    /// Check the decompressed contents against `diff_id` once fully read,
+    /// if `policy` verifies diff IDs.
+    pub fn verify(mut self, diff_id: Digest, policy: VerificationPolicy) -> Self {
+        self.expected = Some((diff_id, policy));
+        self
+    }
+
+    /// Returns the sha256 hash of the decompressed contents read so far, the
+    /// `diff_id` of the layer once fully read.
+    pub fn diff_id(&self) -> Digest {
+        Digest::from_sha256(self.hasher.clone().result())
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl AsyncRead for DecompressedBlob {
+    /// Reads the decompressed contents, failing at the end of the contents
+    /// if they do not match their digests, see [`DecompressedBlob::chunk`].
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        ready!(self.reader.as_mut().poll_read(cx, buf))?;
+
+        let read = &buf.filled()[filled..];
+        if !read.is_empty() {
+            self.hasher.input(read);
+        } else if buf.remaining() > 0 {
+            if let Some((expected, policy)) = self.expected.take() {
+                if policy.verifies_diff_ids() {
+                    let actual = Digest::from_sha256(self.hasher.clone().result());
+                    policy
+                        .check_digest(&expected, &actual)
+                        .map_err(io::Error::other)?;
+                }
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Returns the error carried by `err`, as wrapped by readers of blob
+/// contents.
+#[cfg(feature = "gzip")]
+pub(crate) fn into_error_response(err: io::Error) -> ErrorResponse {
+    match err.get_ref().map(|inner| inner.is::<ErrorResponse>()) {
+        Some(true) => *err.into_inner().unwrap().downcast().unwrap(),
+        _ => ErrorResponse::from(err),
+    }
+}
+
 impl From<reqwest::Response> for Blob {
     fn from(response: reqwest::Response) -> Self {
         let headers = response.headers();
@@ -69,12 +551,196 @@ impl From<reqwest::Response> for Blob {
             .get(reqwest::header::CONTENT_TYPE)
             .map(|v| std::str::from_utf8(v.as_ref()).unwrap().to_string());
         let len = response.content_length().map(|v| v as usize);
+        let url = Some(response.url().clone());
 
         Self {
             len,
             content_type,
-            response,
+            url,
+            chunks: response
+                .bytes_stream()
+                .map(|chunk| chunk.map_err(ErrorResponse::from))
+                .boxed(),
+            received: 0,
+            expected: None,
             hasher: Sha256::new(),
+            context: None,
+        }
+    }
+}
+
+impl Blob {
+    /// Record that the contents are downloaded by the request `context`, in
+    /// the errors reading them.
+    pub(crate) fn with_context(mut self, context: ErrorContext) -> Self {
+        self.context = Some(context);
+        self
+    }
+
+    /// Hold the request slot `permit` until the contents are consumed.
+    pub(crate) fn with_permit(mut self, permit: Permit) -> Self {
+        self.chunks = self
+            .chunks
+            .map(move |chunk| {
+                let _permit = &permit;
+                chunk
+            })
+            .boxed();
+        self
+    }
+
+    /// Fail with a [`TimeoutPhase::Idle`] timeout if no chunk is received for
+    /// `timeout`.
+    pub(crate) fn with_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        if timeout.is_some() {
+            self.chunks = stream::unfold(Some(self.chunks), move |chunks| async move {
+                let mut chunks = chunks?;
+                match timeout::within(timeout, TimeoutPhase::Idle, chunks.next()).await {
+                    Ok(Some(chunk)) => Some((chunk, Some(chunks))),
+                    Ok(None) => None,
+                    Err(err) => Some((Err(err), None)),
+                }
+            })
+            .boxed();
+        }
+        self
+    }
+
+    /// Resume the download up to `max_resumes` times when reading a chunk
+    /// fails, continuing from the number of bytes already received.
+    ///
+    /// Resumed contents are appended to the same stream, the digest keeps
+    /// covering the whole blob. The original error is returned when the
+    /// download cannot be resumed.
+    pub(crate) fn with_resume(mut self, max_resumes: usize, resume_from: ResumeFrom) -> Self {
+        let state = (self.chunks, 0u64, max_resumes, resume_from);
+        self.chunks = stream::unfold(Some(state), |state| async move {
+            let (mut chunks, mut offset, mut resumes, resume_from) = state?;
+            loop {
+                match chunks.next().await {
+                    Some(Ok(chunk)) => {
+                        offset += chunk.len() as u64;
+                        return Some((Ok(chunk), Some((chunks, offset, resumes, resume_from))));
+                    }
+                    Some(Err(err)) if resumes > 0 => {
+                        resumes -= 1;
+                        // Release the request slot held by the failed
+                        // download before waiting for another one.
+                        drop(chunks);
+                        match resume_from(offset).await {
+                            Ok(Some(blob)) => chunks = blob.chunks,
+                            Ok(None) | Err(_) => return Some((Err(err), None)),
+                        }
+                    }
+                    Some(Err(err)) => return Some((Err(err), None)),
+                    None => return None,
+                }
+            }
+        })
+        .boxed();
+        self
+    }
+
+    /// Returns a blob with the given in-memory contents.
+    pub(crate) fn from_bytes(content: Bytes, content_type: Option<String>) -> Self {
+        let len = content.len();
+        Self::from_chunks(
+            stream::once(async move { Ok(content) }).boxed(),
+            Some(len),
+            content_type,
+        )
+    }
+
+    /// Returns a blob streaming `chunks`.
+    pub(crate) fn from_chunks(
+        chunks: Chunks,
+        len: Option<usize>,
+        content_type: Option<String>,
+    ) -> Self {
+        Self {
+            len,
+            content_type,
+            url: None,
+            chunks,
+            received: 0,
+            expected: None,
+            hasher: Sha256::new(),
+            context: None,
+        }
+    }
+}
+
+/// A range of bytes of a blob, see
+/// [`crate::DockerRegistryClientV2::blob_range`].
+///
+/// ```
+/// # use oci_registry_client::blob::ByteRange;
+/// assert_eq!(ByteRange::from(0..512).to_string(), "bytes=0-511");
+/// assert_eq!(ByteRange::from(1024..).to_string(), "bytes=1024-");
+/// assert_eq!(ByteRange::Last(512).to_string(), "bytes=-512");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteRange {
+    /// Bytes `start..end`.
+    Bounded { start: u64, end: u64 },
+    /// Bytes from `start` to the end of the blob.
+    From(u64),
+    /// The last bytes of the blob.
+    Last(u64),
+}
+
+impl From<std::ops::Range<u64>> for ByteRange {
+    fn from(range: std::ops::Range<u64>) -> Self {
+        Self::Bounded {
+            start: range.start,
+            end: range.end,
+        }
+    }
+}
+
+impl From<std::ops::RangeFrom<u64>> for ByteRange {
+    fn from(range: std::ops::RangeFrom<u64>) -> Self {
+        Self::From(range.start)
+    }
+}
+
+impl ByteRange {
+    /// Returns the offset of the first byte of the range, unless it is
+    /// relative to the end of the blob.
+    pub(crate) fn start(&self) -> Option<u64> {
+        match self {
+            Self::Bounded { start, .. } | Self::From(start) => Some(*start),
+            Self::Last(_) => None,
+        }
+    }
+
+    /// Returns `true` if the range holds no bytes (`start >= end`).
+    pub(crate) fn is_empty(&self) -> bool {
+        matches!(self, Self::Bounded { start, end } if start >= end)
+    }
+}
+
+/// Returns the offset of the first byte of the `Content-Range` of
+/// `response`.
+pub(crate) fn content_range_start(response: &reqwest::Response) -> Option<u64> {
+    let value = response
+        .headers()
+        .get(reqwest::header::CONTENT_RANGE)?
+        .to_str()
+        .ok()?;
+    let (start, _) = value.strip_prefix("bytes ")?.split_once('-')?;
+    start.trim().parse().ok()
+}
+
+impl std::fmt::Display for ByteRange {
+    /// Formats the range as a `Range` header value.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bounded { start, end } => {
+                write!(f, "bytes={}-{}", start, end.saturating_sub(1))
+            }
+            Self::From(start) => write!(f, "bytes={}-", start),
+            Self::Last(len) => write!(f, "bytes=-{}", len),
         }
     }
 }
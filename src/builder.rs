@@ -0,0 +1,451 @@
+//! Client builder.
+//!
+//! [`DockerRegistryClientV2::builder`] configures a client with named
+//! options instead of positional arguments, only the registry API URL is
+//! required: the token endpoint is discovered when not configured.
+//!
+//! The API URL may omit its scheme (`registry.example.com:5000`), HTTPS is
+//! then used. Plain HTTP must be allowed explicitly with
+//! [`ClientBuilder::insecure_http`], for local registries:
+//!
+//! ```no_run
+//! # use oci_registry_client::DockerRegistryClientV2;
+//! # fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = DockerRegistryClientV2::builder("localhost:5000")
+//!     .insecure_http(true)
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ```no_run
+//! # use oci_registry_client::{auth::Credentials, DockerRegistryClientV2};
+//! # fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = DockerRegistryClientV2::builder("https://registry-1.docker.io")
+//!     .service("registry.docker.io")
+//!     .auth_url("https://auth.docker.io/token")
+//!     .credentials(Credentials::new("ci", "s3cr3t"))
+//!     .user_agent("my-tool/1.0")
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    auth::Credentials, errors::ErrorResponse, manifest::MediaType, proxy::Proxy, redirect,
+    registries, retry::RetryPolicy, timeout::Timeouts, tls::TlsConfig, DockerRegistryClientV2,
+    USER_AGENT,
+};
+use reqwest::{header::HeaderMap, Url};
+use std::{
+    fmt, io,
+    net::{SocketAddr, ToSocketAddrs},
+    sync::Arc,
+    time::Duration,
+};
+
+/// Builder of a [`DockerRegistryClientV2`], see the [module](self)
+/// documentation.
+#[derive(Clone, Debug)]
+pub struct ClientBuilder {
+    api_url: String,
+    service: Option<String>,
+    auth_url: Option<String>,
+    credentials: Option<Credentials>,
+    timeouts: Timeouts,
+    retry: Option<RetryPolicy>,
+    mirrors: Vec<DockerRegistryClientV2>,
+    insecure_http: bool,
+    server_name: Option<String>,
+    http: HttpOptions,
+    manifest_media_types: Option<Vec<MediaType>>,
+}
+
+impl ClientBuilder {
+    pub(crate) fn new<T: Into<String>>(api_url: T) -> Self {
+        Self {
+            api_url: api_url.into().trim_end_matches('/').to_owned(),
+            service: None,
+            auth_url: None,
+            credentials: None,
+            timeouts: Timeouts::default(),
+            retry: None,
+            mirrors: vec![],
+            insecure_http: false,
+            server_name: None,
+            http: HttpOptions::default(),
+            manifest_media_types: None,
+        }
+    }
+
+    /// Name of the registry service sent in token requests (example:
+    /// registry.docker.io), the host of the API URL by default, or the
+    /// service of a [well-known registry](crate::registries).
+    pub fn service<T: Into<String>>(mut self, service: T) -> Self {
+        self.service = Some(service.into());
+        self
+    }
+
+    /// Address to get a OAuth 2.0 token for the service (see
+    /// [`DockerRegistryClientV2::auth`]). Defaults to the token endpoint of
+    /// [well-known registries](crate::registries), other registries are
+    /// probed for the endpoint (and service) they announce, see
+    /// [`DockerRegistryClientV2::auth_endpoint`].
+    pub fn auth_url<T: Into<String>>(mut self, auth_url: T) -> Self {
+        self.auth_url = Some(auth_url.into());
+        self
+    }
+
+    /// Authenticate as the user identified by `credentials`, see
+    /// [`DockerRegistryClientV2::with_credentials`].
+    pub fn credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    /// Timeouts applied to the phases of requests, see [`crate::timeout`].
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Maximum time to establish a connection.
+    pub fn connect_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeouts.connect = timeout;
+        self
+    }
+
+    /// Maximum time to wait for the response headers, then for each chunk
+    /// of the response body, of all requests.
+    pub fn read_timeout(mut self, timeout: Option<Duration>) -> Self {
+        for phase in [&mut self.timeouts.manifests, &mut self.timeouts.blobs] {
+            phase.first_byte = timeout;
+            phase.idle = timeout;
+        }
+        self
+    }
+
+    /// Maximum total time of manifest, config and token requests. Blob
+    /// downloads are only bounded by the [read timeout](Self::read_timeout).
+    pub fn request_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeouts.manifests.total = timeout;
+        self
+    }
+
+    /// Try `mirror` before the registry, see [`crate::mirror`]. Can be
+    /// called several times, mirrors are tried in order.
+    pub fn mirror(mut self, mirror: DockerRegistryClientV2) -> Self {
+        self.mirrors.push(mirror);
+        self
+    }
+
+    /// Retry throttled and failed requests according to `policy`, see
+    /// [`crate::retry`].
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// TLS options of the underlying HTTP client, see [`crate::tls`].
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.http.tls = tls;
+        self
+    }
+
+    /// Name of the registry server, when it differs from the host of the API
+    /// URL (a load balancer or an IP address for example).
+    ///
+    /// Connections are still opened to the host and port of the API URL,
+    /// but `name` is sent in the `Host` header and as TLS SNI, and the
+    /// server certificate is validated for `name`. The host of the API URL
+    /// is resolved when the client is built.
+    ///
+    /// ```no_run
+    /// # use oci_registry_client::DockerRegistryClientV2;
+    /// # fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = DockerRegistryClientV2::builder("https://10.0.0.12:5000")
+    ///     .server_name("registry.example.com")
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn server_name<T: Into<String>>(mut self, name: T) -> Self {
+        self.server_name = Some(name.into());
+        self
+    }
+
+    /// Allow plain HTTP requests (disallowed by default), for `http://` API
+    /// URLs or API URLs without scheme. Registry requests are then sent in
+    /// clear text, credentials included.
+    ///
+    /// Without an [auth URL](Self::auth_url), credentials are sent to the
+    /// registry with HTTP basic authentication, as expected by a `registry:2`
+    /// container protected by an `htpasswd` file.
+    pub fn insecure_http(mut self, insecure: bool) -> Self {
+        self.insecure_http = insecure;
+        self
+    }
+
+    /// Send requests through `proxy`, see [`crate::proxy`]. Can be called
+    /// several times, the first proxy matching a request is used.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.http.proxies.push(proxy);
+        self
+    }
+
+    /// Options of the connection pool, see [`PoolOptions`].
+    pub fn pool(mut self, pool: PoolOptions) -> Self {
+        self.http.pool = pool;
+        self
+    }
+
+    /// `User-Agent` header sent with requests, the crate name and version by
+    /// default.
+    pub fn user_agent<T: Into<String>>(mut self, user_agent: T) -> Self {
+        self.http.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Headers sent with every request, added to the headers already set
+    /// (an organization specific header, a tracing header...). Headers set
+    /// by a request take precedence.
+    ///
+    /// Unlike the `Authorization` header, default headers are also sent to
+    /// the other origins (blob storage, CDNs) requests are redirected to.
+    ///
+    /// ```no_run
+    /// # use oci_registry_client::DockerRegistryClientV2;
+    /// # use reqwest::header::{HeaderMap, HeaderValue};
+    /// # fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mut headers = HeaderMap::new();
+    /// headers.insert("x-request-source", HeaderValue::from_static("ci"));
+    /// let client = DockerRegistryClientV2::builder("https://registry.example.com")
+    ///     .user_agent("my-tool/1.0")
+    ///     .default_headers(headers)
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn default_headers(mut self, headers: HeaderMap) -> Self {
+        self.http.default_headers.extend(headers);
+        self
+    }
+
+    /// Send requests with `client` instead of building one, to use options
+    /// this crate does not expose.
+    ///
+    /// The connect timeout, TLS, proxy, pool, user agent and default headers
+    /// options of this builder are then ignored. Redirects follow the policy of `client`
+    /// instead of [`crate::redirect::policy`].
+    pub fn http_client(mut self, client: reqwest::Client) -> Self {
+        self.http.client = Some(client);
+        self
+    }
+
+    /// Call `configure` on the [`reqwest::ClientBuilder`] set up with the
+    /// options of this builder, before building the HTTP client.
+    ///
+    /// `configure` is called again when the HTTP client is rebuilt (see
+    /// [`DockerRegistryClientV2::set_timeouts`]).
+    ///
+    /// ```no_run
+    /// # use oci_registry_client::DockerRegistryClientV2;
+    /// # use std::time::Duration;
+    /// # fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = DockerRegistryClientV2::builder("https://registry.example.com")
+    ///     .configure_http_client(|builder| {
+    ///         builder
+    ///             .resolve("registry.example.com", "10.0.0.12:443".parse().unwrap())
+    ///             .tcp_nodelay(true)
+    ///     })
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn configure_http_client<F>(mut self, configure: F) -> Self
+    where
+        F: Fn(reqwest::ClientBuilder) -> reqwest::ClientBuilder + Send + Sync + 'static,
+    {
+        self.http.configure = Some(Configure(Arc::new(configure)));
+        self
+    }
+
+    /// Manifest media types accepted when fetching manifests, in order of
+    /// preference. OCI and Docker schema 2 manifests and indexes are
+    /// accepted by default.
+    pub fn manifest_media_types<I: IntoIterator<Item = MediaType>>(mut self, types: I) -> Self {
+        self.manifest_media_types = Some(types.into_iter().collect());
+        self
+    }
+
+    /// Returns the configured client, failing if the HTTP client can't be
+    /// built (invalid TLS options or proxy URL for example).
+    ///
+    /// Requests to `http://` URLs fail unless [insecure
+    /// HTTP](Self::insecure_http) is allowed.
+    pub fn build(mut self) -> Result<DockerRegistryClientV2, ErrorResponse> {
+        if !self.api_url.contains("://") {
+            let scheme = if self.insecure_http { "http" } else { "https" };
+            self.api_url = format!("{}://{}", scheme, self.api_url);
+        }
+        if let Some(name) = self.server_name.take() {
+            self.api_url = self.connect_as(&name)?;
+        }
+        self.http.https_only = !self.insecure_http;
+        let client = self.http.build(self.timeouts.connect)?;
+        let host = reqwest::Url::parse(&self.api_url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_owned));
+        let known = host.as_deref().and_then(registries::well_known);
+        let service = self.service.unwrap_or_else(|| match (known, host) {
+            (Some(known), _) => known.service.to_owned(),
+            (None, Some(host)) => host,
+            (None, None) => self.api_url.clone(),
+        });
+        let auth_url = self
+            .auth_url
+            .or_else(|| known.map(|known| known.auth_url.to_owned()));
+
+        let mut registry = DockerRegistryClientV2::from_http_client(
+            service,
+            self.api_url,
+            auth_url.unwrap_or_default(),
+            client,
+            self.http,
+        );
+        registry.credentials = self.credentials;
+        registry.timeouts = self.timeouts;
+        registry.retry = self.retry;
+        registry.set_mirrors(self.mirrors);
+        if let Some(types) = self.manifest_media_types {
+            let types: Vec<_> = types.iter().map(MediaType::as_str).collect();
+            registry.manifest_accept = types.join(", ");
+        }
+        Ok(registry)
+    }
+}
+
+impl ClientBuilder {
+    /// Resolve the host of the API URL to connect to it when talking to
+    /// `name`, returning the API URL with `name` as host.
+    fn connect_as(&mut self, name: &str) -> Result<String, ErrorResponse> {
+        let invalid = |err| io::Error::new(io::ErrorKind::InvalidInput, err);
+        let mut url = Url::parse(&self.api_url).map_err(invalid)?;
+        let addrs: Vec<SocketAddr> = match (url.host_str(), url.port_or_known_default()) {
+            (Some(host), Some(port)) => (host.trim_matches(['[', ']']), port)
+                .to_socket_addrs()?
+                .collect(),
+            _ => vec![],
+        };
+        url.set_host(Some(name)).map_err(invalid)?;
+
+        let tls = std::mem::take(&mut self.http.tls);
+        self.http.tls = addrs
+            .into_iter()
+            .fold(tls, |tls, addr| tls.resolve(name, addr));
+        Ok(url.as_str().trim_end_matches('/').to_owned())
+    }
+}
+
+/// Options of the connection pool of the HTTP client.
+///
+/// Many layers pulled concurrently open as many connections to the
+/// registry. Capping the idle connections kept (and the concurrent requests,
+/// see [`DockerRegistryClientV2::set_max_concurrent_requests`]) keeps heavy
+/// pulls from exhausting connections.
+///
+/// ```no_run
+/// # use oci_registry_client::{builder::PoolOptions, DockerRegistryClientV2};
+/// # use std::time::Duration;
+/// # fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = DockerRegistryClientV2::builder("https://registry.example.com")
+///     .pool(PoolOptions {
+///         max_idle_per_host: 8,
+///         idle_timeout: Some(Duration::from_secs(30)),
+///         tcp_keepalive: Some(Duration::from_secs(60)),
+///     })
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolOptions {
+    /// Maximum number of idle connections kept per host (unlimited by
+    /// default).
+    pub max_idle_per_host: usize,
+    /// Time after which idle connections are closed (90 seconds by default),
+    /// `None` to keep them open.
+    pub idle_timeout: Option<Duration>,
+    /// Interval of TCP keepalive probes (disabled by default).
+    pub tcp_keepalive: Option<Duration>,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        Self {
+            max_idle_per_host: usize::MAX,
+            idle_timeout: Some(Duration::from_secs(90)),
+            tcp_keepalive: None,
+        }
+    }
+}
+
+/// Options of the underlying HTTP client, kept to rebuild it when they
+/// change.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct HttpOptions {
+    pub(crate) tls: TlsConfig,
+    pub(crate) user_agent: Option<String>,
+    pub(crate) default_headers: HeaderMap,
+    pub(crate) proxies: Vec<Proxy>,
+    pub(crate) pool: PoolOptions,
+    pub(crate) https_only: bool,
+    pub(crate) client: Option<reqwest::Client>,
+    pub(crate) configure: Option<Configure>,
+}
+
+/// Customization of the HTTP client builder, see
+/// [`ClientBuilder::configure_http_client`].
+#[derive(Clone)]
+pub(crate) struct Configure(
+    Arc<dyn Fn(reqwest::ClientBuilder) -> reqwest::ClientBuilder + Send + Sync>,
+);
+
+impl fmt::Debug for Configure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Configure")
+    }
+}
+
+impl HttpOptions {
+    /// Build the HTTP client used to send requests.
+    pub(crate) fn build(
+        &self,
+        connect_timeout: Option<Duration>,
+    ) -> Result<reqwest::Client, ErrorResponse> {
+        if let Some(client) = &self.client {
+            return Ok(client.clone());
+        }
+        let user_agent = self.user_agent.as_deref().unwrap_or(USER_AGENT);
+        let mut builder = self.tls.apply(
+            reqwest::Client::builder()
+                .user_agent(user_agent)
+                .default_headers(self.default_headers.clone())
+                .redirect(redirect::policy())
+                .pool_max_idle_per_host(self.pool.max_idle_per_host)
+                .pool_idle_timeout(self.pool.idle_timeout)
+                .tcp_keepalive(self.pool.tcp_keepalive)
+                .https_only(self.https_only),
+        );
+        if let Some(timeout) = connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        for proxy in &self.proxies {
+            builder = builder.proxy(proxy.to_reqwest()?);
+        }
+        if let Some(Configure(configure)) = &self.configure {
+            builder = configure(builder);
+        }
+        Ok(builder.build()?)
+    }
+}
@@ -0,0 +1,120 @@
+//! HTTP caching information.
+//!
+//! Registries (and CDNs in front of them) announce how long responses may be
+//! reused with the `Cache-Control`, `Expires` and `Age` headers.
+//! [`CacheControl`] exposes them for manifest responses, so pull-through
+//! caches can honor upstream freshness policies:
+//!
+//! ```no_run
+//! # use oci_registry_client::DockerRegistryClientV2;
+//! # async fn example(client: DockerRegistryClientV2) -> Result<(), Box<dyn std::error::Error>> {
+//! let (body, content_type, digest, cache_control) = client
+//!     .manifest_raw_with_cache_control("library/alpine", "latest")
+//!     .await?;
+//! match cache_control.ttl() {
+//!     Some(ttl) => println!("{} is fresh for {:?}", digest, ttl),
+//!     None => println!("{} must be revalidated", digest),
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use reqwest::header::{HeaderMap, AGE, CACHE_CONTROL, DATE, EXPIRES};
+use std::time::{Duration, SystemTime};
+
+/// Caching directives of a response.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    /// `max-age` directive.
+    pub max_age: Option<Duration>,
+    /// `s-maxage` directive, overriding `max-age` for shared caches.
+    pub s_maxage: Option<Duration>,
+    /// `no-cache`: responses must be revalidated before being reused.
+    pub no_cache: bool,
+    /// `no-store`: responses must not be stored.
+    pub no_store: bool,
+    /// `must-revalidate`: stale responses must not be reused.
+    pub must_revalidate: bool,
+    /// `private`: responses must not be stored by shared caches.
+    pub private: bool,
+    /// `Expires` header.
+    pub expires: Option<SystemTime>,
+    /// `Date` header.
+    pub date: Option<SystemTime>,
+    /// `Age` header, time the response already spent in upstream caches.
+    pub age: Option<Duration>,
+}
+
+impl CacheControl {
+    /// Parse the caching headers of a response.
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+        let date = |name| header(name).and_then(|value| httpdate::parse_http_date(value).ok());
+        let seconds = |value: &str| value.trim().parse().ok().map(Duration::from_secs);
+
+        let mut cache_control = Self {
+            expires: date(EXPIRES),
+            date: date(DATE),
+            age: header(AGE).and_then(seconds),
+            ..Self::default()
+        };
+
+        let directives = headers
+            .get_all(CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+        for directive in directives {
+            let (name, value) = match directive.split_once('=') {
+                Some((name, value)) => (name, Some(value.trim().trim_matches('"'))),
+                None => (directive, None),
+            };
+            match name.trim().to_ascii_lowercase().as_str() {
+                "max-age" => cache_control.max_age = value.and_then(seconds),
+                "s-maxage" => cache_control.s_maxage = value.and_then(seconds),
+                "no-cache" => cache_control.no_cache = true,
+                "no-store" => cache_control.no_store = true,
+                "must-revalidate" => cache_control.must_revalidate = true,
+                "private" => cache_control.private = true,
+                _ => {}
+            }
+        }
+
+        cache_control
+    }
+
+    /// Returns how long the response stays fresh, from the time it was
+    /// received, for a shared cache.
+    ///
+    /// `None` if it can't be reused without revalidation: it is not
+    /// cacheable, already stale or no freshness information was announced.
+    ///
+    /// ```
+    /// # use oci_registry_client::cache_control::CacheControl;
+    /// # use std::time::Duration;
+    /// let cache_control = CacheControl {
+    ///     max_age: Some(Duration::from_secs(300)),
+    ///     age: Some(Duration::from_secs(60)),
+    ///     ..CacheControl::default()
+    /// };
+    /// assert_eq!(cache_control.ttl(), Some(Duration::from_secs(240)));
+    /// ```
+    pub fn ttl(&self) -> Option<Duration> {
+        if self.no_store || self.no_cache || self.private {
+            return None;
+        }
+
+        let lifetime = self.s_maxage.or(self.max_age).or_else(|| {
+            let expires = self.expires?;
+            Some(
+                expires
+                    .duration_since(self.date.unwrap_or_else(SystemTime::now))
+                    .unwrap_or_default(),
+            )
+        })?;
+
+        lifetime
+            .checked_sub(self.age.unwrap_or_default())
+            .filter(|ttl| !ttl.is_zero())
+    }
+}
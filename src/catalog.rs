@@ -0,0 +1,125 @@
+//! Repository listing.
+//!
+//! The `_catalog` endpoint lists the repositories of a registry. It is
+//! usually restricted to administrators, and disabled on public registries
+//! such as Docker Hub.
+//!
+//! ```no_run
+//! # use oci_registry_client::DockerRegistryClientV2;
+//! # async fn example(client: DockerRegistryClientV2) -> Result<(), Box<dyn std::error::Error>> {
+//! let mut last = None;
+//! loop {
+//!     let page = client.catalog_page(Some(100), last.as_deref()).await?;
+//!     for repository in &page.repositories {
+//!         println!("{}", repository);
+//!     }
+//!     match page.last {
+//!         Some(next) => last = Some(next),
+//!         None => break,
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    auth::Scope,
+    errors::{reject_html, ErrorResponse},
+    pagination::next_page_url,
+    scheduler::Priority,
+    DockerRegistryClientV2, MEDIA_TYPE_JSON,
+};
+use reqwest::{header, StatusCode, Url};
+
+/// A page of repositories.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CatalogPage {
+    pub repositories: Vec<String>,
+    /// Value of `last` to request the next page with, `None` on the last
+    /// page.
+    pub last: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct CatalogResponse {
+    #[serde(default)]
+    repositories: Option<Vec<String>>,
+}
+
+impl DockerRegistryClientV2 {
+    /// List all repositories of the registry, following pagination.
+    pub async fn catalog(&self) -> Result<Vec<String>, ErrorResponse> {
+        let mut repositories = vec![];
+        let mut next = Some(format!("{}/v2/_catalog", &self.api_url));
+
+        while let Some(url) = next {
+            let (page, next_url) = self.fetch_catalog(&url).await?;
+            repositories.extend(page);
+            next = next_url;
+        }
+
+        Ok(repositories)
+    }
+
+    /// List at most `n` repositories (registry default if `None`), starting
+    /// after the repository `last`.
+    pub async fn catalog_page(
+        &self,
+        n: Option<usize>,
+        last: Option<&str>,
+    ) -> Result<CatalogPage, ErrorResponse> {
+        let mut url = Url::parse(&format!("{}/v2/_catalog", &self.api_url))
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+        {
+            let mut query = url.query_pairs_mut();
+            if let Some(n) = n {
+                query.append_pair("n", &n.to_string());
+            }
+            if let Some(last) = last {
+                query.append_pair("last", last);
+            }
+        }
+
+        let (repositories, next) = self.fetch_catalog(url.as_str()).await?;
+        let last = next.map(|next| {
+            Url::parse(&next)
+                .ok()
+                .and_then(|next| {
+                    next.query_pairs()
+                        .find(|(key, _)| key == "last")
+                        .map(|(_, value)| value.into_owned())
+                })
+                .or_else(|| repositories.last().cloned())
+                .unwrap_or_default()
+        });
+
+        Ok(CatalogPage { repositories, last })
+    }
+
+    /// Fetch the catalog page at `url`, returns its repositories and the URL
+    /// of the next page.
+    async fn fetch_catalog(
+        &self,
+        url: &str,
+    ) -> Result<(Vec<String>, Option<String>), ErrorResponse> {
+        let scope = Scope {
+            r#type: "registry".to_owned(),
+            name: "catalog".to_owned(),
+            actions: "*".to_owned(),
+        };
+        let request = self.client.get(url).header(header::ACCEPT, MEDIA_TYPE_JSON);
+        let request = self.authorize_scope(request, Some(&scope)).await?;
+        let _permit = self.scheduler.acquire(Priority::High).await;
+        let response = self
+            .send_with_retries(request, &self.timeouts.manifests)
+            .await?;
+
+        if response.status() != StatusCode::OK {
+            return Err(ErrorResponse::from_response(response).await);
+        }
+
+        let next = next_page_url(url, response.headers());
+        let page: CatalogResponse = reject_html(response).await?.json().await?;
+        Ok((page.repositories.unwrap_or_default(), next))
+    }
+}
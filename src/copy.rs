@@ -0,0 +1,475 @@
+//! Copy images between repositories and registries.
+//!
+//! Manifests are copied byte for byte, so copied images keep their digests,
+//! unless they are translated to another format (see
+//! [`CopySpec::convert_to`]).
+//! [`copy_many`] copies several images concurrently, blobs shared between
+//! images (base layers) are transferred once and then mounted into the other
+//! destination repositories.
+//!
+//! ```no_run
+//! use oci_registry_client::{
+//!     copy::{copy_many, CopySpec},
+//!     rename::{NameRule, NameRules},
+//!     DockerRegistryClientV2,
+//! };
+//!
+//! # async fn example(source: DockerRegistryClientV2, destination: DockerRegistryClientV2) -> Result<(), Box<dyn std::error::Error>> {
+//! let rules = NameRules::new(vec![NameRule::Prefix {
+//!     from: "library".to_owned(),
+//!     to: "mirror".to_owned(),
+//! }])?;
+//! let specs = vec![
+//!     CopySpec::new("library/alpine", "3.19").rename(&rules),
+//!     CopySpec::new("library/busybox", "latest").rename(&rules),
+//! ];
+//!
+//! let results = copy_many(&source, &destination, specs, 4, |progress| {
+//!     println!("{}/{} images", progress.images_done, progress.images_total);
+//! })
+//! .await;
+//!
+//! for result in results {
+//!     match result.result {
+//!         Ok(digest) => println!("{} copied ({})", result.spec.destination, digest),
+//!         Err(err) => eprintln!("{} failed; err={}", result.spec.source, err),
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    audit::Operation,
+    errors::ErrorResponse,
+    lockfile::{LockedImage, LockedManifest, LockedPlatform},
+    manifest::{Digest, Manifest, ManifestFormat, ManifestResponse, MediaType},
+    reference::Reference,
+    rename::NameRules,
+    DockerRegistryClientV2,
+};
+use futures_util::{future::BoxFuture, stream, FutureExt, StreamExt};
+use sha2::{Digest as Sha256Digest, Sha256};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// An image to copy.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CopySpec {
+    pub source: String,
+    pub reference: String,
+    pub destination: String,
+    pub destination_reference: String,
+    /// Format manifests are translated to, `None` to copy them as is.
+    pub format: Option<ManifestFormat>,
+}
+
+impl CopySpec {
+    /// Returns a spec copying `reference` of `image` to the same repository
+    /// name and reference at the destination.
+    pub fn new<T: Into<String>>(image: T, reference: T) -> Self {
+        let image = image.into();
+        let reference = reference.into();
+
+        Self {
+            destination: image.clone(),
+            destination_reference: reference.clone(),
+            source: image,
+            reference,
+            format: None,
+        }
+    }
+
+    /// Returns a spec copying the image `source` to `destination`, both
+    /// references in the registries of the source and destination clients.
+    pub fn from_references(source: &Reference, destination: &Reference) -> Self {
+        Self {
+            source: source.repository.clone(),
+            reference: source.tag_or_digest(),
+            destination: destination.repository.clone(),
+            destination_reference: destination.tag_or_digest(),
+            format: None,
+        }
+    }
+
+    /// Copy to repository `destination`.
+    pub fn to<T: Into<String>>(mut self, destination: T) -> Self {
+        self.destination = destination.into();
+        self
+    }
+
+    /// Translate manifests to `format`, for registries rejecting the media
+    /// types of the source (some only accept OCI manifests).
+    ///
+    /// Translated manifests get new digests.
+    pub fn convert_to(mut self, format: ManifestFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Copy to the repository name obtained by applying `rules` to the source
+    /// repository name.
+    pub fn rename(mut self, rules: &NameRules) -> Self {
+        self.destination = rules.apply(&self.source);
+        self
+    }
+}
+
+/// Aggregated progress of a copy.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CopyProgress {
+    pub images_total: usize,
+    pub images_done: usize,
+    /// Blobs transferred from the source.
+    pub blobs_copied: usize,
+    /// Blobs already present (or mounted) at the destination.
+    pub blobs_reused: usize,
+    pub bytes_copied: u64,
+}
+
+/// Outcome of the copy of a single image.
+#[derive(Debug)]
+pub struct CopyResult {
+    pub spec: CopySpec,
+    /// Digest of the copied manifest.
+    pub result: Result<Digest, ErrorResponse>,
+    /// The digests copied, as a lockfile entry for the destination image
+    /// (see [`crate::lockfile`]). `None` if the copy failed or for schema 1
+    /// images.
+    pub locked: Option<LockedImage>,
+}
+
+/// What to record in the lock of a copied manifest.
+#[allow(clippy::large_enum_variant)]
+enum Lock {
+    Image(Manifest),
+    /// The image manifests of a list, `None` if one can't be locked.
+    List(Option<Vec<LockedManifest>>),
+}
+
+impl Copied {
+    /// Returns the lockfile entry of the image copied for `spec`.
+    fn lock(self, spec: &CopySpec) -> Option<LockedImage> {
+        Some(LockedImage {
+            image: spec.destination.clone(),
+            reference: spec.destination_reference.clone(),
+            digest: self.digest,
+            media_type: self.media_type,
+            manifests: self.manifests?,
+        })
+    }
+}
+
+/// A manifest copied to the destination.
+struct Copied {
+    digest: Digest,
+    size: usize,
+    media_type: MediaType,
+    /// The image manifests copied, `None` for schema 1 images.
+    manifests: Option<Vec<LockedManifest>>,
+}
+
+struct CopyContext<'a> {
+    source: &'a DockerRegistryClientV2,
+    destination: &'a DockerRegistryClientV2,
+    /// Destination repository each blob was copied to, per digest.
+    blobs: Mutex<HashMap<Digest, Arc<tokio::sync::Mutex<Option<String>>>>>,
+    progress: Mutex<CopyProgress>,
+    on_progress: &'a (dyn Fn(&CopyProgress) + Send + Sync),
+}
+
+/// Copy a single image from `source` to `destination`.
+///
+/// Returns the digest of the copied manifest.
+pub async fn copy_image(
+    source: &DockerRegistryClientV2,
+    destination: &DockerRegistryClientV2,
+    spec: &CopySpec,
+) -> Result<Digest, ErrorResponse> {
+    CopyContext::new(source, destination, 1, &|_| {})
+        .copy(spec)
+        .await
+        .map(|copied| copied.digest)
+}
+
+/// Copy multiple images from `source` to `destination`, at most
+/// `concurrency` images at a time.
+///
+/// Blobs are deduplicated across all images. `on_progress` is called every
+/// time a blob or an image is done. Results are returned in `specs` order.
+pub async fn copy_many<F>(
+    source: &DockerRegistryClientV2,
+    destination: &DockerRegistryClientV2,
+    specs: Vec<CopySpec>,
+    concurrency: usize,
+    on_progress: F,
+) -> Vec<CopyResult>
+where
+    F: Fn(&CopyProgress) + Send + Sync,
+{
+    let context = CopyContext::new(source, destination, specs.len(), &on_progress);
+    let context = &context;
+
+    stream::iter(specs)
+        .map(|spec| async move {
+            let copied = context.copy(&spec).await;
+            context.report(|progress| progress.images_done += 1);
+            let (result, locked) = match copied {
+                Ok(copied) => (Ok(copied.digest.clone()), copied.lock(&spec)),
+                Err(err) => (Err(err), None),
+            };
+            CopyResult {
+                spec,
+                result,
+                locked,
+            }
+        })
+        .buffered(concurrency.max(1))
+        .collect()
+        .await
+}
+
+impl<'a> CopyContext<'a> {
+    fn new(
+        source: &'a DockerRegistryClientV2,
+        destination: &'a DockerRegistryClientV2,
+        images_total: usize,
+        on_progress: &'a (dyn Fn(&CopyProgress) + Send + Sync),
+    ) -> Self {
+        Self {
+            source,
+            destination,
+            blobs: Mutex::new(HashMap::new()),
+            progress: Mutex::new(CopyProgress {
+                images_total,
+                ..Default::default()
+            }),
+            on_progress,
+        }
+    }
+
+    fn report<F: FnOnce(&mut CopyProgress)>(&self, update: F) {
+        let progress = {
+            let mut progress = self.progress.lock().unwrap();
+            update(&mut progress);
+            progress.clone()
+        };
+        (self.on_progress)(&progress);
+    }
+
+    async fn copy(&self, spec: &CopySpec) -> Result<Copied, ErrorResponse> {
+        let operation = Operation::start("copy", &spec.destination, &spec.destination_reference);
+        let result = self
+            .copy_manifest(
+                spec,
+                &operation,
+                &spec.reference,
+                Some(&spec.destination_reference),
+            )
+            .await;
+        operation.finish(result.as_ref().map(|copied| &copied.digest));
+        result
+    }
+
+    /// Copy the manifest `reference` (and everything it references) to
+    /// `destination_reference`.
+    fn copy_manifest<'b>(
+        &'b self,
+        spec: &'b CopySpec,
+        operation: &'b Operation,
+        reference: &'b str,
+        destination_reference: Option<&'b str>,
+    ) -> BoxFuture<'b, Result<Copied, ErrorResponse>> {
+        async move {
+            let (body, content_type, _) = self.source.manifest_raw(&spec.source, reference).await?;
+
+            let (content_type, body, lock) =
+                match ManifestResponse::from_slice(content_type.as_deref(), &body)? {
+                    ManifestResponse::Image(manifest) => {
+                        let blobs =
+                            std::iter::once((&manifest.config.digest, manifest.config.size)).chain(
+                                manifest
+                                    .layers
+                                    .iter()
+                                    .filter(|layer| !layer.is_foreign())
+                                    .map(|layer| (&layer.digest, layer.size)),
+                            );
+                        for (digest, size) in blobs {
+                            self.copy_blob(spec, operation, digest, size).await?;
+                        }
+                        match spec.format {
+                            Some(format) => {
+                                let manifest = manifest.convert(format);
+                                let content_type = manifest.media_type_or(MediaType::OciManifest);
+                                (
+                                    content_type.to_string(),
+                                    serde_json::to_vec(&manifest)?.into(),
+                                    Lock::Image(manifest),
+                                )
+                            }
+                            None => (
+                                content_type.unwrap_or_else(|| {
+                                    manifest.media_type_or(MediaType::OciManifest).to_string()
+                                }),
+                                body,
+                                Lock::Image(manifest),
+                            ),
+                        }
+                    }
+                    ManifestResponse::List(mut list) => {
+                        // Converted manifests get new digests, pushed by digest.
+                        let mut manifests = Some(vec![]);
+                        for item in list.manifests.iter_mut() {
+                            let copied = self
+                                .copy_manifest(spec, operation, &item.digest.to_string(), None)
+                                .await?;
+                            item.digest = copied.digest;
+                            item.size = copied.size;
+                            // The lock records the platforms of the list items.
+                            let platform = item.platform.as_ref().map(LockedPlatform::from);
+                            manifests = manifests.zip(copied.manifests).map(|(mut all, copied)| {
+                                all.extend(copied.into_iter().map(|mut manifest| {
+                                    manifest.platform = manifest.platform.or(platform.clone());
+                                    manifest
+                                }));
+                                all
+                            });
+                        }
+                        match spec.format {
+                            Some(format) => {
+                                let list = list.convert(format);
+                                let content_type = list.media_type_or(MediaType::OciImageIndex);
+                                (
+                                    content_type.to_string(),
+                                    serde_json::to_vec(&list)?.into(),
+                                    Lock::List(manifests),
+                                )
+                            }
+                            None => (
+                                content_type.unwrap_or_else(|| {
+                                    list.media_type_or(MediaType::OciImageIndex).to_string()
+                                }),
+                                body,
+                                Lock::List(manifests),
+                            ),
+                        }
+                    }
+                    ManifestResponse::Schema1(manifest) => {
+                        // Schema 1 does not record blob sizes.
+                        for layer in &manifest.fs_layers {
+                            self.copy_blob(spec, operation, &layer.blob_sum, 0).await?;
+                        }
+                        // Schema 1 manifests are copied as is.
+                        let content_type = content_type
+                            .unwrap_or_else(|| MediaType::DockerManifestV1Signed.to_string());
+                        (content_type, body, Lock::List(None))
+                    }
+                    ManifestResponse::Raw(raw) => {
+                        return Err(ErrorResponse::unsupported_media_type(
+                            raw.media_type().to_string(),
+                        ))
+                    }
+                };
+
+            let size = body.len();
+            let digest = Digest::from_sha256(Sha256::digest(&body)).to_string();
+            let digest = self
+                .destination
+                .push_manifest_raw(
+                    &spec.destination,
+                    destination_reference.unwrap_or(&digest),
+                    &content_type,
+                    body,
+                )
+                .await?;
+            let manifests = match lock {
+                Lock::Image(manifest) => {
+                    Some(vec![LockedManifest::new(digest.clone(), None, &manifest)])
+                }
+                Lock::List(manifests) => manifests,
+            };
+            Ok(Copied {
+                digest,
+                size,
+                media_type: content_type.into(),
+                manifests,
+            })
+        }
+        .boxed()
+    }
+
+    async fn copy_blob(
+        &self,
+        spec: &CopySpec,
+        operation: &Operation,
+        digest: &Digest,
+        size: usize,
+    ) -> Result<(), ErrorResponse> {
+        let copied_to = self
+            .blobs
+            .lock()
+            .unwrap()
+            .entry(digest.clone())
+            .or_default()
+            .clone();
+        // Concurrent copies of the same blob wait for the first one.
+        let mut copied_to = copied_to.lock().await;
+
+        if self
+            .destination
+            .blob_exists(&spec.destination, digest)
+            .await?
+        {
+            copied_to.get_or_insert_with(|| spec.destination.clone());
+            operation.cache_hit();
+            self.report(|progress| progress.blobs_reused += 1);
+            return Ok(());
+        }
+
+        if let Some(from) = copied_to.as_deref() {
+            if self
+                .destination
+                .mount_blob(&spec.destination, digest, from)
+                .await?
+            {
+                operation.cache_hit();
+                self.report(|progress| progress.blobs_reused += 1);
+                return Ok(());
+            }
+        }
+
+        let blob = self.source.blob(&spec.source, digest).await?;
+        let size = match size {
+            0 => blob.len().unwrap_or_default(),
+            size => size,
+        };
+        // A throttled upload downloads the blob again from the source.
+        let mut blob = Some(blob);
+        self.destination
+            .upload_blob(&spec.destination, digest, size, || {
+                let blob = blob.take();
+                async move {
+                    let blob = match blob {
+                        Some(blob) => blob,
+                        None => self.source.blob(&spec.source, digest).await?,
+                    };
+                    Ok(blob.into_verified_body(
+                        digest.clone(),
+                        self.source.verification,
+                        self.destination.bandwidth.upload.as_ref(),
+                    ))
+                }
+            })
+            .await?;
+        *copied_to = Some(spec.destination.clone());
+        operation.transferred(size as u64);
+
+        self.report(|progress| {
+            progress.blobs_copied += 1;
+            progress.bytes_copied += size as u64;
+        });
+        Ok(())
+    }
+}
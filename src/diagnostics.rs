@@ -0,0 +1,167 @@
+//! Introspection of the client internal state.
+//!
+//! [`DockerRegistryClientV2::diagnostics`] returns a snapshot of the cached
+//! tokens and of the requests in progress or waiting for a slot (see
+//! [`crate::scheduler`]), to monitor clients embedded in long running
+//! services. [`Diagnostics::to_prometheus`] renders it in the Prometheus
+//! text format.
+//!
+//! The connection pool of the underlying HTTP client is not observable,
+//! requests in progress are the closest measure of open connections.
+//!
+//! ```no_run
+//! # use oci_registry_client::DockerRegistryClientV2;
+//! # fn example(client: DockerRegistryClientV2) {
+//! let diagnostics = client.diagnostics();
+//! for token in diagnostics.tokens.iter().filter(|token| token.expired) {
+//!     println!("expired token for {}", token.scope);
+//! }
+//! print!("{}", diagnostics.to_prometheus());
+//! # }
+//! ```
+
+use crate::{scheduler::SchedulerState, DockerRegistryClientV2};
+use std::{
+    fmt::Write,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Snapshot of the client internal state.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostics {
+    /// Registry API URL.
+    pub registry: String,
+    /// Tokens cached per service and scope.
+    pub tokens: Vec<CachedToken>,
+    /// Requests in progress and waiting, shared by clones of the client.
+    pub requests: SchedulerState,
+}
+
+/// A cached token, without its secret value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachedToken {
+    pub service: String,
+    pub scope: String,
+    /// Expiry of the token, `None` if it does not expire.
+    pub expires_at: Option<SystemTime>,
+    /// `true` if the token is expired (or about to expire), it is replaced
+    /// on the next request for its scope.
+    pub expired: bool,
+}
+
+impl Diagnostics {
+    /// Render the snapshot in the Prometheus text exposition format.
+    ///
+    /// ```
+    /// # use oci_registry_client::{diagnostics::Diagnostics, scheduler::SchedulerState};
+    /// let diagnostics = Diagnostics {
+    ///     registry: "https://registry-1.docker.io".to_owned(),
+    ///     tokens: vec![],
+    ///     requests: SchedulerState {
+    ///         in_progress: 2,
+    ///         ..Default::default()
+    ///     },
+    /// };
+    /// assert!(diagnostics.to_prometheus().contains(
+    ///     "oci_registry_client_requests_in_progress{registry=\"https://registry-1.docker.io\"} 2\n"
+    /// ));
+    /// ```
+    pub fn to_prometheus(&self) -> String {
+        let registry = format!("registry=\"{}\"", escape(&self.registry));
+        let mut out = String::new();
+
+        let _ = writeln!(
+            out,
+            "# HELP oci_registry_client_tokens Cached registry tokens.\n\
+             # TYPE oci_registry_client_tokens gauge\n\
+             oci_registry_client_tokens{{{},state=\"valid\"}} {}\n\
+             oci_registry_client_tokens{{{},state=\"expired\"}} {}",
+            registry,
+            self.tokens.iter().filter(|token| !token.expired).count(),
+            registry,
+            self.tokens.iter().filter(|token| token.expired).count(),
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP oci_registry_client_token_expiry_seconds Expiry of cached tokens, as a Unix timestamp.\n\
+             # TYPE oci_registry_client_token_expiry_seconds gauge"
+        );
+        for token in &self.tokens {
+            if let Some(expires_at) = token.expires_at {
+                let expires_at = expires_at
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                let _ = writeln!(
+                    out,
+                    "oci_registry_client_token_expiry_seconds{{{},service=\"{}\",scope=\"{}\"}} {}",
+                    registry,
+                    escape(&token.service),
+                    escape(&token.scope),
+                    expires_at,
+                );
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP oci_registry_client_requests_in_progress Requests holding a slot.\n\
+             # TYPE oci_registry_client_requests_in_progress gauge\n\
+             oci_registry_client_requests_in_progress{{{}}} {}",
+            registry, self.requests.in_progress,
+        );
+        let _ = writeln!(
+            out,
+            "# HELP oci_registry_client_requests_queued Requests waiting for a slot.\n\
+             # TYPE oci_registry_client_requests_queued gauge\n\
+             oci_registry_client_requests_queued{{{},priority=\"high\"}} {}\n\
+             oci_registry_client_requests_queued{{{},priority=\"low\"}} {}",
+            registry, self.requests.queued_high, registry, self.requests.queued_low,
+        );
+        if let Some(max) = self.requests.max_concurrent_requests {
+            let _ = writeln!(
+                out,
+                "# HELP oci_registry_client_requests_max Maximum number of requests in progress.\n\
+                 # TYPE oci_registry_client_requests_max gauge\n\
+                 oci_registry_client_requests_max{{{}}} {}",
+                registry, max,
+            );
+        }
+
+        out
+    }
+}
+
+/// Escape a Prometheus label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl DockerRegistryClientV2 {
+    /// Returns a snapshot of the cached tokens and of the requests in
+    /// progress, see [`crate::diagnostics`].
+    pub fn diagnostics(&self) -> Diagnostics {
+        let mut tokens: Vec<_> = self
+            .token_cache
+            .entries()
+            .into_iter()
+            .map(|(service, scope, token)| CachedToken {
+                service,
+                scope,
+                expires_at: token.expires_at(),
+                expired: token.is_expired(),
+            })
+            .collect();
+        tokens.sort_by(|a, b| (&a.service, &a.scope).cmp(&(&b.service, &b.scope)));
+
+        Diagnostics {
+            registry: self.api_url.clone(),
+            tokens,
+            requests: self.scheduler.state(),
+        }
+    }
+}
@@ -1,7 +1,15 @@
 //! Error representation.
 
-use reqwest;
-use std::fmt;
+use crate::{
+    auth::Challenge, manifest::Digest, push::MissingReference, reference::ParseReferenceError,
+    retry, timeout::TimeoutPhase,
+};
+use bytes::Bytes;
+use reqwest::{self, header::HeaderMap, Method, StatusCode};
+use std::{borrow::Cow, fmt, str, time::Duration};
+
+/// Maximum number of characters of an unexpected body kept in errors.
+const SNIPPET_LEN: usize = 200;
 
 /// A list of errors.
 #[derive(serde::Deserialize, Debug)]
@@ -9,18 +17,179 @@ pub struct ErrorList {
     errors: Vec<Error>,
 }
 
+impl ErrorList {
+    /// Returns the errors of the list.
+    pub fn errors(&self) -> &[Error] {
+        &self.errors
+    }
+
+    /// Returns an iterator over the errors of the list.
+    pub fn iter(&self) -> std::slice::Iter<'_, Error> {
+        self.errors.iter()
+    }
+
+    /// Returns `true` if the list has an error with `code`.
+    pub fn contains(&self, code: &ErrorCode) -> bool {
+        self.errors.iter().any(|error| &error.code == code)
+    }
+}
+
+impl<'a> IntoIterator for &'a ErrorList {
+    type Item = &'a Error;
+    type IntoIter = std::slice::Iter<'a, Error>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
 /// An error.
 ///
 /// Represents an error returned by Image Registry API.
-#[allow(dead_code)]
 #[derive(serde::Deserialize, Debug)]
 pub struct Error {
-    code: String,
+    code: ErrorCode,
     message: String,
     #[serde(default)]
     detail: serde_json::Value,
 }
 
+impl Error {
+    /// Returns the code of the error.
+    pub fn code(&self) -> &ErrorCode {
+        &self.code
+    }
+
+    /// Returns the message of the error.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns the unstructured details of the error (`null` if none).
+    pub fn detail(&self) -> &serde_json::Value {
+        &self.detail
+    }
+}
+
+/// Code of an error returned by Image Registry API.
+///
+/// Codes of the distribution specification are parsed to their own variant,
+/// any other one is kept as [`ErrorCode::Unknown`].
+///
+/// ```
+/// # use oci_registry_client::errors::ErrorCode;
+/// assert_eq!(ErrorCode::from("MANIFEST_UNKNOWN"), ErrorCode::ManifestUnknown);
+/// assert_eq!(ErrorCode::from("QUOTA_EXCEEDED"), ErrorCode::Unknown("QUOTA_EXCEEDED".to_owned()));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorCode {
+    /// `BLOB_UNKNOWN`: blob unknown to registry.
+    BlobUnknown,
+    /// `BLOB_UPLOAD_INVALID`: blob upload invalid.
+    BlobUploadInvalid,
+    /// `BLOB_UPLOAD_UNKNOWN`: blob upload unknown to registry.
+    BlobUploadUnknown,
+    /// `DIGEST_INVALID`: provided digest did not match uploaded content.
+    DigestInvalid,
+    /// `MANIFEST_BLOB_UNKNOWN`: manifest references a manifest or blob
+    /// unknown to registry.
+    ManifestBlobUnknown,
+    /// `MANIFEST_INVALID`: manifest invalid.
+    ManifestInvalid,
+    /// `MANIFEST_UNKNOWN`: manifest unknown to registry.
+    ManifestUnknown,
+    /// `NAME_INVALID`: invalid repository name.
+    NameInvalid,
+    /// `NAME_UNKNOWN`: repository name not known to registry.
+    NameUnknown,
+    /// `SIZE_INVALID`: provided length did not match content length.
+    SizeInvalid,
+    /// `UNAUTHORIZED`: authentication required.
+    Unauthorized,
+    /// `DENIED`: requested access to the resource is denied.
+    Denied,
+    /// `UNSUPPORTED`: the operation is unsupported.
+    Unsupported,
+    /// `TOOMANYREQUESTS`: too many requests.
+    TooManyRequests,
+    /// Any other code.
+    Unknown(String),
+}
+
+impl ErrorCode {
+    /// Returns the string form of this code.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::BlobUnknown => "BLOB_UNKNOWN",
+            Self::BlobUploadInvalid => "BLOB_UPLOAD_INVALID",
+            Self::BlobUploadUnknown => "BLOB_UPLOAD_UNKNOWN",
+            Self::DigestInvalid => "DIGEST_INVALID",
+            Self::ManifestBlobUnknown => "MANIFEST_BLOB_UNKNOWN",
+            Self::ManifestInvalid => "MANIFEST_INVALID",
+            Self::ManifestUnknown => "MANIFEST_UNKNOWN",
+            Self::NameInvalid => "NAME_INVALID",
+            Self::NameUnknown => "NAME_UNKNOWN",
+            Self::SizeInvalid => "SIZE_INVALID",
+            Self::Unauthorized => "UNAUTHORIZED",
+            Self::Denied => "DENIED",
+            Self::Unsupported => "UNSUPPORTED",
+            Self::TooManyRequests => "TOOMANYREQUESTS",
+            Self::Unknown(code) => code,
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl str::FromStr for ErrorCode {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const KNOWN: [ErrorCode; 14] = [
+            ErrorCode::BlobUnknown,
+            ErrorCode::BlobUploadInvalid,
+            ErrorCode::BlobUploadUnknown,
+            ErrorCode::DigestInvalid,
+            ErrorCode::ManifestBlobUnknown,
+            ErrorCode::ManifestInvalid,
+            ErrorCode::ManifestUnknown,
+            ErrorCode::NameInvalid,
+            ErrorCode::NameUnknown,
+            ErrorCode::SizeInvalid,
+            ErrorCode::Unauthorized,
+            ErrorCode::Denied,
+            ErrorCode::Unsupported,
+            ErrorCode::TooManyRequests,
+        ];
+
+        Ok(KNOWN
+            .into_iter()
+            .find(|code| code.as_str() == s)
+            .unwrap_or_else(|| ErrorCode::Unknown(s.to_owned())))
+    }
+}
+
+impl From<&str> for ErrorCode {
+    fn from(code: &str) -> Self {
+        let Ok(code) = code.parse();
+        code
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for ErrorCode {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Ok(String::deserialize(deserializer)?.as_str().into())
+    }
+}
+
 /// Details about an error.
 #[allow(dead_code)]
 #[derive(serde::Deserialize, Debug)]
@@ -32,35 +201,668 @@ pub struct ErrorDetail {
     action: String,
 }
 
+/// Status, headers and body of an unsuccessful registry response, with the
+/// request it answered.
+#[derive(Clone, Debug)]
+pub struct ResponseDetails {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    request: Option<ErrorContext>,
+}
+
+impl ResponseDetails {
+    /// Read the details of `response`, consuming its body.
+    async fn read(response: reqwest::Response) -> Self {
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes().await.unwrap_or_default();
+        Self {
+            status,
+            headers,
+            body,
+            request: None,
+        }
+    }
+
+    /// Status of the response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Headers of the response.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Raw body of the response, empty if it could not be read.
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    /// Body of the response as text, invalid UTF-8 sequences replaced.
+    pub fn text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.body)
+    }
+
+    /// Request the response answered, if known.
+    pub fn request(&self) -> Option<&ErrorContext> {
+        self.request.as_ref()
+    }
+}
+
+/// Request an error occurred on: method, URL and the repository and
+/// reference (tag or digest) it was about.
+#[derive(Clone, Debug)]
+pub struct ErrorContext {
+    method: Method,
+    url: String,
+    repository: Option<String>,
+    reference: Option<String>,
+}
+
+impl ErrorContext {
+    pub(crate) fn new(
+        method: Method,
+        url: &str,
+        repository: Option<&str>,
+        reference: Option<&str>,
+    ) -> Self {
+        Self {
+            method,
+            url: url.to_owned(),
+            repository: repository.map(str::to_owned),
+            reference: reference.map(str::to_owned),
+        }
+    }
+
+    /// Method of the request.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// URL of the request.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Repository the request was about, if any.
+    pub fn repository(&self) -> Option<&str> {
+        self.repository.as_deref()
+    }
+
+    /// Tag or digest the request was about, if any.
+    pub fn reference(&self) -> Option<&str> {
+        self.reference.as_deref()
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.method, self.url)?;
+        match (&self.repository, &self.reference) {
+            // Digests have an algorithm prefix, tags can't contain `:`.
+            (Some(repository), Some(reference)) if reference.contains(':') => {
+                write!(f, " ({}@{})", repository, reference)
+            }
+            (Some(repository), Some(reference)) => {
+                write!(f, " ({}:{})", repository, reference)
+            }
+            (Some(repository), None) => write!(f, " ({})", repository),
+            _ => Ok(()),
+        }
+    }
+}
+
 /// Error response
 ///
-/// `APIError` is returned when Image Registry API returns an error, otherwise
-/// `RequestError` is returned
-#[derive(Debug)]
+/// `Api` is returned when Image Registry API returns an error, `NotFound`
+/// when the requested repository, manifest or blob does not exist,
+/// `Unauthorized` when it rejects the credentials (or their scope), with the
+/// `WWW-Authenticate` challenge it answered with, `RateLimited` when it
+/// throttles requests (`429 Too Many Requests`, or `503 Service Unavailable`
+/// with a `Retry-After` header), with the delay it asks to wait,
+/// `UnexpectedHtmlResponse` when it returns an HTML page (typically a SSO
+/// login page or a captive portal) instead of an API response,
+/// `UnexpectedResponse` when an unsuccessful response has no error list
+/// (empty, plain text or malformed body),
+/// `UnsupportedMediaType` when it returns content of an unexpected media type,
+/// `DigestMismatch` when downloaded content does not match its digest, with
+/// the URL it was downloaded from,
+/// `SizeMismatch` when downloaded content does not match its size,
+/// `UnsupportedDigestAlgorithm` when content can't be verified because its
+/// digest uses an algorithm this build does not support,
+/// `MissingReferences` when a manifest references content missing from the
+/// repository it is pushed to,
+/// `PlatformNotFound` when a manifest list has no manifest for a platform,
+/// `BodyTooLarge` when a manifest or config exceeds the configured size limit,
+/// `Timeout` when a phase of a request exceeds its configured timeout,
+/// `InvalidReference` when an image reference can't be parsed,
+/// `DecodeError` when a response body can't be parsed, `IoError` when reading
+/// or writing local content fails, otherwise `Transport` is returned when
+/// the request can't be sent or its response read.
+///
+/// Errors built from a registry response keep its status, headers and body,
+/// see [`ErrorResponse::response`].
+///
+/// Errors occurring on a request (all but `InvalidReference`) also record
+/// the request that failed (see [`ErrorContext`] and
+/// [`ErrorResponse::context`]), which is part of their message. The
+/// underlying error of `Transport`, `IoError` and `DecodeError` is their
+/// [`std::error::Error::source`].
+///
+/// ```no_run
+/// # use oci_registry_client::{errors::ErrorResponse, DockerRegistryClientV2};
+/// # async fn example(client: DockerRegistryClientV2) -> Result<(), ErrorResponse> {
+/// match client.manifest("library/alpine", "3.99").await {
+///     Ok(manifest) => println!("{} layers", manifest.layers.len()),
+///     Err(ErrorResponse::NotFound { .. }) => println!("no such tag"),
+///     Err(err @ ErrorResponse::RateLimited { .. }) => {
+///         println!("retry in {:?}", err.retry_after());
+///     }
+///     Err(err) => return Err(err),
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, thiserror::Error)]
 pub enum ErrorResponse {
-    APIError(ErrorList),
-    RequestError(reqwest::Error),
+    #[error("API error (status {}){}:{errors}", response.status, request_suffix(response))]
+    Api {
+        errors: ErrorList,
+        response: Box<ResponseDetails>,
+    },
+    #[error(
+        "Not found (status {}){}{}",
+        response.status,
+        request_suffix(response),
+        errors_suffix(.errors)
+    )]
+    NotFound {
+        errors: Option<ErrorList>,
+        response: Box<ResponseDetails>,
+    },
+    #[error(
+        "Unauthorized (status {}){}{}{}",
+        response.status,
+        request_suffix(response),
+        challenge_suffix(.challenge),
+        errors_suffix(.errors)
+    )]
+    Unauthorized {
+        challenge: Option<Box<Challenge>>,
+        errors: Option<ErrorList>,
+        response: Box<ResponseDetails>,
+    },
+    #[error(
+        "Rate limited (status {}){}{}{}",
+        response.status,
+        request_suffix(response),
+        retry_after_suffix(.retry_after),
+        errors_suffix(.errors)
+    )]
+    RateLimited {
+        retry_after: Option<Duration>,
+        errors: Option<ErrorList>,
+        response: Box<ResponseDetails>,
+    },
+    #[error("Transport error{}", context_suffix(.context.as_deref()))]
+    Transport {
+        #[source]
+        source: reqwest::Error,
+        context: Option<Box<ErrorContext>>,
+    },
+    #[error("I/O error{}", context_suffix(.context.as_deref()))]
+    IoError {
+        #[source]
+        source: std::io::Error,
+        context: Option<Box<ErrorContext>>,
+    },
+    #[error("Decode error{}", context_suffix(.context.as_deref()))]
+    DecodeError {
+        #[source]
+        source: serde_json::Error,
+        context: Option<Box<ErrorContext>>,
+    },
+    #[error(
+        "Unexpected HTML response (status {}){}: the registry may require an \
+         interactive (SSO) login or a proxy is intercepting requests\n  {snippet}",
+        response.status,
+        request_suffix(response)
+    )]
+    UnexpectedHtmlResponse {
+        snippet: String,
+        response: Box<ResponseDetails>,
+    },
+    #[error(
+        "Unexpected response (status {}){}{}",
+        .0.status,
+        request_suffix(.0),
+        body_suffix(.0)
+    )]
+    UnexpectedResponse(Box<ResponseDetails>),
+    #[error(
+        "Unsupported media type: {media_type}{}",
+        context_suffix(.context.as_deref())
+    )]
+    UnsupportedMediaType {
+        media_type: String,
+        context: Option<Box<ErrorContext>>,
+    },
+    #[error(
+        "Digest mismatch: expected \"{expected}\", got \"{actual}\"{}{}",
+        url_suffix(.url, .context.as_deref()),
+        context_suffix(.context.as_deref())
+    )]
+    DigestMismatch {
+        expected: Digest,
+        actual: Digest,
+        url: Option<Box<reqwest::Url>>,
+        context: Option<Box<ErrorContext>>,
+    },
+    #[error(
+        "Size mismatch: expected {expected} bytes, got {actual}{}",
+        context_suffix(.context.as_deref())
+    )]
+    SizeMismatch {
+        expected: u64,
+        actual: u64,
+        context: Option<Box<ErrorContext>>,
+    },
+    #[error(
+        "Unsupported digest algorithm: {algorithm}{}",
+        context_suffix(.context.as_deref())
+    )]
+    UnsupportedDigestAlgorithm {
+        algorithm: String,
+        context: Option<Box<ErrorContext>>,
+    },
+    #[error(
+        "Manifest references missing content{}:{}",
+        context_suffix(.context.as_deref()),
+        missing_list(.missing)
+    )]
+    MissingReferences {
+        missing: Vec<MissingReference>,
+        context: Option<Box<ErrorContext>>,
+    },
+    #[error(
+        "No manifest found for platform {platform}{}",
+        context_suffix(.context.as_deref())
+    )]
+    PlatformNotFound {
+        platform: String,
+        context: Option<Box<ErrorContext>>,
+    },
+    #[error(
+        "Response body exceeds the limit of {limit} bytes{}",
+        context_suffix(.context.as_deref())
+    )]
+    BodyTooLarge {
+        limit: u64,
+        context: Option<Box<ErrorContext>>,
+    },
+    #[error("Request timed out ({phase}){}", context_suffix(.context.as_deref()))]
+    Timeout {
+        phase: TimeoutPhase,
+        /// The reqwest error, for connect and total timeouts.
+        #[source]
+        source: Option<reqwest::Error>,
+        context: Option<Box<ErrorContext>>,
+    },
+    #[error(transparent)]
+    InvalidReference(#[from] ParseReferenceError),
 }
 
-impl std::fmt::Display for ErrorResponse {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl ErrorResponse {
+    /// Returns the delay the registry asked to wait before retrying, for
+    /// `RateLimited` errors.
+    pub fn retry_after(&self) -> Option<Duration> {
         match self {
-            Self::APIError(err) => {
-                write!(f, "API error:")?;
-                for e in err.errors.iter() {
-                    write!(f, "\n  {}: {}", e.code, e.message)?;
-                }
-                Ok(())
+            Self::RateLimited { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// Returns the status, headers and body of the registry response the
+    /// error was built from, if any.
+    pub fn response(&self) -> Option<&ResponseDetails> {
+        match self {
+            Self::Api { response, .. }
+            | Self::NotFound { response, .. }
+            | Self::Unauthorized { response, .. }
+            | Self::RateLimited { response, .. }
+            | Self::UnexpectedHtmlResponse { response, .. }
+            | Self::UnexpectedResponse(response) => Some(response),
+            _ => None,
+        }
+    }
+
+    /// Returns the errors listed in the registry response the error was
+    /// built from, if any.
+    ///
+    /// ```no_run
+    /// # use oci_registry_client::{errors::ErrorCode, DockerRegistryClientV2};
+    /// # async fn example(client: DockerRegistryClientV2) {
+    /// if let Err(err) = client.manifest("library/alpine", "3.99").await {
+    ///     if err.errors().is_some_and(|errors| errors.contains(&ErrorCode::ManifestUnknown)) {
+    ///         println!("no such tag");
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub fn errors(&self) -> Option<&ErrorList> {
+        match self {
+            Self::Api { errors, .. } => Some(errors),
+            Self::NotFound { errors, .. }
+            | Self::Unauthorized { errors, .. }
+            | Self::RateLimited { errors, .. } => errors.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Returns the request the error occurred on, if known.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self.context_slot() {
+            Some(context) => context.as_deref(),
+            None => self.response().and_then(ResponseDetails::request),
+        }
+    }
+
+    /// Returns a `UnsupportedMediaType` error, its context is recorded by the caller.
+    pub(crate) fn unsupported_media_type(media_type: String) -> Self {
+        Self::UnsupportedMediaType {
+            media_type,
+            context: None,
+        }
+    }
+
+    /// Returns a `SizeMismatch` error, its context is recorded by the caller.
+    pub(crate) fn size_mismatch(expected: u64, actual: u64) -> Self {
+        Self::SizeMismatch {
+            expected,
+            actual,
+            context: None,
+        }
+    }
+
+    /// Returns a `PlatformNotFound` error, its context is recorded by the caller.
+    pub(crate) fn platform_not_found(platform: String) -> Self {
+        Self::PlatformNotFound {
+            platform,
+            context: None,
+        }
+    }
+
+    /// Returns a `BodyTooLarge` error, its context is recorded by the caller.
+    pub(crate) fn body_too_large(limit: u64) -> Self {
+        Self::BodyTooLarge {
+            limit,
+            context: None,
+        }
+    }
+
+    /// Returns a `Timeout` error, its context is recorded by the caller.
+    pub(crate) fn timeout(phase: TimeoutPhase) -> Self {
+        Self::Timeout {
+            phase,
+            source: None,
+            context: None,
+        }
+    }
+
+    /// Returns the context of the errors not built from a registry
+    /// response.
+    fn context_slot(&self) -> Option<&Option<Box<ErrorContext>>> {
+        match self {
+            Self::Transport { context, .. }
+            | Self::IoError { context, .. }
+            | Self::DecodeError { context, .. }
+            | Self::UnsupportedMediaType { context, .. }
+            | Self::DigestMismatch { context, .. }
+            | Self::SizeMismatch { context, .. }
+            | Self::UnsupportedDigestAlgorithm { context, .. }
+            | Self::MissingReferences { context, .. }
+            | Self::PlatformNotFound { context, .. }
+            | Self::BodyTooLarge { context, .. }
+            | Self::Timeout { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Record that the error occurred on the request `context`, unless it
+    /// already has one.
+    pub(crate) fn with_context(mut self, context: ErrorContext) -> Self {
+        match &mut self {
+            Self::Api { response, .. }
+            | Self::NotFound { response, .. }
+            | Self::Unauthorized { response, .. }
+            | Self::RateLimited { response, .. }
+            | Self::UnexpectedHtmlResponse { response, .. }
+            | Self::UnexpectedResponse(response) => {
+                response.request.get_or_insert(context);
             }
-            Self::RequestError(err) => write!(f, "Request error: {}", err),
+            Self::Transport { context: slot, .. }
+            | Self::IoError { context: slot, .. }
+            | Self::DecodeError { context: slot, .. }
+            | Self::UnsupportedMediaType { context: slot, .. }
+            | Self::DigestMismatch { context: slot, .. }
+            | Self::SizeMismatch { context: slot, .. }
+            | Self::UnsupportedDigestAlgorithm { context: slot, .. }
+            | Self::MissingReferences { context: slot, .. }
+            | Self::PlatformNotFound { context: slot, .. }
+            | Self::BodyTooLarge { context: slot, .. }
+            | Self::Timeout { context: slot, .. } => {
+                slot.get_or_insert_with(|| Box::new(context));
+            }
+            Self::InvalidReference(_) => {}
+        }
+        self
+    }
+
+    /// Record that the content of a `DigestMismatch` error was downloaded
+    /// from `url`.
+    pub(crate) fn with_url(self, url: Option<&reqwest::Url>) -> Self {
+        match self {
+            Self::DigestMismatch {
+                expected,
+                actual,
+                url: None,
+                context,
+            } => Self::DigestMismatch {
+                expected,
+                actual,
+                url: url.cloned().map(Box::new),
+                context,
+            },
+            err => err,
+        }
+    }
+
+    /// Returns the status of the registry response the error was built
+    /// from, if any.
+    pub fn status(&self) -> Option<StatusCode> {
+        self.response().map(ResponseDetails::status)
+    }
+
+    /// Build an error from an unsuccessful registry response.
+    ///
+    /// The status, headers and body are read before the body is parsed, so
+    /// they are kept whatever the body holds.
+    pub(crate) async fn from_response(response: reqwest::Response) -> Self {
+        let retry_after = retry::retry_after(&response);
+        let response = Box::new(ResponseDetails::read(response).await);
+        let status = response.status;
+        let errors = serde_json::from_slice::<ErrorList>(&response.body);
+
+        if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            let challenge = response
+                .headers
+                .get(reqwest::header::WWW_AUTHENTICATE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(Challenge::parse)
+                .map(Box::new);
+            return Self::Unauthorized {
+                challenge,
+                errors: errors.ok(),
+                response,
+            };
+        }
+
+        if status == StatusCode::TOO_MANY_REQUESTS
+            || (status == StatusCode::SERVICE_UNAVAILABLE && retry_after.is_some())
+        {
+            return Self::RateLimited {
+                retry_after,
+                errors: errors.ok(),
+                response,
+            };
+        }
+
+        if status == StatusCode::NOT_FOUND {
+            return Self::NotFound {
+                errors: errors.ok(),
+                response,
+            };
+        }
+
+        if is_html(&response.headers) {
+            return Self::UnexpectedHtmlResponse {
+                snippet: response.text().chars().take(SNIPPET_LEN).collect(),
+                response,
+            };
+        }
+
+        match errors {
+            Ok(errors) => Self::Api { errors, response },
+            Err(_) => Self::UnexpectedResponse(response),
         }
     }
 }
 
-impl std::error::Error for ErrorResponse {}
+/// Returns an `UnexpectedHtmlResponse` error if `response` is an HTML page.
+pub(crate) async fn reject_html(
+    response: reqwest::Response,
+) -> Result<reqwest::Response, ErrorResponse> {
+    if !is_html(response.headers()) {
+        return Ok(response);
+    }
+
+    let response = Box::new(ResponseDetails::read(response).await);
+    Err(ErrorResponse::UnexpectedHtmlResponse {
+        snippet: response.text().chars().take(SNIPPET_LEN).collect(),
+        response,
+    })
+}
+
+/// Returns `true` if `headers` announce an HTML body.
+fn is_html(headers: &HeaderMap) -> bool {
+    headers
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim_start().starts_with("text/html"))
+        .unwrap_or(false)
+}
+
+impl fmt::Display for ErrorList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for e in self.errors.iter() {
+            write!(f, "\n  {}: {}", e.code, e.message)?;
+        }
+        Ok(())
+    }
+}
+
+fn errors_suffix(errors: &Option<ErrorList>) -> String {
+    errors.as_ref().map(ToString::to_string).unwrap_or_default()
+}
+
+fn challenge_suffix(challenge: &Option<Box<Challenge>>) -> String {
+    match challenge {
+        Some(challenge) => format!(": registry requested {}", challenge),
+        None => String::new(),
+    }
+}
+
+fn retry_after_suffix(retry_after: &Option<Duration>) -> String {
+    match retry_after {
+        Some(retry_after) => format!(": retry after {}s", retry_after.as_secs()),
+        None => String::new(),
+    }
+}
+
+fn url_suffix(url: &Option<Box<reqwest::Url>>, context: Option<&ErrorContext>) -> String {
+    match url {
+        // The URL of the request is part of its context.
+        Some(url) if context.is_some_and(|context| context.url == url.as_str()) => String::new(),
+        Some(url) => format!(" (from {})", url),
+        None => String::new(),
+    }
+}
+
+fn request_suffix(response: &ResponseDetails) -> String {
+    context_suffix(response.request.as_ref())
+}
+
+fn context_suffix(context: Option<&ErrorContext>) -> String {
+    match context {
+        Some(context) => format!(" for {}", context),
+        None => String::new(),
+    }
+}
+
+fn body_suffix(response: &ResponseDetails) -> String {
+    match response.text().trim() {
+        "" => String::new(),
+        body => format!(": {}", body.chars().take(SNIPPET_LEN).collect::<String>()),
+    }
+}
+
+fn missing_list(missing: &[MissingReference]) -> String {
+    missing
+        .iter()
+        .map(|reference| match reference {
+            MissingReference::Blob(digest) => format!("\n  blob {}", digest),
+            MissingReference::Manifest(digest) => format!("\n  manifest {}", digest),
+        })
+        .collect()
+}
+
+impl From<serde_json::Error> for ErrorResponse {
+    fn from(error: serde_json::Error) -> Self {
+        ErrorResponse::DecodeError {
+            source: error,
+            context: None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ErrorResponse {
+    fn from(error: std::io::Error) -> Self {
+        ErrorResponse::IoError {
+            source: error,
+            context: None,
+        }
+    }
+}
 
 impl From<reqwest::Error> for ErrorResponse {
     fn from(error: reqwest::Error) -> Self {
-        ErrorResponse::RequestError(error)
+        // reqwest only times out on the connect and total timeouts.
+        let phase = match (error.is_timeout(), error.is_connect()) {
+            (true, true) => TimeoutPhase::Connect,
+            (true, false) => TimeoutPhase::Total,
+            _ => {
+                return ErrorResponse::Transport {
+                    source: error,
+                    context: None,
+                }
+            }
+        };
+        ErrorResponse::Timeout {
+            phase,
+            source: Some(error),
+            context: None,
+        }
     }
 }
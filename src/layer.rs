@@ -0,0 +1,610 @@
+//! Layer contents.
+//!
+//! [`DockerRegistryClientV2::layer_entries`] lists the files of a layer as
+//! it downloads, without storing it. The layer is checked against its digest
+//! once read to the end, the last item of the stream is the error if it does
+//! not match. Dropping the stream stops the download, so scanners looking
+//! for a given file can stop early (without verifying the layer):
+//!
+//! ```no_run
+//! use futures_util::StreamExt;
+//! # use oci_registry_client::{manifest::Digest, DockerRegistryClientV2};
+//! # async fn example(client: DockerRegistryClientV2, digest: Digest) -> Result<(), Box<dyn std::error::Error>> {
+//! let entries = client.layer_entries("library/alpine", &digest).await?;
+//! futures_util::pin_mut!(entries);
+//!
+//! while let Some(entry) = entries.next().await {
+//!     if entry?.path == "etc/alpine-release" {
+//!         println!("found it");
+//!         break;
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`DockerRegistryClientV2::extract_file`] reads a single file of an image,
+//! as seen in its flattened root filesystem: layers are searched from the
+//! top-most one and whiteouts (`.wh.<name>` and `.wh..wh..opq` files) hide
+//! files of the layers below.
+
+use crate::{
+    blob,
+    errors::ErrorResponse,
+    manifest::{host_architecture, Digest},
+    reference::{AsRepository, AsTagOrDigest},
+    DockerRegistryClientV2,
+};
+use async_compression::tokio::bufread::GzipDecoder;
+use bytes::Bytes;
+use futures_util::stream::{self, Stream, StreamExt};
+use std::{
+    io::{self, Read},
+    pin::Pin,
+};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncRead},
+    sync::mpsc,
+};
+use tokio_util::io::SyncIoBridge;
+
+pub(crate) const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Kind of a [`TarEntry`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    Hardlink,
+    CharDevice,
+    BlockDevice,
+    Fifo,
+    /// Any other entry type, with its type flag.
+    Other(u8),
+}
+
+impl EntryKind {
+    fn from_flag(flag: u8) -> Self {
+        match flag {
+            b'0' | b'\0' | b'7' => Self::File,
+            b'1' => Self::Hardlink,
+            b'2' => Self::Symlink,
+            b'3' => Self::CharDevice,
+            b'4' => Self::BlockDevice,
+            b'5' => Self::Directory,
+            b'6' => Self::Fifo,
+            flag => Self::Other(flag),
+        }
+    }
+}
+
+/// A file of a layer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TarEntry {
+    /// Path of the entry, relative to the root of the layer.
+    pub path: String,
+    pub kind: EntryKind,
+    pub size: u64,
+    pub mode: u32,
+    /// Target of symbolic and hard links.
+    pub link_name: Option<String>,
+    /// Contents of regular files, if requested.
+    pub contents: Option<Bytes>,
+}
+
+impl DockerRegistryClientV2 {
+    /// Stream the entries of the layer identified by `digest`, as it
+    /// downloads. Gzip compressed and uncompressed layers are supported.
+    pub async fn layer_entries<I: AsRepository + ?Sized>(
+        &self,
+        image: &I,
+        digest: &Digest,
+    ) -> Result<impl Stream<Item = Result<TarEntry, ErrorResponse>>, ErrorResponse> {
+        let image = image.as_repository();
+        let blob = self.blob(image, digest).await?;
+        Ok(entries(blob.into_reader(), Contents::None).await?)
+    }
+
+    /// Stream the entries of the layer identified by `digest` together with
+    /// the contents of regular files.
+    pub async fn layer_entries_with_contents<I: AsRepository + ?Sized>(
+        &self,
+        image: &I,
+        digest: &Digest,
+    ) -> Result<impl Stream<Item = Result<TarEntry, ErrorResponse>>, ErrorResponse> {
+        let image = image.as_repository();
+        let blob = self.blob(image, digest).await?;
+        Ok(entries(blob.into_reader(), Contents::All).await?)
+    }
+
+    /// Returns the file at `path` in the image `reference`, with its
+    /// contents, or `None` if the image does not contain it.
+    ///
+    /// Layers are searched from the top-most one, respecting whiteouts, and
+    /// only downloaded until the layer holding the file (read to its end to
+    /// verify it) is found. For manifest lists the `linux` manifest of the
+    /// host architecture is used.
+    ///
+    /// ```no_run
+    /// # use oci_registry_client::DockerRegistryClientV2;
+    /// # async fn example(client: DockerRegistryClientV2) -> Result<(), Box<dyn std::error::Error>> {
+    /// if let Some(file) = client.extract_file("library/alpine", "latest", "/etc/os-release").await? {
+    ///     println!("{}", String::from_utf8_lossy(&file.contents.unwrap_or_default()));
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn extract_file<I: AsRepository + ?Sized, T: AsTagOrDigest + ?Sized>(
+        &self,
+        image: &I,
+        reference: &T,
+        path: &str,
+    ) -> Result<Option<TarEntry>, ErrorResponse> {
+        let image = image.as_repository();
+        let reference = &*reference.as_tag_or_digest();
+        let manifest = self
+            .manifest_for_platform(image, reference, "linux", host_architecture())
+            .await?;
+
+        for layer in manifest.layers.iter().rev() {
+            let blob = self.layer_blob(image, layer).await?;
+            match find_file(blob.into_reader(), path).await? {
+                Lookup::Found(entry) => return Ok(Some(*entry)),
+                Lookup::Deleted => return Ok(None),
+                Lookup::Missing => {}
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the file at `path` in the layer identified by `digest`, with
+    /// its contents.
+    ///
+    /// Whiteouts are not applied, a file deleted by this layer is not found.
+    pub async fn extract_file_from_layer<I: AsRepository + ?Sized>(
+        &self,
+        image: &I,
+        digest: &Digest,
+        path: &str,
+    ) -> Result<Option<TarEntry>, ErrorResponse> {
+        let image = image.as_repository();
+        let blob = self.blob(image, digest).await?;
+        match find_file(blob.into_reader(), path).await? {
+            Lookup::Found(entry) => Ok(Some(*entry)),
+            Lookup::Deleted | Lookup::Missing => Ok(None),
+        }
+    }
+}
+
+/// Prefix of whiteout files, marking a deleted file of a lower layer.
+const WHITEOUT_PREFIX: &str = ".wh.";
+/// Whiteout file hiding all the contents of its directory from lower layers.
+const WHITEOUT_OPAQUE: &str = ".wh..wh..opq";
+
+enum Lookup {
+    Found(Box<TarEntry>),
+    /// The file is deleted (or hidden) by this layer.
+    Deleted,
+    Missing,
+}
+
+/// Look for `path` in the layer `reader`.
+async fn find_file<R>(reader: R, path: &str) -> Result<Lookup, ErrorResponse>
+where
+    R: AsyncBufRead + Send + Unpin + 'static,
+{
+    let path = path.trim_start_matches('/').trim_start_matches("./");
+    let entries = entries(reader, Contents::Path(path.to_owned())).await?;
+    futures_util::pin_mut!(entries);
+
+    let mut lookup = None;
+    let mut opaque = false;
+    while let Some(entry) = entries.next().await {
+        let entry = entry?;
+        if lookup.is_some() {
+            // Read to the end to verify the contents.
+            continue;
+        }
+        let name = entry.path.trim_end_matches('/');
+        if name == path {
+            lookup = Some(Lookup::Found(Box::new(entry)));
+            continue;
+        }
+
+        let (dir, base) = name.rsplit_once('/').unwrap_or(("", name));
+        let within = |dir: &str| dir.is_empty() || path.starts_with(&format!("{}/", dir));
+        if base == WHITEOUT_OPAQUE {
+            // Files of this layer stay visible, keep looking.
+            opaque |= within(dir);
+        } else if let Some(deleted) = base.strip_prefix(WHITEOUT_PREFIX) {
+            let deleted = match dir {
+                "" => deleted.to_owned(),
+                dir => format!("{}/{}", dir, deleted),
+            };
+            if path == deleted || within(&deleted) {
+                lookup = Some(Lookup::Deleted);
+            }
+        }
+    }
+
+    Ok(lookup.unwrap_or(match opaque {
+        true => Lookup::Deleted,
+        false => Lookup::Missing,
+    }))
+}
+
+pub(crate) type Reader = Pin<Box<dyn AsyncRead + Send>>;
+
+/// Regular files whose contents are read by [`entries`].
+pub(crate) enum Contents {
+    None,
+    All,
+    Path(String),
+    Matching(Box<dyn Fn(&str) -> bool + Send>),
+}
+
+impl Contents {
+    fn includes(&self, path: &str) -> bool {
+        match self {
+            Self::None => false,
+            Self::All => true,
+            Self::Path(wanted) => wanted == path,
+            Self::Matching(wanted) => wanted(path),
+        }
+    }
+}
+
+/// Returns the contents of `reader`, decompressed if it is gzip
+/// compressed.
+pub(crate) async fn decompress<R>(mut reader: R) -> io::Result<Reader>
+where
+    R: AsyncBufRead + Send + Unpin + 'static,
+{
+    let head = reader.fill_buf().await?;
+    Ok(if head.starts_with(&GZIP_MAGIC) {
+        let mut decoder = GzipDecoder::new(reader);
+        decoder.multiple_members(true);
+        Box::pin(decoder)
+    } else {
+        Box::pin(reader)
+    })
+}
+
+/// Returns the entries of the (possibly gzip compressed) tar stream `reader`.
+///
+/// The archive is read by a blocking task, which stops once the stream is
+/// dropped.
+pub(crate) async fn entries<R>(
+    reader: R,
+    contents: Contents,
+) -> io::Result<impl Stream<Item = Result<TarEntry, ErrorResponse>>>
+where
+    R: AsyncBufRead + Send + Unpin + 'static,
+{
+    let reader = SyncIoBridge::new(decompress(reader).await?);
+    let (sender, receiver) = mpsc::channel(1);
+    tokio::task::spawn_blocking(move || {
+        if let Err(err) = read_entries(reader, &contents, &sender) {
+            let _ = sender.blocking_send(Err(blob::into_error_response(err)));
+        }
+    });
+
+    Ok(stream::unfold(receiver, |mut receiver| async move {
+        let entry = receiver.recv().await?;
+        Some((entry, receiver))
+    }))
+}
+
+/// Send the entries of the tar stream `reader` to `sender`, until it is
+/// closed.
+fn read_entries<R: Read>(
+    reader: R,
+    contents: &Contents,
+    sender: &mpsc::Sender<Result<TarEntry, ErrorResponse>>,
+) -> io::Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = String::from_utf8_lossy(&entry.path_bytes())
+            .trim_start_matches("./")
+            .to_owned();
+        let kind = EntryKind::from_flag(entry.header().entry_type().as_byte());
+        let link_name = entry
+            .link_name_bytes()
+            .map(|name| String::from_utf8_lossy(&name).into_owned())
+            .filter(|name| !name.is_empty());
+
+        let data = match kind == EntryKind::File && contents.includes(&path) {
+            true => {
+                let mut data = Vec::new();
+                entry.read_to_end(&mut data)?;
+                Some(Bytes::from(data))
+            }
+            false => None,
+        };
+
+        let entry = TarEntry {
+            path,
+            kind,
+            size: entry.size(),
+            mode: entry.header().mode()?,
+            link_name,
+            contents: data,
+        };
+        if sender.blocking_send(Ok(entry)).is_err() {
+            return Ok(());
+        }
+    }
+
+    // Read to the end to verify the contents.
+    io::copy(&mut archive.into_inner(), &mut io::sink())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_compression::tokio::write::GzipEncoder;
+    use std::io::Cursor;
+    use tokio::io::AsyncWriteExt;
+
+    const BLOCK_SIZE: u64 = 512;
+
+    /// Returns a ustar header of an entry of `size` bytes.
+    fn header(path: &str, flag: u8, size: u64, link_name: &str) -> Vec<u8> {
+        let mut header = vec![0u8; BLOCK_SIZE as usize];
+        header[..path.len()].copy_from_slice(path.as_bytes());
+        header[100..108].copy_from_slice(b"0000644\0");
+        header[124..136].copy_from_slice(format!("{:011o}\0", size).as_bytes());
+        header[156] = flag;
+        header[157..157 + link_name.len()].copy_from_slice(link_name.as_bytes());
+        header[257..265].copy_from_slice(b"ustar\x0000");
+        checksummed(header)
+    }
+
+    /// Returns `header` with its checksum set.
+    fn checksummed(mut header: Vec<u8>) -> Vec<u8> {
+        header[148..156].copy_from_slice(b"        ");
+        let sum: u32 = header.iter().map(|b| u32::from(*b)).sum();
+        header[148..156].copy_from_slice(format!("{:06o}\0 ", sum).as_bytes());
+        header
+    }
+
+    /// Returns `data` padded to a whole number of blocks.
+    fn padded(data: &[u8]) -> Vec<u8> {
+        let mut data = data.to_vec();
+        data.resize(
+            data.len().div_ceil(BLOCK_SIZE as usize) * BLOCK_SIZE as usize,
+            0,
+        );
+        data
+    }
+
+    /// Returns a tarball of `entries` (headers and data), with its end
+    /// marker.
+    fn tarball(entries: &[Vec<u8>]) -> Vec<u8> {
+        let mut tar = entries.concat();
+        tar.extend([0u8; 2 * BLOCK_SIZE as usize]);
+        tar
+    }
+
+    fn file(path: &str, contents: &[u8]) -> Vec<u8> {
+        [
+            header(path, b'0', contents.len() as u64, ""),
+            padded(contents),
+        ]
+        .concat()
+    }
+
+    fn pax(records: &[(&str, &str)]) -> Vec<u8> {
+        let data: String = records
+            .iter()
+            .map(|(key, value)| {
+                let record = format!(" {}={}\n", key, value);
+                // The length counts its own digits.
+                let mut length = record.len();
+                while length != length.to_string().len() + record.len() {
+                    length += 1;
+                }
+                format!("{}{}", length, record)
+            })
+            .collect();
+        [
+            header("PaxHeaders/entry", b'x', data.len() as u64, ""),
+            padded(data.as_bytes()),
+        ]
+        .concat()
+    }
+
+    async fn read_entries(tar: Vec<u8>) -> Vec<TarEntry> {
+        let entries = entries(Cursor::new(tar), Contents::All).await.unwrap();
+        entries.map(|entry| entry.unwrap()).collect().await
+    }
+
+    async fn lookup(tar: Vec<u8>, path: &str) -> Lookup {
+        find_file(Cursor::new(tar), path).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn reads_ustar_entries() {
+        let mut prefixed = header("name.txt", b'0', 2, "");
+        prefixed[345..354].copy_from_slice(b"long/path");
+        let tar = tarball(&[
+            header("./etc/", b'5', 0, ""),
+            file("./etc/hosts", b"127.0.0.1 localhost\n"),
+            header("etc/localtime", b'2', 0, "/usr/share/zoneinfo/UTC"),
+            header("etc/hosts.bak", b'1', 0, "etc/hosts"),
+            checksummed(prefixed),
+            padded(b"ok"),
+        ]);
+
+        let entries = read_entries(tar).await;
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry.kind, entry.link_name.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("etc/", EntryKind::Directory, None),
+                ("etc/hosts", EntryKind::File, None),
+                (
+                    "etc/localtime",
+                    EntryKind::Symlink,
+                    Some("/usr/share/zoneinfo/UTC")
+                ),
+                ("etc/hosts.bak", EntryKind::Hardlink, Some("etc/hosts")),
+                ("long/path/name.txt", EntryKind::File, None),
+            ]
+        );
+        assert_eq!(entries[1].mode, 0o644);
+        assert_eq!(entries[1].size, 20);
+        assert_eq!(
+            entries[1].contents.as_deref(),
+            Some(&b"127.0.0.1 localhost\n"[..])
+        );
+        assert_eq!(entries[4].contents.as_deref(), Some(&b"ok"[..]));
+    }
+
+    #[tokio::test]
+    async fn applies_pax_headers() {
+        let path = format!("{}/file.txt", "d".repeat(150));
+        let tar = tarball(&[
+            pax(&[("path", &path), ("size", "5"), ("mtime", "1700000000.5")]),
+            // The size of the header is overridden by the PAX header.
+            header("truncated", b'0', 0, ""),
+            padded(b"hello"),
+            pax(&[("linkpath", "target/with/a/long/name")]),
+            header("link", b'2', 0, "short"),
+            file("after", b"next"),
+        ]);
+
+        let entries = read_entries(tar).await;
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].path, path);
+        assert_eq!(entries[0].size, 5);
+        assert_eq!(entries[0].contents.as_deref(), Some(&b"hello"[..]));
+        assert_eq!(
+            entries[1].link_name.as_deref(),
+            Some("target/with/a/long/name")
+        );
+        assert_eq!(entries[2].path, "after");
+        assert_eq!(entries[2].contents.as_deref(), Some(&b"next"[..]));
+    }
+
+    #[tokio::test]
+    async fn applies_gnu_long_names() {
+        let path = format!("{}/file.txt", "d".repeat(200));
+        let link_name = format!("{}/target", "t".repeat(200));
+        let tar = tarball(&[
+            header("././@LongLink", b'L', path.len() as u64 + 1, ""),
+            padded(format!("{}\0", path).as_bytes()),
+            file("truncated", b"long"),
+            header("././@LongLink", b'K', link_name.len() as u64, ""),
+            padded(link_name.as_bytes()),
+            header("link", b'1', 0, "truncated"),
+        ]);
+
+        let entries = read_entries(tar).await;
+        assert_eq!(entries[0].path, path);
+        assert_eq!(entries[0].contents.as_deref(), Some(&b"long"[..]));
+        assert_eq!(entries[1].path, "link");
+        assert_eq!(entries[1].link_name.as_deref(), Some(link_name.as_str()));
+    }
+
+    #[tokio::test]
+    async fn reads_base_256_sizes() {
+        let mut base_256 = header("big", b'0', 0, "");
+        base_256[124..136].copy_from_slice(&[0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x02, 0x01]);
+        let contents = vec![7u8; 0x201];
+        let tar = tarball(&[checksummed(base_256), padded(&contents), file("after", b"")]);
+
+        let entries = read_entries(tar).await;
+        assert_eq!(entries[0].size, 0x201);
+        assert_eq!(entries[0].contents.as_deref(), Some(&contents[..]));
+        assert_eq!(entries[1].path, "after");
+    }
+
+    #[tokio::test]
+    async fn reads_gzip_compressed_layers() {
+        let tar = tarball(&[file("etc/hostname", b"alpine\n")]);
+        let mut encoder = GzipEncoder::new(vec![]);
+        encoder.write_all(&tar).await.unwrap();
+        encoder.shutdown().await.unwrap();
+
+        let entries = read_entries(encoder.into_inner()).await;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].contents.as_deref(), Some(&b"alpine\n"[..]));
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_end_marker() {
+        let mut tar = tarball(&[file("a", b"a")]);
+        tar.extend(file("trailing", b"data"));
+
+        let entries = read_entries(tar).await;
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn finds_files() {
+        let tar = || {
+            tarball(&[
+                header("etc/", b'5', 0, ""),
+                file("etc/os-release", b"ID=alpine\n"),
+            ])
+        };
+
+        match lookup(tar(), "/etc/os-release").await {
+            Lookup::Found(entry) => {
+                assert_eq!(entry.contents.as_deref(), Some(&b"ID=alpine\n"[..]))
+            }
+            _ => panic!("etc/os-release not found"),
+        }
+        assert!(matches!(lookup(tar(), "etc/passwd").await, Lookup::Missing));
+        assert!(matches!(lookup(tar(), "etc").await, Lookup::Found(_)));
+    }
+
+    #[tokio::test]
+    async fn applies_whiteouts() {
+        let tar = tarball(&[
+            file("etc/.wh.passwd", b""),
+            file(".wh.var", b""),
+            file("etc/.wh.passwd-", b""),
+        ]);
+        assert!(matches!(
+            lookup(tar.clone(), "etc/passwd").await,
+            Lookup::Deleted
+        ));
+        // Deleted directories hide their contents.
+        assert!(matches!(
+            lookup(tar.clone(), "var/lib/apk").await,
+            Lookup::Deleted
+        ));
+        assert!(matches!(
+            lookup(tar.clone(), "etc/group").await,
+            Lookup::Missing
+        ));
+        assert!(matches!(lookup(tar, "etc/pass").await, Lookup::Missing));
+    }
+
+    #[tokio::test]
+    async fn applies_opaque_whiteouts() {
+        let tar = tarball(&[
+            file("etc/.wh..wh..opq", b""),
+            file("etc/hostname", b"new\n"),
+        ]);
+        // Files of the layer itself stay visible.
+        assert!(matches!(
+            lookup(tar.clone(), "etc/hostname").await,
+            Lookup::Found(_)
+        ));
+        assert!(matches!(
+            lookup(tar.clone(), "etc/passwd").await,
+            Lookup::Deleted
+        ));
+        assert!(matches!(lookup(tar, "usr/bin/env").await, Lookup::Missing));
+    }
+}
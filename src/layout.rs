@@ -0,0 +1,300 @@
+//! OCI image layouts.
+//!
+//! [`DockerRegistryClientV2::pull_image`] downloads an image into a
+//! directory following the [OCI image
+//! layout](https://github.com/opencontainers/image-spec/blob/main/image-layout.md),
+//! readable by `skopeo`, `podman`, `umoci` and other OCI tools:
+//!
+//! ```text
+//! <root>/oci-layout              layout version
+//! <root>/index.json              manifests of the layout, by tag
+//! <root>/blobs/sha256/<hash>     manifests, configs and layers
+//! ```
+//!
+//! ```no_run
+//! # use oci_registry_client::DockerRegistryClientV2;
+//! # async fn example(client: DockerRegistryClientV2) -> Result<(), Box<dyn std::error::Error>> {
+//! client
+//!     .pull_image("library/alpine", "3.19", "/tmp/alpine", None)
+//!     .await?;
+//! // skopeo copy oci:/tmp/alpine:3.19 docker-daemon:alpine:3.19
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Several images can be pulled into the same layout, concurrently or not,
+//! blobs already present are not downloaded again. Updates of `index.json`
+//! are serialized with a `index.json.lock` file.
+
+use crate::{
+    audit::Operation,
+    errors::ErrorResponse,
+    lockfile::{LockedImage, LockedManifest, LockedPlatform},
+    manifest::{
+        host_architecture, Descriptor, Digest, ImageIndex, ManifestResponse, MediaType, Platform,
+    },
+    reference::{AsRepository, AsTagOrDigest},
+    store::{self, ContentStore, StoreLayout},
+    DockerRegistryClientV2,
+};
+use futures_util::{stream, StreamExt, TryStreamExt};
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Annotation of `index.json` entries giving the tag of the manifest.
+pub const ANNOTATION_REF_NAME: &str = "org.opencontainers.image.ref.name";
+
+/// Maximum number of blobs downloaded at the same time by
+/// [`DockerRegistryClientV2::pull_image`].
+const MAX_CONCURRENT_BLOBS: usize = 4;
+
+/// Delay between attempts to take the lock of `index.json`.
+const LOCK_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// Age after which a lock of `index.json` is considered left behind by a
+/// crashed process, updates take milliseconds.
+const STALE_LOCK_AGE: Duration = Duration::from_secs(30);
+
+impl DockerRegistryClientV2 {
+    /// Download the image `reference` of `image` into the OCI image layout
+    /// at `path`, creating it if needed, see [`crate::layout`].
+    ///
+    /// If `reference` is a manifest list, the manifest of `platform` (by
+    /// default `linux` on the architecture of the host) is pulled. The
+    /// config and layers are downloaded concurrently and verified according
+    /// to the [`crate::verify::VerificationPolicy`] of the client, manifests
+    /// are stored as served so they keep their digest.
+    ///
+    /// The manifest is added to `index.json`, annotated with `reference`
+    /// when it is a tag (replacing the manifest previously pulled with this
+    /// tag). Returns its descriptor.
+    pub async fn pull_image<I: AsRepository + ?Sized, T: AsTagOrDigest + ?Sized, P: AsRef<Path>>(
+        &self,
+        image: &I,
+        reference: &T,
+        path: P,
+        platform: Option<&Platform>,
+    ) -> Result<Descriptor, ErrorResponse> {
+        let image = image.as_repository();
+        let reference = &*reference.as_tag_or_digest();
+        self.pull_image_locked(image, reference, path, platform)
+            .await
+            .map(|(descriptor, _)| descriptor)
+    }
+
+    /// Same as [`DockerRegistryClientV2::pull_image`], also returning the
+    /// digests pulled as a lockfile entry (see [`crate::lockfile`]).
+    ///
+    /// For manifest lists, the entry has the digest of the list and only
+    /// the manifest of the pulled platform.
+    pub async fn pull_image_locked<
+        I: AsRepository + ?Sized,
+        T: AsTagOrDigest + ?Sized,
+        P: AsRef<Path>,
+    >(
+        &self,
+        image: &I,
+        reference: &T,
+        path: P,
+        platform: Option<&Platform>,
+    ) -> Result<(Descriptor, LockedImage), ErrorResponse> {
+        let image = image.as_repository();
+        let reference = &*reference.as_tag_or_digest();
+        let operation = Operation::start("pull", image, reference);
+        let result = self
+            .pull_into_layout(&operation, image, reference, path.as_ref(), platform)
+            .await;
+        operation.finish(result.as_ref().map(|(_, locked)| &locked.digest));
+        result
+    }
+
+    async fn pull_into_layout(
+        &self,
+        operation: &Operation,
+        image: &str,
+        reference: &str,
+        path: &Path,
+        platform: Option<&Platform>,
+    ) -> Result<(Descriptor, LockedImage), ErrorResponse> {
+        let store = ContentStore::new(path, StoreLayout::Oci);
+
+        let (mut body, mut content_type, mut digest) = self.manifest_raw(image, reference).await?;
+        let mut selected = None;
+        let mut list_digest = None;
+        let manifest = loop {
+            match ManifestResponse::from_slice(content_type.as_deref(), &body)? {
+                ManifestResponse::Image(manifest) => break manifest,
+                ManifestResponse::List(list) if selected.is_none() => {
+                    list_digest =
+                        Some((digest.clone(), list.media_type_or(MediaType::OciImageIndex)));
+                    let (os, architecture) = match platform {
+                        Some(platform) => (
+                            platform.os.clone(),
+                            match &platform.variant {
+                                Some(variant) => format!("{}/{}", platform.architecture, variant),
+                                None => platform.architecture.clone(),
+                            },
+                        ),
+                        None => ("linux".to_owned(), host_architecture().to_owned()),
+                    };
+                    let item = list.find_platform(&os, &architecture).ok_or_else(|| {
+                        ErrorResponse::platform_not_found(format!("{}/{}", os, architecture))
+                    })?;
+                    selected = Some(item.platform.clone());
+                    (body, content_type, digest) =
+                        self.manifest_raw(image, &item.digest.to_string()).await?;
+                }
+                other => {
+                    return Err(ErrorResponse::unsupported_media_type(
+                        other.media_type().to_string(),
+                    ))
+                }
+            }
+        };
+
+        let blobs: Vec<_> = std::iter::once(&manifest.config)
+            .chain(manifest.unique_layers())
+            .collect();
+        stream::iter(blobs)
+            .map(|descriptor| self.pull_blob(&store, operation, image, descriptor))
+            .buffer_unordered(MAX_CONCURRENT_BLOBS)
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        store
+            .write(&format!("manifest-{}", digest), &digest, &body)
+            .await?;
+        let selected = selected.flatten();
+        let manifests = vec![LockedManifest::new(
+            digest.clone(),
+            selected.as_ref().map(LockedPlatform::from),
+            &manifest,
+        )];
+        let (locked_digest, media_type) = list_digest.unwrap_or_else(|| {
+            (
+                digest.clone(),
+                manifest.media_type_or(MediaType::OciManifest),
+            )
+        });
+        let locked = LockedImage {
+            image: image.to_owned(),
+            reference: reference.to_owned(),
+            digest: locked_digest,
+            media_type,
+            manifests,
+        };
+
+        let mut descriptor = Descriptor::new(
+            manifest.media_type_or(MediaType::OciManifest),
+            digest,
+            body.len(),
+        );
+        descriptor.platform = selected;
+        if reference.parse::<Digest>().is_err() {
+            descriptor.annotations = Some(HashMap::from([(
+                ANNOTATION_REF_NAME.to_owned(),
+                reference.to_owned(),
+            )]));
+        }
+        add_to_index(store.root(), &descriptor).await?;
+        Ok((descriptor, locked))
+    }
+
+    /// Download the blob of `descriptor` into `store`, unless it is stored.
+    async fn pull_blob(
+        &self,
+        store: &ContentStore,
+        operation: &Operation,
+        image: &str,
+        descriptor: &Descriptor,
+    ) -> Result<(), ErrorResponse> {
+        if store.contains(&descriptor.digest).await {
+            operation.cache_hit();
+            return Ok(());
+        }
+        let blob = self.layer_blob(image, descriptor).await?;
+        store
+            .ingest_blob(
+                &format!("layer-{}", descriptor.digest),
+                &descriptor.digest,
+                blob,
+                self.verification,
+            )
+            .await?;
+        operation.transferred(descriptor.size as u64);
+        Ok(())
+    }
+}
+
+/// Add `descriptor` to the `index.json` file of the layout at `root`,
+/// replacing the entry with the same digest or tag.
+async fn add_to_index(root: &Path, descriptor: &Descriptor) -> Result<(), ErrorResponse> {
+    let _lock = IndexLock::acquire(root).await?;
+    let path = root.join("index.json");
+    let mut index = match tokio::fs::read(&path).await {
+        Ok(content) => serde_json::from_slice(&content)?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => ImageIndex::new(),
+        Err(err) => return Err(err.into()),
+    };
+
+    let ref_name = |descriptor: &Descriptor| {
+        descriptor
+            .annotations
+            .as_ref()
+            .and_then(|annotations| annotations.get(ANNOTATION_REF_NAME).cloned())
+    };
+    let name = ref_name(descriptor);
+    index.manifests.retain(|item| match &name {
+        Some(name) => ref_name(item).as_ref() != Some(name),
+        None => item.digest != descriptor.digest || ref_name(item).is_some(),
+    });
+    index.manifests.push(descriptor.clone());
+
+    store::write_atomic(&path, &serde_json::to_vec(&index)?).await?;
+    Ok(())
+}
+
+/// Exclusive lock of the `index.json` file of a layout, held as long as the
+/// lock file exists.
+struct IndexLock(PathBuf);
+
+impl IndexLock {
+    async fn acquire(root: &Path) -> io::Result<Self> {
+        let path = root.join("index.json.lock");
+        loop {
+            let created = tokio::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .await;
+            match created {
+                Ok(_) => return Ok(Self(path)),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    let stale = tokio::fs::metadata(&path)
+                        .await
+                        .and_then(|metadata| metadata.modified())
+                        .ok()
+                        .and_then(|modified| modified.elapsed().ok())
+                        .is_some_and(|age| age > STALE_LOCK_AGE);
+                    match stale {
+                        true => {
+                            let _ = tokio::fs::remove_file(&path).await;
+                        }
+                        false => tokio::time::sleep(LOCK_RETRY_DELAY).await,
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl Drop for IndexLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
@@ -11,11 +11,11 @@
 //! use oci_registry_client::DockerRegistryClientV2;
 //!
 //! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
-//! let mut client = DockerRegistryClientV2::new(
+//! let mut client = DockerRegistryClientV2::try_new(
 //!     "registry.docker.io",
 //!     "https://registry-1.docker.io",
 //!     "https://auth.docker.io/token"
-//! );
+//! )?;
 //! let token = client.auth("repository", "library/ubuntu", "latest").await?;
 //! client.set_auth_token(Some(token));
 //!
@@ -23,7 +23,7 @@
 //! println!("{:?}", manifest);
 //!
 //! for layer in &manifest.layers {
-//!    let mut out_file = File::create(Path::new("/tmp/").join(&layer.digest.to_string()))?;
+//!    let mut out_file = File::create(Path::new("/tmp/").join(layer.digest.to_string()))?;
 //!    let mut blob = client.blob("library/ubuntu", &layer.digest).await?;
 //!
 //!    while let Some(chunk) = blob.chunk().await? {
@@ -34,15 +34,91 @@
 //! # Ok(())
 //! # }
 //! ```
+//!
+//! # Features
+//!
+//! * `gzip`: decompression of layers (`Blob::decompress`), listing their
+//!   files (`layer`) and image analysis (`analyze`).
+//! * `tar`: unpacking layers (`Blob::untar_to`, `rootfs`) and Docker
+//!   archives (`archive`), enables `gzip`.
+//! * `regex`: regular expression repository rename rules
+//!   (`NameRule::Regex`).
+//! * `tracing`: emit `tracing` events for retries, redirects, mirror
+//!   fallbacks and audit records.
 
+#[cfg(feature = "gzip")]
+pub mod analyze;
+#[cfg(feature = "tar")]
+pub mod archive;
+pub mod attestation;
+mod audit;
+pub mod auth;
+pub mod bandwidth;
 pub mod blob;
+pub mod builder;
+pub mod cache_control;
+pub mod catalog;
+pub mod copy;
+pub mod diagnostics;
 pub mod errors;
+#[cfg(feature = "gzip")]
+pub mod layer;
+pub mod layout;
+pub mod limits;
+pub mod lockfile;
 pub mod manifest;
+pub mod mirror;
+pub mod notifications;
+pub mod pagination;
+pub mod parallel;
+pub mod proxy;
+pub mod push;
+pub mod ratelimit;
+pub mod redirect;
+pub mod reference;
+pub mod referrers;
+pub mod registries;
+pub mod rename;
+pub mod retry;
+#[cfg(feature = "tar")]
+pub mod rootfs;
+pub mod scheduler;
+pub mod store;
+pub mod timeout;
+pub mod tls;
+pub mod verify;
+pub mod watch;
 
-use blob::Blob;
-use errors::{ErrorList, ErrorResponse};
-use manifest::{Digest, Image, Manifest, ManifestList};
-use reqwest::{Method, StatusCode};
+#[cfg(feature = "gzip")]
+use analyze::AnalysisCache;
+pub use auth::AuthToken;
+use auth::{
+    AuthEndpoint, Challenge, Credentials, EndpointCache, Scope, TokenCache, TokenProvider,
+    TokenRequestOptions,
+};
+use bandwidth::Bandwidth;
+use blob::{Blob, ByteRange};
+use builder::{ClientBuilder, HttpOptions};
+use bytes::Bytes;
+use cache_control::CacheControl;
+use errors::{reject_html, ErrorContext, ErrorResponse};
+use futures_util::FutureExt;
+use limits::SizeLimits;
+use manifest::{
+    Descriptor, Digest, Image, Layer, Manifest, ManifestList, ManifestResponse, MediaType,
+    RawManifest,
+};
+use push::PushThrottle;
+use reference::{AsRepository, AsTagOrDigest};
+use reqwest::{Method, RequestBuilder, StatusCode};
+use retry::RetryPolicy;
+use scheduler::{Permit, Priority, Scheduler};
+use sha2::{Digest as Sha256Digest, Sha256};
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use store::{CachedBlob, ContentStore};
+use timeout::Timeouts;
+use tls::TlsConfig;
+use verify::VerificationPolicy;
 
 static USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
 
@@ -56,6 +132,26 @@ pub struct DockerRegistryClientV2 {
     api_url: String,
     oauth_url: String,
     auth_token: Option<AuthToken>,
+    credentials: Option<Credentials>,
+    token_provider: Option<Arc<dyn TokenProvider>>,
+    token_cache: TokenCache,
+    token_request_options: TokenRequestOptions,
+    endpoint_cache: EndpointCache,
+    content_store: Option<ContentStore>,
+    push_throttle: PushThrottle,
+    push_preflight: bool,
+    verification: VerificationPolicy,
+    size_limits: SizeLimits,
+    #[cfg(feature = "gzip")]
+    analysis_cache: AnalysisCache,
+    scheduler: Scheduler,
+    bandwidth: Bandwidth,
+    timeouts: Timeouts,
+    chunk_retries: usize,
+    retry: Option<RetryPolicy>,
+    mirrors: Arc<Vec<DockerRegistryClientV2>>,
+    manifest_accept: String,
+    http: HttpOptions,
     client: reqwest::Client,
 }
 
@@ -63,15 +159,45 @@ pub struct DockerRegistryClientV2 {
 #[serde(rename_all = "camelCase")]
 pub struct Version {}
 
+/// Manifest metadata returned by [`DockerRegistryClientV2::manifest_head`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ManifestHead {
+    /// Digest announced in the `Docker-Content-Digest` header.
+    pub digest: Option<Digest>,
+    pub size: Option<u64>,
+    pub media_type: Option<MediaType>,
+    pub cache_control: CacheControl,
+}
+
 const MEDIA_TYPE_JSON: &str = "application/json";
+/// `client_id` sent with OAuth2 token requests when none is configured.
+const DEFAULT_CLIENT_ID: &str = "oci-registry-client";
 const MEDIA_TYPE_MANIFEST_LIST_V2: &str =
     "application/vnd.docker.distribution.manifest.list.v2+json";
 const MEDIA_TYPE_MANIFEST_V2: &str = "application/vnd.docker.distribution.manifest.v2+json";
+const MEDIA_TYPE_MANIFEST_V1: &str = "application/vnd.docker.distribution.manifest.v1+json";
+const MEDIA_TYPE_MANIFEST_V1_SIGNED: &str =
+    "application/vnd.docker.distribution.manifest.v1+prettyjws";
 const MEDIA_TYPE_IMAGE_CONFIG: &str = "application/vnd.docker.container.image.v1+json";
+const MEDIA_TYPE_OCI_MANIFEST_V1: &str = "application/vnd.oci.image.manifest.v1+json";
+const MEDIA_TYPE_OCI_IMAGE_INDEX_V1: &str = "application/vnd.oci.image.index.v1+json";
+const MEDIA_TYPE_OCI_IMAGE_CONFIG: &str = "application/vnd.oci.image.config.v1+json";
+
+/// Manifest media types sent in the `Accept` header, in order of preference.
+const MANIFEST_MEDIA_TYPES: [&str; 5] = [
+    MEDIA_TYPE_OCI_MANIFEST_V1,
+    MEDIA_TYPE_OCI_IMAGE_INDEX_V1,
+    MEDIA_TYPE_MANIFEST_V2,
+    MEDIA_TYPE_MANIFEST_LIST_V2,
+    MEDIA_TYPE_MANIFEST_V1_SIGNED,
+];
 
 impl DockerRegistryClientV2 {
     /// Returns a new `DockerRegistryClientV2`.
     ///
+    /// Panics when the HTTP client can't be built, see
+    /// [`DockerRegistryClientV2::try_new`].
+    ///
     /// # Arguments
     ///
     /// * `service` - Name of a Image Registry Service (example: registry.docker.io)
@@ -81,6 +207,7 @@ impl DockerRegistryClientV2 {
     /// # Example
     ///
     /// ```no_run
+    /// # #![allow(deprecated)]
     /// # use oci_registry_client::DockerRegistryClientV2;
     /// let mut client = DockerRegistryClientV2::new(
     ///     "registry.docker.io",
@@ -88,28 +215,332 @@ impl DockerRegistryClientV2 {
     ///     "https://auth.docker.io/token"
     /// );
     /// ```
+    #[deprecated(
+        since = "0.2.2",
+        note = "panics when the HTTP client can't be built, use `try_new` or `builder`"
+    )]
     pub fn new<T: Into<String>>(service: T, api_url: T, oauth_url: T) -> Self {
-        let client = reqwest::Client::builder()
-            .user_agent(USER_AGENT)
-            .build()
-            .unwrap();
+        Self::try_new(service, api_url, oauth_url).unwrap()
+    }
+
+    /// Returns a new `DockerRegistryClientV2`, or an error when the HTTP
+    /// client can't be built (for example when no TLS backend is usable).
+    ///
+    /// See [`DockerRegistryClientV2::new`] for a description of the
+    /// arguments.
+    ///
+    /// ```no_run
+    /// # use oci_registry_client::DockerRegistryClientV2;
+    /// # fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mut client = DockerRegistryClientV2::try_new(
+    ///     "registry.docker.io",
+    ///     "https://registry-1.docker.io",
+    ///     "https://auth.docker.io/token"
+    /// )?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn try_new<T: Into<String>>(
+        service: T,
+        api_url: T,
+        oauth_url: T,
+    ) -> Result<Self, ErrorResponse> {
+        let http = HttpOptions::default();
+        let client = http.build(Timeouts::default().connect)?;
+
+        Ok(Self::from_http_client(
+            service, api_url, oauth_url, client, http,
+        ))
+    }
 
+    /// Returns a builder of a client for the registry API at `api_url`, see
+    /// [`crate::builder`].
+    pub fn builder<T: Into<String>>(api_url: T) -> ClientBuilder {
+        ClientBuilder::new(api_url)
+    }
+
+    /// Returns a new `DockerRegistryClientV2` using the given TLS options.
+    ///
+    /// See [`DockerRegistryClientV2::new`] for a description of the other
+    /// arguments.
+    pub fn with_tls<T: Into<String>>(
+        service: T,
+        api_url: T,
+        oauth_url: T,
+        tls: TlsConfig,
+    ) -> Result<Self, ErrorResponse> {
+        let http = HttpOptions {
+            tls,
+            ..HttpOptions::default()
+        };
+        let client = http.build(Timeouts::default().connect)?;
+
+        Ok(Self::from_http_client(
+            service, api_url, oauth_url, client, http,
+        ))
+    }
+
+    fn from_http_client<T: Into<String>>(
+        service: T,
+        api_url: T,
+        oauth_url: T,
+        client: reqwest::Client,
+        http: HttpOptions,
+    ) -> Self {
         Self {
             service: service.into(),
             api_url: api_url.into(),
             oauth_url: oauth_url.into(),
             auth_token: None,
+            credentials: None,
+            token_provider: None,
+            token_cache: TokenCache::default(),
+            token_request_options: TokenRequestOptions::default(),
+            endpoint_cache: EndpointCache::shared(),
+            content_store: None,
+            push_throttle: PushThrottle::default(),
+            push_preflight: false,
+            verification: VerificationPolicy::default(),
+            size_limits: SizeLimits::default(),
+            #[cfg(feature = "gzip")]
+            analysis_cache: AnalysisCache::default(),
+            scheduler: Scheduler::default(),
+            bandwidth: Bandwidth::default(),
+            timeouts: Timeouts::default(),
+            chunk_retries: 0,
+            retry: None,
+            mirrors: Arc::default(),
+            manifest_accept: MANIFEST_MEDIA_TYPES.join(", "),
+            http,
             client,
         }
     }
 
+    /// Returns a client acting as the user identified by `credentials`.
+    ///
+    /// The new client shares the HTTP connections, caches and request limits
+    /// of this one, only the identity differs: tokens are fetched with
+    /// `credentials` as needed and cached separately, the token and
+    /// [`TokenProvider`] of this client are not used.
+    ///
+    /// ```no_run
+    /// # use oci_registry_client::{auth::Credentials, DockerRegistryClientV2};
+    /// # async fn example(client: DockerRegistryClientV2) -> Result<(), Box<dyn std::error::Error>> {
+    /// let alice = client.with_credentials(Credentials::new("alice", "alice-token"));
+    /// let bob = client.with_credentials(Credentials::new("bob", "bob-token"));
+    /// alice.manifest("alice/app", "latest").await?;
+    /// bob.manifest("bob/app", "latest").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_credentials(&self, credentials: Credentials) -> Self {
+        Self {
+            credentials: Some(credentials),
+            ..self.with_identity()
+        }
+    }
+
+    /// Returns a client authenticating with `token`, sharing the HTTP
+    /// connections, caches and request limits of this one.
+    ///
+    /// See [`DockerRegistryClientV2::with_credentials`].
+    pub fn with_token(&self, token: AuthToken) -> Self {
+        Self {
+            auth_token: Some(token),
+            ..self.with_identity()
+        }
+    }
+
+    /// Returns a clone of this client without identity.
+    fn with_identity(&self) -> Self {
+        Self {
+            auth_token: None,
+            credentials: None,
+            token_provider: None,
+            // Tokens are granted to an identity, never share them.
+            token_cache: TokenCache::default(),
+            ..self.clone()
+        }
+    }
+
     /// Set access token to authenticate subsequent requests.
     pub fn set_auth_token(&mut self, token: Option<AuthToken>) {
         self.auth_token = token;
     }
 
+    /// Set a [`TokenProvider`] consulted before each request.
+    ///
+    /// Tokens returned by the provider take precedence over the token set
+    /// with [`DockerRegistryClientV2::set_auth_token`].
+    pub fn set_token_provider(&mut self, provider: Option<Arc<dyn TokenProvider>>) {
+        self.token_provider = provider;
+    }
+
+    /// Set the options applied to requests sent to the token endpoint by
+    /// [`DockerRegistryClientV2::auth`].
+    pub fn set_token_request_options(&mut self, options: TokenRequestOptions) {
+        self.token_request_options = options;
+    }
+
+    /// Set the cache used to store resolved authentication endpoints.
+    ///
+    /// Defaults to [`EndpointCache::shared`].
+    pub fn set_endpoint_cache(&mut self, cache: EndpointCache) {
+        self.endpoint_cache = cache;
+    }
+
+    /// Set the checks applied to fetched manifests and blobs.
+    ///
+    /// Defaults to [`VerificationPolicy::Digests`].
+    pub fn set_verification_policy(&mut self, policy: VerificationPolicy) {
+        self.verification = policy;
+    }
+
+    /// Set the timeouts applied to the phases of requests, see
+    /// [`timeout`].
+    ///
+    /// Changing the connect timeout rebuilds the underlying HTTP client, its
+    /// pooled connections are dropped. The connect timeout of a client
+    /// provided with [`builder::ClientBuilder::http_client`] is not changed.
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) -> Result<(), ErrorResponse> {
+        if timeouts.connect != self.timeouts.connect {
+            self.client = self.http.build(timeouts.connect)?;
+        }
+        self.timeouts = timeouts;
+        Ok(())
+    }
+
+    /// Set the number of times a blob download is resumed when reading its
+    /// contents fails (none by default), see [`Self::blob_resumable`].
+    ///
+    /// Applies to the blobs downloaded from the registry by [`Self::blob`]
+    /// and [`Self::layer_blob`], and the methods built on them (pulls,
+    /// exports, file extraction). Foreign layers downloaded from their
+    /// `urls` are not resumed.
+    pub fn set_chunk_retries(&mut self, retries: usize) {
+        self.chunk_retries = retries;
+    }
+
+    /// Set the maximum sizes of manifests and configs read in memory.
+    pub fn set_size_limits(&mut self, limits: SizeLimits) {
+        self.size_limits = limits;
+    }
+
+    /// Set the local store blobs are downloaded to by
+    /// [`DockerRegistryClientV2::store_blob`].
+    pub fn set_content_store(&mut self, store: Option<ContentStore>) {
+        self.content_store = store;
+    }
+
+    /// Resolve the token endpoint announced by this registry.
+    ///
+    /// Probes `GET /v2/` and reads the `WWW-Authenticate` challenge. Returns
+    /// `None` if the registry does not require authentication. Results are
+    /// stored in the endpoint cache, so subsequent calls (from any client
+    /// sharing the cache) do not hit the registry.
+    ///
+    /// Some registries (usually behind a CDN) answer `/v2/` with an error
+    /// while serving content, `None` is then returned without being cached,
+    /// use [`DockerRegistryClientV2::auth_endpoint_for`] to probe a
+    /// repository instead.
+    pub async fn auth_endpoint(&self) -> Result<Option<AuthEndpoint>, ErrorResponse> {
+        self.probe_auth_endpoint(None).await
+    }
+
+    /// Resolve the token endpoint announced by this registry, probing
+    /// `image` when `/v2/` does not answer as expected.
+    ///
+    /// The fallback probe is an anonymous `HEAD` request on the `latest`
+    /// manifest of `image`, only its `WWW-Authenticate` challenge is used.
+    pub async fn auth_endpoint_for<I: AsRepository + ?Sized>(
+        &self,
+        image: &I,
+    ) -> Result<Option<AuthEndpoint>, ErrorResponse> {
+        let image = image.as_repository();
+        self.probe_auth_endpoint(Some(image)).await
+    }
+
+    async fn probe_auth_endpoint(
+        &self,
+        image: Option<&str>,
+    ) -> Result<Option<AuthEndpoint>, ErrorResponse> {
+        if let Some(endpoint) = self.endpoint_cache.get(&self.api_url) {
+            return Ok(endpoint);
+        }
+
+        let url = format!("{}/v2/", self.api_url);
+        let mut response = self
+            .send_with_retries(self.client.get(&url), &self.timeouts.manifests)
+            .await?;
+
+        if !matches!(response.status(), StatusCode::OK | StatusCode::UNAUTHORIZED) {
+            let image = match image {
+                Some(image) => image,
+                None => return Ok(None),
+            };
+            let url = format!("{}/v2/{}/manifests/latest", self.api_url, image);
+            let request = self
+                .client
+                .head(&url)
+                .header(reqwest::header::ACCEPT, &self.manifest_accept);
+            response = self
+                .send_with_retries(request, &self.timeouts.manifests)
+                .await?;
+        }
+
+        let endpoint = match response.status() {
+            StatusCode::UNAUTHORIZED => match response
+                .headers()
+                .get(reqwest::header::WWW_AUTHENTICATE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(Challenge::parse)
+                .as_ref()
+                .and_then(AuthEndpoint::from_challenge)
+            {
+                Some(endpoint) => Some(endpoint),
+                // An unusable challenge does not mean that the registry is
+                // anonymous, it is not cached.
+                None => return Ok(None),
+            },
+            // The manifest probe answers 404 (or 200) to anonymous clients.
+            status if status.is_success() || status == StatusCode::NOT_FOUND => None,
+            _ => return Err(ErrorResponse::from_response(response).await),
+        };
+
+        self.endpoint_cache
+            .insert(&self.api_url, endpoint.clone())
+            .await;
+        Ok(endpoint)
+    }
+
+    /// Returns the token endpoint of this registry: the configured one, or
+    /// the one announced by the registry (see
+    /// [`DockerRegistryClientV2::auth_endpoint`]) when the client has no
+    /// `auth_url`. `None` if the registry has no token service.
+    async fn token_endpoint(&self) -> Result<Option<AuthEndpoint>, ErrorResponse> {
+        if !self.oauth_url.is_empty() {
+            return Ok(Some(AuthEndpoint {
+                realm: self.oauth_url.clone(),
+                service: self.service.clone(),
+            }));
+        }
+        Ok(self.auth_endpoint().await?.map(|endpoint| AuthEndpoint {
+            service: match endpoint.service.is_empty() {
+                true => self.service.clone(),
+                false => endpoint.service,
+            },
+            ..endpoint
+        }))
+    }
+
     /// Fetch a access token from `auth_url` for this `service`.
     ///
+    /// Without `auth_url`, the token endpoint and service announced by the
+    /// registry are used, see [`DockerRegistryClientV2::auth_endpoint`].
+    ///
+    /// Tokens are cached by service and scope, a cached token is returned
+    /// while it is still valid.
+    ///
     /// # Arguments
     ///
     /// * `type` - Scope type (example: "repository").
@@ -121,66 +552,1010 @@ impl DockerRegistryClientV2 {
         name: &str,
         action: &str,
     ) -> Result<AuthToken, ErrorResponse> {
+        let scope = self.token_request_options.format_scope(&Scope {
+            r#type: r#type.to_owned(),
+            name: name.to_owned(),
+            actions: action.to_owned(),
+        });
+        let endpoint = self
+            .token_endpoint()
+            .await?
+            .unwrap_or_else(|| AuthEndpoint {
+                realm: self.oauth_url.clone(),
+                service: self.service.clone(),
+            });
+        if let Some(token) = self.token_cache.get(&endpoint.service, &scope) {
+            return Ok(token);
+        }
+
+        let options = &self.token_request_options;
+        let refresh_token = match &self.token_provider {
+            Some(provider) => provider.refresh_token(&endpoint.service).await?,
+            None => None,
+        };
+        let request = match &refresh_token {
+            // OAuth2 refresh token grant, client_id is required.
+            Some(refresh_token) => {
+                let mut form = vec![
+                    ("grant_type", "refresh_token"),
+                    ("refresh_token", refresh_token.as_str()),
+                    ("service", endpoint.service.as_str()),
+                    ("scope", scope.as_str()),
+                ];
+                form.extend(options.params());
+                if !form.iter().any(|(key, _)| *key == "client_id") {
+                    form.push(("client_id", DEFAULT_CLIENT_ID));
+                }
+                self.client.post(&endpoint.realm).form(&form)
+            }
+            None => {
+                let request = self
+                    .client
+                    .get(&endpoint.realm)
+                    .query(&[
+                        ("service", endpoint.service.as_str()),
+                        ("scope", scope.as_str()),
+                    ])
+                    .query(&options.params());
+                match &self.credentials {
+                    Some(credentials) => {
+                        request.basic_auth(&credentials.username, Some(&credentials.password))
+                    }
+                    None => request,
+                }
+            }
+        };
         let response = self
-            .client
-            .get(&self.oauth_url)
-            .query(&[
-                ("service", self.service.clone()),
-                ("scope", format!("{}:{}:{}", r#type, name, action)),
-            ])
-            .send()
+            .send_with_retries(request, &self.timeouts.manifests)
             .await?;
 
         match response.status() {
-            StatusCode::OK => Ok(response.json::<AuthToken>().await?),
-            _ => Err(ErrorResponse::APIError(response.json::<ErrorList>().await?)),
+            StatusCode::OK => {
+                let token = reject_html(response).await?.json::<AuthToken>().await?;
+                if let (Some(provider), Some(issued)) =
+                    (&self.token_provider, token.refresh_token())
+                {
+                    if refresh_token.as_deref() != Some(issued) {
+                        provider
+                            .store_refresh_token(&endpoint.service, issued)
+                            .await?;
+                    }
+                }
+                self.token_cache
+                    .insert(&endpoint.service, &scope, token.clone());
+                Ok(token)
+            }
+            _ => Err(ErrorResponse::from_response(response).await),
         }
     }
 
     /// Get API version.
     pub async fn version(&self) -> Result<Version, ErrorResponse> {
         let url = format!("{}/v2", self.api_url);
-        self.request(Method::GET, &url, MEDIA_TYPE_JSON).await
+        self.request(Method::GET, &url, None, &[MEDIA_TYPE_JSON])
+            .await
+    }
+
+    /// Returns `true` if `reference` (a tag or digest) exists in `image`.
+    pub async fn manifest_exists<I: AsRepository + ?Sized, T: AsTagOrDigest + ?Sized>(
+        &self,
+        image: &I,
+        reference: &T,
+    ) -> Result<bool, ErrorResponse> {
+        let image = image.as_repository();
+        let reference = &*reference.as_tag_or_digest();
+        let url = format!("{}/v2/{}/manifests/{}", &self.api_url, image, reference);
+        self.exists(&url, image, &[self.manifest_accept.as_str()])
+            .await
+    }
+
+    /// Returns the digest, size and media type of the manifest `reference`
+    /// without downloading it, using a `HEAD` request.
+    ///
+    /// ```no_run
+    /// # use oci_registry_client::DockerRegistryClientV2;
+    /// # async fn example(client: DockerRegistryClientV2) -> Result<(), Box<dyn std::error::Error>> {
+    /// let head = client.manifest_head("library/alpine", "latest").await?;
+    /// if let Some(digest) = head.digest {
+    ///     println!("library/alpine@{}", digest);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn manifest_head<I: AsRepository + ?Sized, T: AsTagOrDigest + ?Sized>(
+        &self,
+        image: &I,
+        reference: &T,
+    ) -> Result<ManifestHead, ErrorResponse> {
+        let image = image.as_repository();
+        let reference = &*reference.as_tag_or_digest();
+        let url = format!("{}/v2/{}/manifests/{}", &self.api_url, image, reference);
+        let context = || ErrorContext::new(Method::HEAD, &url, Some(image), Some(reference));
+        in_context(context, async {
+            let request = self
+                .client
+                .head(&url)
+                .header(reqwest::header::ACCEPT, &self.manifest_accept);
+            let request = self.authorize(request, Some(image)).await?;
+            let _permit = self.scheduler.acquire(Priority::High).await;
+            let response = self
+                .send_with_retries(request, &self.timeouts.manifests)
+                .await?;
+
+            if response.status() != StatusCode::OK {
+                return Err(ErrorResponse::from_response(response).await);
+            }
+
+            let headers = response.headers();
+            let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+            Ok(ManifestHead {
+                digest: header(reqwest::header::HeaderName::from_static(
+                    "docker-content-digest",
+                ))
+                .and_then(|value| value.parse().ok()),
+                size: header(reqwest::header::CONTENT_LENGTH).and_then(|value| value.parse().ok()),
+                media_type: header(reqwest::header::CONTENT_TYPE).map(MediaType::from),
+                cache_control: CacheControl::from_headers(headers),
+            })
+        })
+        .await
+    }
+
+    /// Get the manifest of `reference`, whatever its kind.
+    ///
+    /// The kind is selected from the returned `Content-Type`, use this when
+    /// `reference` may resolve either to a manifest list or an image manifest.
+    /// JSON manifests that can't be parsed (unknown media types or shapes)
+    /// are returned as [`ManifestResponse::Raw`].
+    ///
+    /// ```no_run
+    /// # use oci_registry_client::{manifest::ManifestResponse, DockerRegistryClientV2};
+    /// # async fn example(client: DockerRegistryClientV2) -> Result<(), Box<dyn std::error::Error>> {
+    /// match client.get_manifest("library/alpine", "latest").await? {
+    ///     ManifestResponse::List(list) => println!("{} platforms", list.manifests.len()),
+    ///     ManifestResponse::Image(manifest) => println!("{} layers", manifest.layers.len()),
+    ///     _ => {}
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_manifest<I: AsRepository + ?Sized, T: AsTagOrDigest + ?Sized>(
+        &self,
+        image: &I,
+        reference: &T,
+    ) -> Result<ManifestResponse, ErrorResponse> {
+        let image = image.as_repository();
+        let reference = &*reference.as_tag_or_digest();
+        let (body, content_type, digest) = self.manifest_raw(image, reference).await?;
+
+        match ManifestResponse::from_slice(content_type.as_deref(), &body) {
+            Err(
+                err @ (ErrorResponse::DecodeError { .. }
+                | ErrorResponse::UnsupportedMediaType { .. }),
+            ) => match serde_json::from_slice(&body) {
+                Ok(value) => Ok(ManifestResponse::Raw(RawManifest {
+                    value,
+                    content_type,
+                    digest,
+                })),
+                Err(_) => Err(err),
+            },
+            result => result,
+        }
+        .map_err(|err| err.with_context(self.manifest_context(image, reference)))
     }
 
     /// List manifests from given image and reference.
-    pub async fn list_manifests(
+    ///
+    /// Both Docker manifest lists and OCI image indexes are accepted.
+    pub async fn list_manifests<I: AsRepository + ?Sized, T: AsTagOrDigest + ?Sized>(
+        &self,
+        image: &I,
+        reference: &T,
+    ) -> Result<ManifestList, ErrorResponse> {
+        let image = image.as_repository();
+        let reference = &*reference.as_tag_or_digest();
+        Ok(self.list_manifests_with_digest(image, reference).await?.0)
+    }
+
+    /// List manifests from given image and reference, together with the
+    /// digest of the manifest list.
+    ///
+    /// The digest is the canonical content digest (as announced in the
+    /// `Docker-Content-Digest` header), suitable to pin `reference`.
+    pub async fn list_manifests_with_digest<I: AsRepository + ?Sized, T: AsTagOrDigest + ?Sized>(
+        &self,
+        image: &I,
+        reference: &T,
+    ) -> Result<(ManifestList, Digest), ErrorResponse> {
+        let image = image.as_repository();
+        let reference = &*reference.as_tag_or_digest();
+        match self.fetch_manifest(image, reference).await? {
+            (ManifestResponse::List(list), digest) => Ok((list, digest)),
+            (other, _) => Err(ErrorResponse::unsupported_media_type(
+                other.media_type().to_string(),
+            )
+            .with_context(self.manifest_context(image, reference))),
+        }
+    }
+
+    /// Get the image manifest.
+    ///
+    /// Both Docker (schema 2) and OCI image manifests are accepted.
+    pub async fn manifest<I: AsRepository + ?Sized, T: AsTagOrDigest + ?Sized>(
+        &self,
+        image: &I,
+        reference: &T,
+    ) -> Result<Manifest, ErrorResponse> {
+        let image = image.as_repository();
+        let reference = &*reference.as_tag_or_digest();
+        Ok(self.manifest_with_digest(image, reference).await?.0)
+    }
+
+    /// Get the image manifest of `reference` for the `os`/`architecture`
+    /// platform.
+    ///
+    /// If `reference` is a manifest list, the manifest matching the platform
+    /// is fetched, otherwise the image manifest is returned as is. A variant
+    /// may be given with the architecture (`arm/v7`).
+    ///
+    /// ```no_run
+    /// # use oci_registry_client::DockerRegistryClientV2;
+    /// # async fn example(client: DockerRegistryClientV2) -> Result<(), Box<dyn std::error::Error>> {
+    /// let manifest = client
+    ///     .manifest_for_platform("library/alpine", "latest", "linux", "arm64")
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn manifest_for_platform<I: AsRepository + ?Sized, T: AsTagOrDigest + ?Sized>(
+        &self,
+        image: &I,
+        reference: &T,
+        os: &str,
+        architecture: &str,
+    ) -> Result<Manifest, ErrorResponse> {
+        let image = image.as_repository();
+        let reference = &*reference.as_tag_or_digest();
+        let list = match self.fetch_manifest(image, reference).await? {
+            (ManifestResponse::Image(manifest), _) => return Ok(manifest),
+            (ManifestResponse::List(list), _) => list,
+            (other, _) => {
+                return Err(
+                    ErrorResponse::unsupported_media_type(other.media_type().to_string())
+                        .with_context(self.manifest_context(image, reference)),
+                )
+            }
+        };
+
+        let item = list.find_platform(os, architecture).ok_or_else(|| {
+            ErrorResponse::platform_not_found(format!("{}/{}", os, architecture))
+                .with_context(self.manifest_context(image, reference))
+        })?;
+
+        self.manifest(image, &item.digest.to_string()).await
+    }
+
+    /// Get the image manifest, together with its digest.
+    ///
+    /// The digest is the canonical content digest (as announced in the
+    /// `Docker-Content-Digest` header), suitable to pin `reference`.
+    pub async fn manifest_with_digest<I: AsRepository + ?Sized, T: AsTagOrDigest + ?Sized>(
+        &self,
+        image: &I,
+        reference: &T,
+    ) -> Result<(Manifest, Digest), ErrorResponse> {
+        let image = image.as_repository();
+        let reference = &*reference.as_tag_or_digest();
+        match self.fetch_manifest(image, reference).await? {
+            (ManifestResponse::Image(manifest), digest) => Ok((manifest, digest)),
+            (other, _) => Err(ErrorResponse::unsupported_media_type(
+                other.media_type().to_string(),
+            )
+            .with_context(self.manifest_context(image, reference))),
+        }
+    }
+
+    /// Get the manifest bytes exactly as served by the registry.
+    ///
+    /// Returns the body, its content type and its sha256 digest. The digest is
+    /// computed over the body and checked against the `Docker-Content-Digest`
+    /// header (and `reference`, when it is a digest), a
+    /// [`ErrorResponse::DigestMismatch`] is returned if they differ.
+    pub async fn manifest_raw<I: AsRepository + ?Sized, T: AsTagOrDigest + ?Sized>(
+        &self,
+        image: &I,
+        reference: &T,
+    ) -> Result<(Bytes, Option<String>, Digest), ErrorResponse> {
+        let image = image.as_repository();
+        let reference = &*reference.as_tag_or_digest();
+        let (body, content_type, digest, _) = self
+            .manifest_raw_with_cache_control(image, reference)
+            .await?;
+        Ok((body, content_type, digest))
+    }
+
+    /// Get the manifest bytes exactly as served by the registry, together
+    /// with the caching directives of the response.
+    ///
+    /// See [`DockerRegistryClientV2::manifest_raw`].
+    pub async fn manifest_raw_with_cache_control<
+        I: AsRepository + ?Sized,
+        T: AsTagOrDigest + ?Sized,
+    >(
+        &self,
+        image: &I,
+        reference: &T,
+    ) -> Result<(Bytes, Option<String>, Digest, CacheControl), ErrorResponse> {
+        let image = image.as_repository();
+        let reference = &*reference.as_tag_or_digest();
+        for mirror in self.mirrors.iter() {
+            match mirror.registry_manifest_raw(image, reference).await {
+                Ok(manifest) => return Ok(manifest),
+                Err(err) => mirror::skipped(mirror, &err),
+            }
+        }
+        self.registry_manifest_raw(image, reference).await
+    }
+
+    /// Get the manifest bytes from this registry, ignoring mirrors.
+    async fn registry_manifest_raw(
         &self,
         image: &str,
         reference: &str,
-    ) -> Result<ManifestList, ErrorResponse> {
+    ) -> Result<(Bytes, Option<String>, Digest, CacheControl), ErrorResponse> {
         let url = format!("{}/v2/{}/manifests/{}", &self.api_url, image, reference);
-        self.request(Method::GET, &url, MEDIA_TYPE_MANIFEST_LIST_V2)
-            .await
+        let context = || ErrorContext::new(Method::GET, &url, Some(image), Some(reference));
+        in_context(context, async {
+            let request = self
+                .client
+                .get(&url)
+                .header(reqwest::header::ACCEPT, &self.manifest_accept);
+            let request = self.authorize(request, Some(image)).await?;
+            let _permit = self.scheduler.acquire(Priority::High).await;
+            let response = self
+                .send_with_retries(request, &self.timeouts.manifests)
+                .await?;
+
+            if response.status() != StatusCode::OK {
+                return Err(ErrorResponse::from_response(response).await);
+            }
+
+            let response = reject_html(response).await?;
+            let content_type = response
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_owned());
+            let announced = response
+                .headers()
+                .get("docker-content-digest")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<Digest>().ok());
+            let cache_control = CacheControl::from_headers(response.headers());
+            let length = response.content_length();
+            let response_url = response.url().clone();
+            let body = limits::read_body(
+                response,
+                self.size_limits.max_manifest_size,
+                self.timeouts.manifests.idle,
+            )
+            .await?;
+            let digest = Digest::from_sha256(Sha256::digest(&body));
+            self.verification.check_size(length, body.len() as u64)?;
+
+            // Signed schema 1 digests are computed over the payload without its
+            // signatures, they can't be checked against the body.
+            let signed = content_type
+                .as_deref()
+                .map(|value| value.starts_with(MEDIA_TYPE_MANIFEST_V1_SIGNED))
+                .unwrap_or(false);
+            let expected = reference
+                .parse::<Digest>()
+                .ok()
+                .into_iter()
+                .chain(announced)
+                .filter(|_| !signed);
+            for expected in expected {
+                self.verification
+                    .check_digest(&expected, &digest)
+                    .map_err(|err| err.with_url(Some(&response_url)))?;
+            }
+
+            Ok((body, content_type, digest, cache_control))
+        })
+        .await
     }
 
-    /// Get the image manifest.
-    pub async fn manifest(&self, image: &str, reference: &str) -> Result<Manifest, ErrorResponse> {
+    /// Fetch a manifest accepting every supported media type, parsed according
+    /// to the returned `Content-Type`, together with its digest.
+    async fn fetch_manifest(
+        &self,
+        image: &str,
+        reference: &str,
+    ) -> Result<(ManifestResponse, Digest), ErrorResponse> {
+        let (body, content_type, digest) = self.manifest_raw(image, reference).await?;
+        let manifest = ManifestResponse::from_slice(content_type.as_deref(), &body)
+            .map_err(|err| err.with_context(self.manifest_context(image, reference)))?;
+        Ok((manifest, digest))
+    }
+
+    /// Returns the context of the request of the manifest `reference` of
+    /// `image`.
+    fn manifest_context(&self, image: &str, reference: &str) -> ErrorContext {
         let url = format!("{}/v2/{}/manifests/{}", &self.api_url, image, reference);
-        self.request(Method::GET, &url, MEDIA_TYPE_MANIFEST_V2)
-            .await
+        ErrorContext::new(Method::GET, &url, Some(image), Some(reference))
+    }
+
+    /// Get the container config of `manifest`.
+    ///
+    /// A config embedded in the descriptor (`data`) is used without request.
+    pub async fn manifest_config<I: AsRepository + ?Sized>(
+        &self,
+        image: &I,
+        manifest: &Manifest,
+    ) -> Result<Image, ErrorResponse> {
+        let image = image.as_repository();
+        match self.embedded_data(&manifest.config)? {
+            Some(data) => Ok(serde_json::from_slice(&data)?),
+            None => self.config(image, &manifest.config.digest).await,
+        }
+    }
+
+    /// Returns the content embedded in `descriptor`, checked according to the
+    /// verification policy.
+    fn embedded_data(&self, descriptor: &Descriptor) -> Result<Option<Bytes>, ErrorResponse> {
+        let data = match &descriptor.data {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+
+        self.verification
+            .check_size(Some(descriptor.size as u64), data.len() as u64)?;
+        self.verification.check_digest(
+            &descriptor.digest,
+            &Digest::from_sha256(Sha256::digest(data)),
+        )?;
+        Ok(Some(data.clone()))
     }
 
     /// Get the container config.
-    pub async fn config(&self, image: &str, reference: &Digest) -> Result<Image, ErrorResponse> {
+    ///
+    /// The config is verified against `reference` according to the
+    /// [`VerificationPolicy`] of the client.
+    pub async fn config<I: AsRepository + ?Sized>(
+        &self,
+        image: &I,
+        reference: &Digest,
+    ) -> Result<Image, ErrorResponse> {
+        let image = image.as_repository();
+        for mirror in self.mirrors.iter() {
+            match mirror.registry_config(image, reference).await {
+                Ok(config) => return Ok(config),
+                Err(err) => mirror::skipped(mirror, &err),
+            }
+        }
+        self.registry_config(image, reference).await
+    }
+
+    /// Get the container config from this registry, ignoring mirrors.
+    async fn registry_config(
+        &self,
+        image: &str,
+        reference: &Digest,
+    ) -> Result<Image, ErrorResponse> {
         let url = format!("{}/v2/{}/blobs/{}", &self.api_url, image, reference);
-        self.request(Method::GET, &url, MEDIA_TYPE_IMAGE_CONFIG)
-            .await
+        let context =
+            || ErrorContext::new(Method::GET, &url, Some(image), Some(&reference.to_string()));
+        in_context(context, async {
+            let request = self.client.get(&url).header(
+                reqwest::header::ACCEPT,
+                [MEDIA_TYPE_IMAGE_CONFIG, MEDIA_TYPE_OCI_IMAGE_CONFIG].join(", "),
+            );
+            let request = self.authorize(request, Some(image)).await?;
+            let _permit = self.scheduler.acquire(Priority::High).await;
+            let response = self
+                .send_with_retries(request, &self.timeouts.manifests)
+                .await?;
+
+            if response.status() != StatusCode::OK {
+                return Err(ErrorResponse::from_response(response).await);
+            }
+
+            let response = reject_html(response).await?;
+            let response_url = response.url().clone();
+            let body = limits::read_body(
+                response,
+                self.size_limits.max_config_size,
+                self.timeouts.manifests.idle,
+            )
+            .await?;
+            self.verification
+                .check_digest(reference, &Digest::from_sha256(Sha256::digest(&body)))
+                .map_err(|err| err.with_url(Some(&response_url)))?;
+            Ok(serde_json::from_slice(&body)?)
+        })
+        .await
     }
 
     /// Retrieve the blob from the registry identified by `digest`.
-    pub async fn blob(&self, image: &str, digest: &Digest) -> Result<Blob, ErrorResponse> {
+    ///
+    /// The contents are verified against `digest` according to the
+    /// [`VerificationPolicy`] of the client, reading the last chunk fails on
+    /// mismatch.
+    ///
+    /// The download is resumed as configured with
+    /// [`Self::set_chunk_retries`].
+    pub async fn blob<I: AsRepository + ?Sized>(
+        &self,
+        image: &I,
+        digest: &Digest,
+    ) -> Result<Blob, ErrorResponse> {
+        let image = image.as_repository();
+        self.blob_resumable(image, digest, self.chunk_retries).await
+    }
+
+    /// Download the blob identified by `digest` to the file at `path`.
+    ///
+    /// The contents are written to a temporary file next to `path`, renamed
+    /// to `path` once verified (see [`Self::blob`]), so `path` never holds
+    /// partial or corrupted content. Returns the number of bytes written.
+    /// Fails if `path` has no file name (e.g. `/` or `foo/..`).
+    ///
+    /// ```no_run
+    /// # use oci_registry_client::{manifest::Digest, DockerRegistryClientV2};
+    /// # async fn example(client: DockerRegistryClientV2, digest: Digest) -> Result<(), Box<dyn std::error::Error>> {
+    /// let size = client
+    ///     .download_blob("library/alpine", &digest, "/tmp/layer.tar.gz")
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn download_blob<I: AsRepository + ?Sized, P: AsRef<std::path::Path>>(
+        &self,
+        image: &I,
+        digest: &Digest,
+        path: P,
+    ) -> Result<u64, ErrorResponse> {
+        let image = image.as_repository();
+        let path = path.as_ref();
+        let mut name = match path.file_name() {
+            Some(name) => name.to_os_string(),
+            None => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("path has no file name: {}", path.display()),
+                )
+                .into())
+            }
+        };
+        name.push(".partial");
+        let partial = path.with_file_name(name);
+
+        let mut blob = self.blob(image, digest).await?;
+        let result = async {
+            let mut file = tokio::fs::File::create(&partial).await?;
+            blob.write_to(&mut file).await?;
+            file.sync_all().await?;
+            tokio::fs::rename(&partial, path).await?;
+            Ok(blob.received())
+        }
+        .await;
+        if result.is_err() {
+            let _ = tokio::fs::remove_file(&partial).await;
+        }
+        result
+    }
+
+    /// Retrieve the blob identified by `digest`, resuming the download up to
+    /// `max_resumes` times if the connection fails midway.
+    ///
+    /// Interrupted downloads are reissued with a `Range` request starting at
+    /// the first byte not received yet, so a failure near the end of a large
+    /// layer does not restart it from scratch. The digest of the blob covers
+    /// the whole content, as for [`Self::blob`]. `max_resumes` replaces the
+    /// number of retries set with [`Self::set_chunk_retries`].
+    ///
+    /// ```no_run
+    /// # use oci_registry_client::{manifest::Digest, DockerRegistryClientV2};
+    /// # async fn example(client: DockerRegistryClientV2, digest: Digest) -> Result<(), Box<dyn std::error::Error>> {
+    /// let mut blob = client.blob_resumable("library/alpine", &digest, 3).await?;
+    /// while let Some(chunk) = blob.chunk().await? {
+    ///     // ...
+    /// }
+    /// assert_eq!(blob.digest(), digest);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn blob_resumable<I: AsRepository + ?Sized>(
+        &self,
+        image: &I,
+        digest: &Digest,
+        max_resumes: usize,
+    ) -> Result<Blob, ErrorResponse> {
+        let image = image.as_repository();
+        let (response, permit) = self.blob_response(image, digest).await?;
+        let blob = match response.status() {
+            StatusCode::OK => self
+                .blob_from(response, permit, image, digest)
+                .verify(digest.clone(), self.verification),
+            _ => return Err(blob_error(response, image, digest).await),
+        };
+        Ok(self.resumable(blob, image, digest, max_resumes))
+    }
+
+    /// Returns `blob`, downloaded from the registry, resumed up to
+    /// `max_resumes` times, see [`Self::blob_resumable`].
+    fn resumable(&self, blob: Blob, image: &str, digest: &Digest, max_resumes: usize) -> Blob {
+        if max_resumes == 0 {
+            return blob;
+        }
+
+        let client = self.clone();
+        let image = image.to_owned();
+        let digest = digest.clone();
+        let resume_from = move |offset: u64| {
+            let client = client.clone();
+            let image = image.clone();
+            let digest = digest.clone();
+            async move {
+                match client.blob_range(&image, &digest, offset..).await? {
+                    (blob, true) => Ok(Some(blob)),
+                    // The registry ignored the range and restarted from scratch.
+                    (_, false) => Ok(None),
+                }
+            }
+            .boxed()
+        };
+        blob.with_resume(max_resumes, Box::new(resume_from))
+    }
+
+    /// Retrieve the bytes `range` of the blob identified by `digest`.
+    ///
+    /// Returns the blob and `true` if the registry honored the range
+    /// (`206 Partial Content`), or the whole blob and `false` if it ignored
+    /// it. The digest of a partial blob is the digest of the range only.
+    /// Fails if the range is empty (e.g. `0..0`) or if partial content does
+    /// not start at the requested offset.
+    ///
+    /// ```no_run
+    /// # use oci_registry_client::{blob::ByteRange, manifest::Digest, DockerRegistryClientV2};
+    /// # async fn example(client: DockerRegistryClientV2, digest: Digest) -> Result<(), Box<dyn std::error::Error>> {
+    /// // Read the last KiB of a layer (the end of the tar stream).
+    /// let (mut blob, partial) = client.blob_range("library/alpine", &digest, ByteRange::Last(1024)).await?;
+    /// if !partial {
+    ///     println!("range ignored, {:?} bytes to read", blob.len());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn blob_range<I: AsRepository + ?Sized, R: Into<ByteRange>>(
+        &self,
+        image: &I,
+        digest: &Digest,
+        range: R,
+    ) -> Result<(Blob, bool), ErrorResponse> {
+        let image = image.as_repository();
         let url = format!("{}/v2/{}/blobs/{}", &self.api_url, image, digest);
-        let mut request = self.client.get(&url);
-        if let Some(token) = self.auth_token.clone() {
-            request = request.bearer_auth(token.access_token);
+        let context =
+            || ErrorContext::new(Method::GET, &url, Some(image), Some(&digest.to_string()));
+        let range = range.into();
+        if range.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("empty byte range: {:?}", range),
+            )
+            .into());
         }
+        in_context(context, async {
+            let request = self
+                .client
+                .get(&url)
+                .header(reqwest::header::RANGE, range.to_string());
+            let request = self.authorize(request, Some(image)).await?;
+            let permit = self.scheduler.acquire(Priority::Low).await;
+            let response = self
+                .send_with_retries(request, &self.timeouts.blobs)
+                .await?;
 
-        let response = request.send().await?;
+            match response.status() {
+                StatusCode::PARTIAL_CONTENT => {
+                    // Contents are appended at the requested offset, another
+                    // range would corrupt them.
+                    let start = blob::content_range_start(&response);
+                    if let Some(expected) = range.start().filter(|&s| start != Some(s)) {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            format!(
+                                "partial content does not start at offset {}: {:?}",
+                                expected,
+                                response.headers().get(reqwest::header::CONTENT_RANGE),
+                            ),
+                        )
+                        .into());
+                    }
+                    Ok((self.blob_from(response, permit, image, digest), true))
+                }
+                StatusCode::OK => Ok((self.blob_from(response, permit, image, digest), false)),
+                _ => Err(ErrorResponse::from_response(response).await),
+            }
+        })
+        .await
+    }
 
-        match response.status() {
-            StatusCode::OK => Ok(Blob::from(response)),
-            _ => Err(ErrorResponse::APIError(response.json::<ErrorList>().await?)),
+    /// Get the blob of `layer`.
+    ///
+    /// Content embedded in the descriptor (`data`) is returned without
+    /// request. Foreign layers missing from the registry are downloaded from
+    /// their `urls`, in order. Downloads from the registry are resumed as
+    /// configured with [`Self::set_chunk_retries`].
+    pub async fn layer_blob<I: AsRepository + ?Sized>(
+        &self,
+        image: &I,
+        layer: &Layer,
+    ) -> Result<Blob, ErrorResponse> {
+        let image = image.as_repository();
+        if let Some(data) = self.embedded_data(layer)? {
+            return Ok(Blob::from_bytes(data, Some(layer.media_type.to_string())));
+        }
+
+        let (response, permit) = self.blob_response(image, &layer.digest).await?;
+        if response.status() == StatusCode::OK {
+            let blob = self
+                .blob_from(response, permit, image, &layer.digest)
+                .verify(layer.digest.clone(), self.verification);
+            return Ok(self.resumable(blob, image, &layer.digest, self.chunk_retries));
+        }
+
+        let urls = layer.urls.as_deref().unwrap_or_default();
+        if response.status() != StatusCode::NOT_FOUND || urls.is_empty() {
+            return Err(blob_error(response, image, &layer.digest).await);
+        }
+
+        let mut last = response;
+        for url in urls {
+            // External locations do not share the registry credentials.
+            let response = self
+                .send_with_retries(self.client.get(url), &self.timeouts.blobs)
+                .await?;
+            if response.status() == StatusCode::OK {
+                return Ok(self
+                    .blob_from(response, permit, image, &layer.digest)
+                    .verify(layer.digest.clone(), self.verification));
+            }
+            last = response;
+        }
+        Err(blob_error(last, image, &layer.digest).await)
+    }
+
+    /// Send a blob request, returning the response with the request slot
+    /// it holds. Mirrors are tried first, the first successful response is
+    /// returned.
+    async fn blob_response(
+        &self,
+        image: &str,
+        digest: &Digest,
+    ) -> Result<(reqwest::Response, Permit), ErrorResponse> {
+        for mirror in self.mirrors.iter() {
+            match mirror.registry_blob_response(image, digest).await {
+                Ok((response, permit)) if response.status() == StatusCode::OK => {
+                    return Ok((response, permit))
+                }
+                Ok((response, _)) => {
+                    let err = ErrorResponse::from_response(response).await;
+                    mirror::skipped(mirror, &err);
+                }
+                Err(err) => mirror::skipped(mirror, &err),
+            }
+        }
+        self.registry_blob_response(image, digest).await
+    }
+
+    /// Send a blob request to this registry, ignoring mirrors.
+    async fn registry_blob_response(
+        &self,
+        image: &str,
+        digest: &Digest,
+    ) -> Result<(reqwest::Response, Permit), ErrorResponse> {
+        let url = format!("{}/v2/{}/blobs/{}", &self.api_url, image, digest);
+        let context =
+            || ErrorContext::new(Method::GET, &url, Some(image), Some(&digest.to_string()));
+        in_context(context, async {
+            let request = self.authorize(self.client.get(&url), Some(image)).await?;
+            let permit = self.scheduler.acquire(Priority::Low).await;
+            Ok((
+                self.send_with_retries(request, &self.timeouts.blobs)
+                    .await?,
+                permit,
+            ))
+        })
+        .await
+    }
+
+    /// Returns the blob `digest` of `image` of a successful `response`,
+    /// recording the request in its errors, holding `permit` and
+    /// applying the idle timeout of blobs.
+    fn blob_from(
+        &self,
+        response: reqwest::Response,
+        permit: Permit,
+        image: &str,
+        digest: &Digest,
+    ) -> Blob {
+        let context = ErrorContext::new(
+            Method::GET,
+            response.url().as_str(),
+            Some(image),
+            Some(&digest.to_string()),
+        );
+        let blob = Blob::from(response)
+            .with_context(context)
+            .with_permit(permit)
+            .with_idle_timeout(self.timeouts.blobs.idle);
+        match &self.bandwidth.download {
+            Some(limiter) => blob.limit_rate(limiter),
+            None => blob,
+        }
+    }
+
+    /// Download the blob identified by `digest` into the configured content
+    /// store and returns its path. Blobs already stored are not downloaded
+    /// again.
+    ///
+    /// With the [`store::StoreLayout::Containerd`] layout, the blob is
+    /// labeled with the repository it was pulled from.
+    pub async fn store_blob<I: AsRepository + ?Sized>(
+        &self,
+        image: &I,
+        digest: &Digest,
+    ) -> Result<PathBuf, ErrorResponse> {
+        let image = image.as_repository();
+        let store = self.content_store()?;
+
+        let path = if store.contains(digest).await {
+            store.blob_path(digest)
+        } else {
+            let blob = self.blob(image, digest).await?;
+            store
+                .ingest_blob(
+                    &format!("unknown-{}", digest),
+                    digest,
+                    blob,
+                    self.verification,
+                )
+                .await?
+        };
+
+        store.set_labels(digest, &self.source_labels(image)).await?;
+        Ok(path)
+    }
+
+    /// Returns the blob identified by `digest` from the configured content
+    /// store, or downloads it.
+    ///
+    /// Downloaded content is written to the store while the caller reads it,
+    /// so it is only read once.
+    ///
+    /// ```no_run
+    /// # use oci_registry_client::{manifest::Digest, store::CachedBlob, DockerRegistryClientV2};
+    /// # async fn example(client: DockerRegistryClientV2, digest: Digest) -> Result<(), Box<dyn std::error::Error>> {
+    /// match client.blob_cached("library/alpine", &digest).await? {
+    ///     CachedBlob::Hit(path) => println!("cached at {}", path.display()),
+    ///     CachedBlob::Miss(mut blob) => {
+    ///         while let Some(chunk) = blob.chunk().await? {
+    ///             // use chunk
+    ///         }
+    ///         println!("now cached at {}", blob.path().unwrap().display());
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn blob_cached<I: AsRepository + ?Sized>(
+        &self,
+        image: &I,
+        digest: &Digest,
+    ) -> Result<CachedBlob, ErrorResponse> {
+        let image = image.as_repository();
+        let store = self.content_store()?;
+        if store.contains(digest).await {
+            return Ok(CachedBlob::Hit(store.blob_path(digest)));
+        }
+
+        let blob = self.blob(image, digest).await?;
+        let tee = store
+            .tee(
+                &format!("unknown-{}", digest),
+                digest,
+                blob,
+                self.source_labels(image),
+                self.verification,
+            )
+            .await?;
+        Ok(CachedBlob::Miss(Box::new(tee)))
+    }
+
+    fn content_store(&self) -> Result<&ContentStore, ErrorResponse> {
+        Ok(self.content_store.as_ref().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "no content store configured",
+            )
+        })?)
+    }
+
+    /// Returns the labels of blobs pulled from `image`.
+    fn source_labels(&self, image: &str) -> HashMap<String, String> {
+        reqwest::Url::parse(&self.api_url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_owned))
+            .map(|host| {
+                let label = format!("{}.{}", store::LABEL_DISTRIBUTION_SOURCE, host);
+                HashMap::from([(label, image.to_owned())])
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns `true` if `url` exists, using a `HEAD` request.
+    async fn exists(&self, url: &str, image: &str, accept: &[&str]) -> Result<bool, ErrorResponse> {
+        let context = || ErrorContext::new(Method::HEAD, url, Some(image), None);
+        in_context(context, async {
+            let mut request = self.client.head(url);
+            if !accept.is_empty() {
+                request = request.header(reqwest::header::ACCEPT, accept.join(", "));
+            }
+            let request = self.authorize(request, Some(image)).await?;
+            let _permit = self.scheduler.acquire(Priority::High).await;
+            let response = self
+                .send_with_retries(request, &self.timeouts.manifests)
+                .await?;
+
+            match response.status() {
+                StatusCode::NOT_FOUND => Ok(false),
+                status if status.is_success() => Ok(true),
+                _ => Err(ErrorResponse::from_response(response).await),
+            }
+        })
+        .await
+    }
+
+    /// Attach a bearer token for a `pull` on `image` to `request`.
+    async fn authorize(
+        &self,
+        request: RequestBuilder,
+        image: Option<&str>,
+    ) -> Result<RequestBuilder, ErrorResponse> {
+        let scope = image.map(|image| Scope::repository(image, "pull"));
+        self.authorize_scope(request, scope.as_ref()).await
+    }
+
+    /// Attach a bearer token for `scope` to `request`.
+    async fn authorize_scope(
+        &self,
+        request: RequestBuilder,
+        scope: Option<&Scope>,
+    ) -> Result<RequestBuilder, ErrorResponse> {
+        if let Some(provider) = &self.token_provider {
+            if let Some(token) = provider.token(scope).await? {
+                return Ok(request.bearer_auth(token));
+            }
+        }
+
+        let credentials = match (&self.auth_token, &self.credentials) {
+            (Some(token), _) => return Ok(request.bearer_auth(token.token())),
+            (None, Some(credentials)) => credentials,
+            (None, None) => return Ok(request),
+        };
+        match (self.token_endpoint().await?, scope) {
+            // Registries without token service use basic authentication.
+            (None, _) => Ok(request.basic_auth(&credentials.username, Some(&credentials.password))),
+            (Some(_), Some(scope)) => {
+                let token = self
+                    .auth(&scope.r#type, &scope.name, &scope.actions)
+                    .await?;
+                Ok(request.bearer_auth(token.token()))
+            }
+            (Some(_), None) => Ok(request),
         }
     }
 
@@ -188,31 +1563,47 @@ impl DockerRegistryClientV2 {
         &self,
         method: Method,
         url: &str,
-        accept: &str,
+        image: Option<&str>,
+        accept: &[&str],
     ) -> Result<T, ErrorResponse> {
-        let mut request = self
-            .client
-            .request(method, url)
-            .header(reqwest::header::ACCEPT, accept);
-
-        if let Some(token) = self.auth_token.clone() {
-            request = request.bearer_auth(token.access_token);
-        }
+        let context = || ErrorContext::new(method.clone(), url, image, None);
+        in_context(context, async {
+            let request = self
+                .client
+                .request(method.clone(), url)
+                .header(reqwest::header::ACCEPT, accept.join(", "));
+            let request = self.authorize(request, image).await?;
 
-        let response = request.send().await?;
+            let response = self
+                .send_with_retries(request, &self.timeouts.manifests)
+                .await?;
 
-        match response.status() {
-            StatusCode::OK => Ok(response.json::<T>().await?),
-            _ => Err(ErrorResponse::APIError(response.json::<ErrorList>().await?)),
-        }
+            match response.status() {
+                StatusCode::OK => Ok(reject_html(response).await?.json::<T>().await?),
+                _ => Err(ErrorResponse::from_response(response).await),
+            }
+        })
+        .await
     }
 }
 
-/// OAuth 2.0 token.
-#[allow(dead_code)]
-#[derive(serde::Deserialize, Clone, Debug)]
-pub struct AuthToken {
-    access_token: String,
-    expires_in: i32,
-    issued_at: String,
+/// Record the request it occurred on in the error of `request`.
+pub(crate) async fn in_context<T>(
+    context: impl FnOnce() -> ErrorContext,
+    request: impl std::future::Future<Output = Result<T, ErrorResponse>>,
+) -> Result<T, ErrorResponse> {
+    request.await.map_err(|err| err.with_context(context()))
+}
+
+/// Returns the error of an unsuccessful blob `response`.
+async fn blob_error(response: reqwest::Response, image: &str, digest: &Digest) -> ErrorResponse {
+    let context = ErrorContext::new(
+        Method::GET,
+        response.url().as_str(),
+        Some(image),
+        Some(&digest.to_string()),
+    );
+    ErrorResponse::from_response(response)
+        .await
+        .with_context(context)
 }
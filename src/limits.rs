@@ -0,0 +1,67 @@
+//! Response size limits.
+//!
+//! Manifests and image configs are read in memory, [`SizeLimits`] bounds
+//! the size of their bodies so that a broken (or hostile) registry can't
+//! exhaust memory. Bodies larger than the limit are rejected with
+//! [`ErrorResponse::BodyTooLarge`], before being downloaded when the
+//! registry announces their length.
+//!
+//! ```
+//! # use oci_registry_client::{limits::SizeLimits, DockerRegistryClientV2};
+//! # let mut client = DockerRegistryClientV2::try_new("registry.docker.io", "https://registry-1.docker.io", "https://auth.docker.io/token").unwrap();
+//! client.set_size_limits(SizeLimits {
+//!     max_manifest_size: 1024 * 1024,
+//!     ..SizeLimits::default()
+//! });
+//! ```
+
+use crate::{
+    errors::ErrorResponse,
+    timeout::{self, TimeoutPhase},
+};
+use bytes::{Bytes, BytesMut};
+use std::time::Duration;
+
+/// Maximum sizes of the bodies read in memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SizeLimits {
+    /// Maximum size of manifests, manifest lists and referrers indexes, in
+    /// bytes (default 4 MiB).
+    pub max_manifest_size: u64,
+    /// Maximum size of image configs, in bytes (default 8 MiB).
+    pub max_config_size: u64,
+}
+
+impl Default for SizeLimits {
+    fn default() -> Self {
+        Self {
+            max_manifest_size: 4 * 1024 * 1024,
+            max_config_size: 8 * 1024 * 1024,
+        }
+    }
+}
+
+/// Read the body of `response`, failing if it is larger than `limit` bytes.
+///
+/// Fails with an idle timeout if no chunk is received for `idle`.
+pub(crate) async fn read_body(
+    mut response: reqwest::Response,
+    limit: u64,
+    idle: Option<Duration>,
+) -> Result<Bytes, ErrorResponse> {
+    if let Some(length) = response.content_length() {
+        if length > limit {
+            return Err(ErrorResponse::body_too_large(limit));
+        }
+    }
+
+    let mut body = BytesMut::new();
+    while let Some(chunk) = timeout::within(idle, TimeoutPhase::Idle, response.chunk()).await?? {
+        if (body.len() + chunk.len()) as u64 > limit {
+            return Err(ErrorResponse::body_too_large(limit));
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(body.freeze())
+}
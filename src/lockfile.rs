@@ -0,0 +1,341 @@
+//! Image lockfiles.
+//!
+//! A [`Lockfile`] records the digests an image reference resolved to (and
+//! the blobs it is made of), so downstream jobs can verify they deploy
+//! exactly the same content.
+//!
+//! ```no_run
+//! use oci_registry_client::{lockfile::Lockfile, DockerRegistryClientV2};
+//!
+//! # async fn example(client: DockerRegistryClientV2) -> Result<(), Box<dyn std::error::Error>> {
+//! let mut lockfile = Lockfile::new();
+//! lockfile.add(client.lock_image("library/alpine", "latest").await?);
+//! lockfile.write_to(std::fs::File::create("images.lock")?)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`DockerRegistryClientV2::lock_image`] resolves the reference again. To
+//! record exactly what was downloaded, use the lock returned by
+//! [`DockerRegistryClientV2::pull_image_locked`] or carried by the results of
+//! [`crate::copy::copy_many`] instead:
+//!
+//! ```no_run
+//! # use oci_registry_client::{lockfile::Lockfile, DockerRegistryClientV2};
+//! # async fn example(client: DockerRegistryClientV2) -> Result<(), Box<dyn std::error::Error>> {
+//! let (_, locked) = client
+//!     .pull_image_locked("library/alpine", "latest", "/tmp/alpine", None)
+//!     .await?;
+//! let mut lockfile = Lockfile::new();
+//! lockfile.add(locked);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Later, [`verify_lock`] reports any drift between the lockfile and the
+//! registry:
+//!
+//! ```no_run
+//! # use oci_registry_client::{lockfile::{verify_lock, Lockfile}, DockerRegistryClientV2};
+//! # async fn example(client: DockerRegistryClientV2) -> Result<(), Box<dyn std::error::Error>> {
+//! let lockfile = Lockfile::read_from(std::fs::File::open("images.lock")?)?;
+//! let report = verify_lock(&lockfile, &client).await?;
+//! for drift in &report.drift {
+//!     eprintln!("{:?}", drift);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    audit::Operation,
+    errors::ErrorResponse,
+    manifest::{Digest, Manifest, ManifestResponse, MediaType, Platform},
+    reference::{AsRepository, AsTagOrDigest},
+    DockerRegistryClientV2,
+};
+use std::io::{Read, Write};
+
+/// Current lockfile format version.
+pub const LOCKFILE_VERSION: u32 = 1;
+
+/// A set of pinned images.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct Lockfile {
+    pub version: u32,
+    pub images: Vec<LockedImage>,
+}
+
+/// An image reference pinned to the digest it resolved to.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LockedImage {
+    pub image: String,
+    pub reference: String,
+    pub digest: Digest,
+    pub media_type: MediaType,
+    /// Image manifests, one per platform for manifest lists.
+    pub manifests: Vec<LockedManifest>,
+}
+
+/// An image manifest and the blobs it references.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LockedManifest {
+    pub digest: Digest,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<LockedPlatform>,
+    pub config: LockedBlob,
+    pub layers: Vec<LockedBlob>,
+}
+
+/// Platform of a [`LockedManifest`].
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct LockedPlatform {
+    pub os: String,
+    pub architecture: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+}
+
+/// A blob referenced by a [`LockedManifest`].
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LockedBlob {
+    pub digest: Digest,
+    pub size: usize,
+    pub media_type: MediaType,
+}
+
+impl Lockfile {
+    /// Returns an empty lockfile.
+    pub fn new() -> Self {
+        Self {
+            version: LOCKFILE_VERSION,
+            images: vec![],
+        }
+    }
+
+    /// Add (or replace) the entry for `image`.
+    pub fn add(&mut self, image: LockedImage) {
+        self.images
+            .retain(|locked| locked.image != image.image || locked.reference != image.reference);
+        self.images.push(image);
+    }
+
+    /// Write this lockfile as JSON to `writer`.
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<(), ErrorResponse> {
+        Ok(serde_json::to_writer_pretty(writer, self)?)
+    }
+
+    /// Read a lockfile from `reader`.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self, ErrorResponse> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+}
+
+impl Default for Lockfile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<&Platform> for LockedPlatform {
+    fn from(platform: &Platform) -> Self {
+        Self {
+            os: platform.os.clone(),
+            architecture: platform.architecture.clone(),
+            variant: platform.variant.clone(),
+        }
+    }
+}
+
+impl LockedManifest {
+    pub(crate) fn new(
+        digest: Digest,
+        platform: Option<LockedPlatform>,
+        manifest: &Manifest,
+    ) -> Self {
+        Self {
+            digest,
+            platform,
+            config: LockedBlob {
+                digest: manifest.config.digest.clone(),
+                size: manifest.config.size,
+                media_type: manifest.config.media_type.clone(),
+            },
+            layers: manifest
+                .layers
+                .iter()
+                .map(|layer| LockedBlob {
+                    digest: layer.digest.clone(),
+                    size: layer.size,
+                    media_type: layer.media_type.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl DockerRegistryClientV2 {
+    /// Resolve `reference` of `image` and record its digests.
+    ///
+    /// For manifest lists every platform manifest is resolved. The reference
+    /// may resolve to another digest than the one pulled or copied before,
+    /// see the [module](crate::lockfile) documentation.
+    pub async fn lock_image<I: AsRepository + ?Sized, T: AsTagOrDigest + ?Sized>(
+        &self,
+        image: &I,
+        reference: &T,
+    ) -> Result<LockedImage, ErrorResponse> {
+        let image = image.as_repository();
+        let reference = &*reference.as_tag_or_digest();
+        let operation = Operation::start("lock", image, reference);
+        let result = self.resolve_lock(image, reference).await;
+        operation.finish(result.as_ref().map(|locked| &locked.digest));
+        result
+    }
+
+    async fn resolve_lock(
+        &self,
+        image: &str,
+        reference: &str,
+    ) -> Result<LockedImage, ErrorResponse> {
+        let (manifest, digest) = self.fetch_manifest(image, reference).await?;
+
+        let (media_type, manifests) = match manifest {
+            ManifestResponse::Image(manifest) => (
+                manifest.media_type_or(MediaType::OciManifest),
+                vec![LockedManifest::new(digest.clone(), None, &manifest)],
+            ),
+            ManifestResponse::List(list) => {
+                let mut manifests = vec![];
+                for item in &list.manifests {
+                    let manifest = self.manifest(image, &item.digest.to_string()).await?;
+                    manifests.push(LockedManifest::new(
+                        item.digest.clone(),
+                        item.platform.as_ref().map(LockedPlatform::from),
+                        &manifest,
+                    ));
+                }
+                (list.media_type_or(MediaType::OciImageIndex), manifests)
+            }
+            other => {
+                return Err(ErrorResponse::unsupported_media_type(
+                    other.media_type().to_string(),
+                ))
+            }
+        };
+
+        Ok(LockedImage {
+            image: image.to_owned(),
+            reference: reference.to_owned(),
+            digest,
+            media_type,
+            manifests,
+        })
+    }
+}
+
+/// Difference between a [`Lockfile`] and the registry contents.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Drift {
+    /// The reference no longer exists.
+    ReferenceMissing { image: String, reference: String },
+    /// The reference resolves to a different digest.
+    DigestChanged {
+        image: String,
+        reference: String,
+        locked: Digest,
+        current: Digest,
+    },
+    /// A pinned manifest no longer exists.
+    ManifestMissing { image: String, digest: Digest },
+    /// A pinned blob (config or layer) no longer exists.
+    BlobMissing { image: String, digest: Digest },
+}
+
+/// Result of [`verify_lock`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LockReport {
+    pub drift: Vec<Drift>,
+}
+
+impl LockReport {
+    /// Returns `true` if the registry matches the lockfile.
+    pub fn is_clean(&self) -> bool {
+        self.drift.is_empty()
+    }
+}
+
+/// Check that every image of `lockfile` still resolves to its pinned digest
+/// and that all pinned manifests and blobs still exist.
+pub async fn verify_lock(
+    lockfile: &Lockfile,
+    client: &DockerRegistryClientV2,
+) -> Result<LockReport, ErrorResponse> {
+    let mut report = LockReport::default();
+
+    for locked in &lockfile.images {
+        let current = match client.manifest_head(&locked.image, &locked.reference).await {
+            Ok(head) => match head.digest {
+                Some(digest) => Some(digest),
+                // The registry did not announce the digest, compute it.
+                None => Some(
+                    client
+                        .manifest_raw(&locked.image, &locked.reference)
+                        .await?
+                        .2,
+                ),
+            },
+            Err(ErrorResponse::NotFound { .. }) => None,
+            Err(err) => return Err(err),
+        };
+        match current {
+            None => report.drift.push(Drift::ReferenceMissing {
+                image: locked.image.clone(),
+                reference: locked.reference.clone(),
+            }),
+            Some(current) if current != locked.digest => report.drift.push(Drift::DigestChanged {
+                image: locked.image.clone(),
+                reference: locked.reference.clone(),
+                locked: locked.digest.clone(),
+                current,
+            }),
+            Some(_) => {}
+        }
+
+        for manifest in &locked.manifests {
+            let url = format!(
+                "{}/v2/{}/manifests/{}",
+                client.api_url, locked.image, manifest.digest
+            );
+            if !client
+                .exists(&url, &locked.image, &[client.manifest_accept.as_str()])
+                .await?
+            {
+                report.drift.push(Drift::ManifestMissing {
+                    image: locked.image.clone(),
+                    digest: manifest.digest.clone(),
+                });
+            }
+
+            let blobs = std::iter::once(&manifest.config).chain(
+                manifest
+                    .layers
+                    .iter()
+                    .filter(|layer| !layer.media_type.is_foreign()),
+            );
+            for blob in blobs {
+                if !client.blob_exists(&locked.image, &blob.digest).await? {
+                    report.drift.push(Drift::BlobMissing {
+                        image: locked.image.clone(),
+                        digest: blob.digest.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
@@ -1,5 +1,5 @@
 use oci_registry_client::{
-    manifest::{Digest, Layer},
+    manifest::{host_architecture, Digest, Layer},
     DockerRegistryClientV2,
 };
 use std::error::Error;
@@ -17,28 +17,27 @@ struct DownloadProgressReport {
 
 async fn download_layer(
     n: usize,
-    digest: Digest,
     layer: Layer,
     client: DockerRegistryClientV2,
     tx: mpsc::UnboundedSender<DownloadProgressReport>,
 ) -> Result<(), Box<dyn Error + Send>> {
-    let mut blob = client.blob("library/alpine", &layer.digest).await.unwrap();
-    let total = blob.len();
-    let mut downloaded = 0usize;
-    let mut out_file = File::create(format!("/tmp/{}.tar.gz", layer.digest)).unwrap();
-
-    while let Some(chunk) = blob.chunk().await.unwrap() {
-        downloaded += chunk.len();
-        if let Some(total) = total {
+    let digest = layer.digest.clone();
+    let mut blob = client
+        .blob("library/alpine", &layer.digest)
+        .await
+        .unwrap()
+        .on_progress(move |downloaded, total| {
             tx.send(DownloadProgressReport {
                 n,
                 digest: digest.clone(),
-                downloaded,
-                total,
+                downloaded: downloaded as usize,
+                total: total.map_or(layer.size, |total| total as usize),
             })
             .unwrap();
-        }
+        });
+    let mut out_file = File::create(format!("/tmp/{}.tar.gz", layer.digest)).unwrap();
 
+    while let Some(chunk) = blob.chunk().await.unwrap() {
         out_file.write_all(&chunk).unwrap();
     }
 
@@ -59,52 +58,32 @@ impl LayerDownloadStatus {
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let mut client = DockerRegistryClientV2::new(
+    let mut client = DockerRegistryClientV2::try_new(
         "registry.docker.io",
         "https://registry-1.docker.io",
         "https://auth.docker.io/token",
-    );
+    )?;
     let response = client.auth("repository", "library/alpine", "pull").await;
     if let Ok(token) = response {
         client.set_auth_token(Some(token));
     }
 
     let manifest_list = client.list_manifests("library/alpine", "latest").await?;
-
     for manifest in &manifest_list.manifests {
         println!("{:?}", manifest);
-        if manifest.platform.architecture == "amd64" && manifest.platform.os == "linux" {
-            let response = client
-                .manifest("library/alpine", &manifest.digest.to_string())
-                .await?;
-
-            println!("response: {:?}", response);
-        }
     }
 
-    let response = client.manifest("library/alpine", "latest").await?;
+    let response = client
+        .manifest_for_platform("library/alpine", "latest", "linux", host_architecture())
+        .await?;
 
     let (tx, mut rx) = mpsc::unbounded_channel::<DownloadProgressReport>();
 
     let mut layers_status = vec![];
 
-    for (n, layer) in response.layers.iter().cloned().enumerate() {
-        let client = client.clone();
-        if response.layers[0..n]
-            .iter()
-            .any(|l| l.digest == layer.digest)
-        {
-            continue;
-        }
-
+    for (n, layer) in response.unique_layers().cloned().enumerate() {
         layers_status.push(LayerDownloadStatus::Unknown(layer.digest.clone()));
-        tokio::spawn(download_layer(
-            layers_status.len() - 1,
-            layer.digest.clone(),
-            layer.clone(),
-            client.clone(),
-            tx.clone(),
-        ));
+        tokio::spawn(download_layer(n, layer, client.clone(), tx.clone()));
     }
 
     loop {
@@ -3,72 +3,798 @@
 //! See [Imag Manifest V2, Schema 2](https://docs.docker.com/registry/spec/manifest-v2-2/)
 //! for more details.
 
+use crate::{
+    errors::ErrorResponse, MEDIA_TYPE_IMAGE_CONFIG, MEDIA_TYPE_MANIFEST_LIST_V2,
+    MEDIA_TYPE_MANIFEST_V1, MEDIA_TYPE_MANIFEST_V1_SIGNED, MEDIA_TYPE_MANIFEST_V2,
+    MEDIA_TYPE_OCI_IMAGE_CONFIG, MEDIA_TYPE_OCI_IMAGE_INDEX_V1, MEDIA_TYPE_OCI_MANIFEST_V1,
+};
+use bytes::Bytes;
 use serde::{de, ser};
 use sha2::digest::generic_array::{typenum, GenericArray};
+use sha2::{Digest as Sha256Digest, Sha256};
 use std::{collections::HashMap, error::Error, fmt, str};
 
 /// The [`ManifestList`] is the "fat manifest" which points
 /// to specific image manifests for one or more platforms.
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
+///
+/// Represents both Docker manifest lists and [OCI image
+/// indexes](https://github.com/opencontainers/image-spec/blob/main/image-index.md),
+/// OCI indexes may omit `mediaType`.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ManifestList {
     pub schema_version: i32,
-    pub media_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<MediaType>,
     pub manifests: Vec<ManifestItem>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<String>,
+    /// The manifest this one refers to (signatures, SBOMs, ...).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<ManifestItem>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<HashMap<String, String>>,
+}
+
+impl ManifestList {
+    /// Returns the entries of this list, skipping attestation manifests.
+    pub fn platform_manifests(&self) -> impl Iterator<Item = &ManifestItem> {
+        self.manifests.iter().filter(|item| !item.is_attestation())
+    }
+
+    /// Returns the media type of this list, or `default` if it is not set.
+    pub fn media_type_or(&self, default: MediaType) -> MediaType {
+        self.media_type.clone().unwrap_or(default)
+    }
+
+    /// Returns the entry of the `os`/`architecture` platform. A variant may
+    /// be given with the architecture (`arm/v7`), otherwise the first entry
+    /// of the architecture is returned.
+    ///
+    /// See [`host_architecture`] to select the platform of the host.
+    pub fn find_platform(&self, os: &str, architecture: &str) -> Option<&ManifestItem> {
+        let (architecture, variant) = match architecture.split_once('/') {
+            Some((architecture, variant)) => (architecture, Some(variant)),
+            None => (architecture, None),
+        };
+        self.platform_manifests().find(|item| match &item.platform {
+            Some(platform) => {
+                platform.os == os
+                    && platform.architecture == architecture
+                    && (variant.is_none() || platform.variant.as_deref() == variant)
+            }
+            None => false,
+        })
+    }
+
+    /// Returns this list as an OCI image index, see [`MediaType::to_oci`].
+    ///
+    /// Only media types are translated, the digests (and sizes) of
+    /// translated manifests must be updated by the caller.
+    pub fn to_oci(&self) -> ManifestList {
+        self.convert(ManifestFormat::Oci)
+    }
+
+    /// Returns this list as a Docker manifest list, see
+    /// [`MediaType::to_docker`].
+    ///
+    /// Only media types are translated, the digests (and sizes) of
+    /// translated manifests must be updated by the caller.
+    pub fn to_docker(&self) -> ManifestList {
+        self.convert(ManifestFormat::Docker)
+    }
+
+    /// Returns this list with media types translated to `format`.
+    ///
+    /// Docker manifest lists have no `artifactType`, `subject` or
+    /// `annotations`, they are dropped when translating to
+    /// [`ManifestFormat::Docker`].
+    pub fn convert(&self, format: ManifestFormat) -> ManifestList {
+        let mut list = self.clone();
+        list.media_type = Some(format.convert(&self.media_type_or(MediaType::OciImageIndex)));
+        if format == ManifestFormat::Docker {
+            list.artifact_type = None;
+            list.subject = None;
+            list.annotations = None;
+        }
+        for item in list.manifests.iter_mut() {
+            item.media_type = format.convert(&item.media_type);
+        }
+        list
+    }
+}
+
+/// An [OCI image
+/// index](https://github.com/opencontainers/image-spec/blob/main/image-index.md).
+///
+/// Unlike [`ManifestList`], which accepts both dialects, an index always
+/// has the OCI media types, and carries the OCI-only properties. Lists are
+/// converted to indexes (and back) with `From`:
+///
+/// ```
+/// # use oci_registry_client::manifest::{ImageIndex, ManifestList, MediaType};
+/// let list: ManifestList = serde_json::from_str(r#"{
+///     "schemaVersion": 2,
+///     "mediaType": "application/vnd.docker.distribution.manifest.list.v2+json",
+///     "manifests": [{
+///         "mediaType": "application/vnd.docker.distribution.manifest.v2+json",
+///         "size": 528,
+///         "digest": "sha256:0000000000000000000000000000000000000000000000000000000000000000"
+///     }]
+/// }"#).unwrap();
+///
+/// let index = ImageIndex::from(list);
+/// assert_eq!(index.media_type, MediaType::OciImageIndex);
+/// assert_eq!(index.manifests[0].media_type, MediaType::OciManifest);
+///
+/// let list = ManifestList::from(index).to_docker();
+/// assert_eq!(list.media_type, Some(MediaType::DockerManifestList));
+/// ```
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageIndex {
+    pub schema_version: i32,
+    #[serde(default = "ImageIndex::default_media_type")]
+    pub media_type: MediaType,
+    pub manifests: Vec<Descriptor>,
+    /// Type of the artifact, for indexes of artifacts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<String>,
+    /// The manifest this index refers to (signatures, SBOMs, ...).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<Descriptor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<HashMap<String, String>>,
+}
+
+impl ImageIndex {
+    /// Returns an empty index.
+    pub fn new() -> Self {
+        Self {
+            schema_version: 2,
+            media_type: MediaType::OciImageIndex,
+            manifests: Vec::new(),
+            artifact_type: None,
+            subject: None,
+            annotations: None,
+        }
+    }
+
+    fn default_media_type() -> MediaType {
+        MediaType::OciImageIndex
+    }
+}
+
+impl Default for ImageIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<ManifestList> for ImageIndex {
+    /// Returns `list` as an index, media types are translated to OCI.
+    fn from(list: ManifestList) -> Self {
+        let list = list.to_oci();
+        Self {
+            schema_version: list.schema_version,
+            media_type: list.media_type_or(MediaType::OciImageIndex),
+            manifests: list.manifests,
+            artifact_type: list.artifact_type,
+            subject: list.subject,
+            annotations: list.annotations,
+        }
+    }
 }
 
-/// [`ManifestItem`] for a specific platform.
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
+impl From<ImageIndex> for ManifestList {
+    /// Returns `index` as a list, see [`ManifestList::to_docker`] to get a
+    /// Docker manifest list.
+    fn from(index: ImageIndex) -> Self {
+        Self {
+            schema_version: index.schema_version,
+            media_type: Some(index.media_type),
+            manifests: index.manifests,
+            artifact_type: index.artifact_type,
+            subject: index.subject,
+            annotations: index.annotations,
+        }
+    }
+}
+
+/// A [`Descriptor`] of a manifest, in a [`ManifestList`], for a specific
+/// platform.
+///
+/// OCI index entries are not required to describe a platform.
+pub type ManifestItem = Descriptor;
+
+/// An [OCI content
+/// descriptor](https://github.com/opencontainers/image-spec/blob/main/descriptor.md),
+/// referencing a manifest or blob by digest.
+///
+/// Used for manifest list entries, configs and layers, optional properties
+/// only apply to some of them.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
-pub struct ManifestItem {
-    pub media_type: String,
+pub struct Descriptor {
+    pub media_type: MediaType,
     pub size: usize,
     pub digest: Digest,
-    pub platform: Platform,
+    /// Platform of the referenced manifest, in manifest lists.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>,
+    /// Locations the content can be downloaded from, for foreign
+    /// (non-distributable) layers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub urls: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<HashMap<String, String>>,
+    /// Embedded content, base64 encoded in JSON. It is used instead of
+    /// fetching the content, once checked against `digest`.
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "base64_data")]
+    pub data: Option<Bytes>,
+}
+
+impl Descriptor {
+    /// Returns a descriptor of the `size` bytes content identified by
+    /// `digest`, without optional properties.
+    pub fn new<T: Into<MediaType>>(media_type: T, digest: Digest, size: usize) -> Self {
+        Self {
+            media_type: media_type.into(),
+            size,
+            digest,
+            platform: None,
+            urls: None,
+            artifact_type: None,
+            annotations: None,
+            data: None,
+        }
+    }
+
+    /// Returns the descriptor of the empty JSON object (`{}`), used as the
+    /// config of artifact manifests.
+    ///
+    /// The blob must still be pushed, see [`crate::push::BlobSource::from_bytes`].
+    ///
+    /// ```
+    /// # use oci_registry_client::manifest::ManifestConfig;
+    /// let config = ManifestConfig::empty();
+    /// assert_eq!(config.size, 2);
+    /// assert_eq!(
+    ///     config.digest.to_string(),
+    ///     "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+    /// );
+    /// ```
+    pub fn empty() -> Self {
+        Self::new(
+            MediaType::OciEmpty,
+            Digest::from_sha256(Sha256::digest(b"{}")),
+            2,
+        )
+    }
+
+    /// Returns `true` if this manifest list entry is a buildx attestation
+    /// manifest rather than the image of a platform.
+    ///
+    /// ```
+    /// # use oci_registry_client::manifest::ManifestList;
+    /// let list: ManifestList = serde_json::from_str(r#"{
+    ///     "schemaVersion": 2,
+    ///     "mediaType": "application/vnd.oci.image.index.v1+json",
+    ///     "manifests": [{
+    ///         "mediaType": "application/vnd.oci.image.manifest.v1+json",
+    ///         "digest": "sha256:a3ed95caeb02ffe68cdd9fd84406680ae93d633cb16422d00e8a7c22955b46d4",
+    ///         "size": 566,
+    ///         "platform": {"architecture": "amd64", "os": "linux"}
+    ///     }, {
+    ///         "mediaType": "application/vnd.oci.image.manifest.v1+json",
+    ///         "digest": "sha256:5f70bf18a086007016e948b04aed3b82103a36bea41755b6cddfaf10ace3c6ef",
+    ///         "size": 839,
+    ///         "annotations": {
+    ///             "vnd.docker.reference.digest": "sha256:a3ed95caeb02ffe68cdd9fd84406680ae93d633cb16422d00e8a7c22955b46d4",
+    ///             "vnd.docker.reference.type": "attestation-manifest"
+    ///         },
+    ///         "platform": {"architecture": "unknown", "os": "unknown"}
+    ///     }]
+    /// }"#).unwrap();
+    ///
+    /// assert!(list.manifests[1].is_attestation());
+    /// assert_eq!(list.platform_manifests().count(), 1);
+    /// ```
+    pub fn is_attestation(&self) -> bool {
+        let annotated = self
+            .annotations
+            .as_ref()
+            .and_then(|annotations| annotations.get(ANNOTATION_REFERENCE_TYPE))
+            .map(|kind| kind == "attestation-manifest")
+            .unwrap_or(false);
+        let unknown = self
+            .platform
+            .as_ref()
+            .map(|platform| platform.os == "unknown" && platform.architecture == "unknown")
+            .unwrap_or(false);
+        annotated || unknown
+    }
+
+    /// Returns `true` if the content is foreign (non-distributable):
+    /// registries are not expected to store it, it is downloaded from its
+    /// `urls`.
+    pub fn is_foreign(&self) -> bool {
+        self.media_type.is_foreign()
+    }
 }
 
 /// The [`Platform`] describes the platform which the image in the
 /// manifest runs on.
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
+///
+/// Attestation manifests of buildx indexes use the `unknown/unknown`
+/// platform, see [`Descriptor::is_attestation`].
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
 pub struct Platform {
+    #[serde(default)]
     pub architecture: String,
+    #[serde(default)]
     pub os: String,
+    #[serde(
+        rename = "os.version",
+        alias = "osVersion",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub os_version: Option<String>,
+    #[serde(
+        rename = "os.features",
+        alias = "osFeatures",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub os_features: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub variant: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub features: Option<Vec<String>>,
 }
 
+/// Returns the OCI name of the architecture of the host (example: `amd64`).
+pub fn host_architecture() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "x86" => "386",
+        "aarch64" => "arm64",
+        "powerpc64" => "ppc64le",
+        arch => arch,
+    }
+}
+
+/// Annotation of buildx index entries giving the kind of referrer.
+pub const ANNOTATION_REFERENCE_TYPE: &str = "vnd.docker.reference.type";
+/// Annotation of buildx attestation entries giving the digest of the
+/// manifest they describe.
+pub const ANNOTATION_REFERENCE_DIGEST: &str = "vnd.docker.reference.digest";
+
 /// The [`Manifest`] provides a configuration and a set of layers for a
 /// container image.
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
+///
+/// Represents both Docker (schema 2) and [OCI image
+/// manifests](https://github.com/opencontainers/image-spec/blob/main/manifest.md),
+/// OCI manifests may omit `mediaType`.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Manifest {
     pub schema_version: i32,
-    pub media_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<MediaType>,
     pub config: ManifestConfig,
     pub layers: Vec<Layer>,
+    /// Type of the artifact described by this manifest, when it is not a
+    /// container image.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<String>,
+    /// The manifest this one refers to (signatures, SBOMs, ...).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<ManifestItem>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<HashMap<String, String>>,
 }
 
-/// The [`ManifestConfig`] references a configuration object for a container.
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct ManifestConfig {
-    pub media_type: String,
-    pub size: usize,
+impl Manifest {
+    /// Returns the media type of this manifest, or `default` if it is not set.
+    pub fn media_type_or(&self, default: MediaType) -> MediaType {
+        self.media_type.clone().unwrap_or(default)
+    }
+
+    /// Returns the layers of this manifest, without the repetitions of a
+    /// layer (images may contain the same layer several times, it only needs
+    /// to be downloaded once).
+    pub fn unique_layers(&self) -> impl Iterator<Item = &Layer> {
+        let mut seen = std::collections::HashSet::new();
+        self.layers
+            .iter()
+            .filter(move |layer| seen.insert(&layer.digest))
+    }
+
+    /// Returns this manifest as an OCI image manifest, see
+    /// [`MediaType::to_oci`].
+    ///
+    /// The config and layers are unchanged, Docker and OCI configs share the
+    /// same schema and only differ by media type.
+    ///
+    /// ```
+    /// # use oci_registry_client::manifest::{Manifest, MediaType};
+    /// let manifest: Manifest = serde_json::from_str(r#"{
+    ///     "schemaVersion": 2,
+    ///     "mediaType": "application/vnd.docker.distribution.manifest.v2+json",
+    ///     "config": {
+    ///         "mediaType": "application/vnd.docker.container.image.v1+json",
+    ///         "size": 1472,
+    ///         "digest": "sha256:a3ed95caeb02ffe68cdd9fd84406680ae93d633cb16422d00e8a7c22955b46d4"
+    ///     },
+    ///     "layers": [{
+    ///         "mediaType": "application/vnd.docker.image.rootfs.diff.tar.gzip",
+    ///         "size": 3408729,
+    ///         "digest": "sha256:5f70bf18a086007016e948b04aed3b82103a36bea41755b6cddfaf10ace3c6ef"
+    ///     }]
+    /// }"#).unwrap();
+    ///
+    /// let oci = manifest.to_oci();
+    /// assert_eq!(oci.media_type, Some(MediaType::OciManifest));
+    /// assert_eq!(oci.config.media_type, MediaType::OciImageConfig);
+    /// assert_eq!(oci.layers[0].media_type, MediaType::OciLayerGzip);
+    /// assert_eq!(oci.to_docker().layers[0].media_type, MediaType::DockerLayerGzip);
+    /// ```
+    pub fn to_oci(&self) -> Manifest {
+        self.convert(ManifestFormat::Oci)
+    }
+
+    /// Returns this manifest as a Docker (schema 2) image manifest, see
+    /// [`MediaType::to_docker`].
+    pub fn to_docker(&self) -> Manifest {
+        self.convert(ManifestFormat::Docker)
+    }
+
+    /// Returns this manifest with media types translated to `format`.
+    ///
+    /// Docker image manifests have no `artifactType`, `subject` or
+    /// `annotations`, they are dropped when translating to
+    /// [`ManifestFormat::Docker`].
+    pub fn convert(&self, format: ManifestFormat) -> Manifest {
+        let mut manifest = self.clone();
+        manifest.media_type = Some(format.convert(&self.media_type_or(MediaType::OciManifest)));
+        if format == ManifestFormat::Docker {
+            manifest.artifact_type = None;
+            manifest.subject = None;
+            manifest.annotations = None;
+        }
+        manifest.config.media_type = format.convert(&manifest.config.media_type);
+        for layer in manifest.layers.iter_mut() {
+            layer.media_type = format.convert(&layer.media_type);
+        }
+        manifest
+    }
+}
+
+/// A family of media types, manifests can be translated between them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ManifestFormat {
+    /// Docker (schema 2) manifests and manifest lists.
+    Docker,
+    /// OCI image manifests and indexes.
+    Oci,
+}
+
+impl ManifestFormat {
+    /// Returns `media_type` translated to this format.
+    pub fn convert(self, media_type: &MediaType) -> MediaType {
+        match self {
+            Self::Docker => media_type.to_docker(),
+            Self::Oci => media_type.to_oci(),
+        }
+    }
+}
+
+/// A manifest of any supported kind, as returned by
+/// [`crate::DockerRegistryClientV2::get_manifest`].
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+#[allow(clippy::large_enum_variant)]
+pub enum ManifestResponse {
+    /// A Docker manifest list or OCI image index.
+    List(ManifestList),
+    /// A Docker (schema 2) or OCI image manifest.
+    Image(Manifest),
+    /// A legacy Docker (schema 1) image manifest.
+    Schema1(Schema1Manifest),
+    /// A manifest of a kind (or shape) this crate does not model, returned
+    /// by [`crate::DockerRegistryClientV2::get_manifest`] when strict parsing
+    /// fails.
+    Raw(RawManifest),
+}
+
+/// A manifest kept as raw JSON, see [`ManifestResponse::Raw`].
+#[derive(Clone, Debug)]
+pub struct RawManifest {
+    pub value: serde_json::Value,
+    /// `Content-Type` of the response.
+    pub content_type: Option<String>,
     pub digest: Digest,
 }
 
-/// The [`Layer`] references a [`crate::blob::Blob`] by digest.
+impl RawManifest {
+    /// Returns the media type of this manifest, from its content type or its
+    /// `mediaType` field.
+    pub fn media_type(&self) -> MediaType {
+        let content_type = self
+            .content_type
+            .as_deref()
+            .and_then(|value| value.split(';').next())
+            .map(str::trim)
+            .filter(|value| !value.is_empty() && *value != "application/json");
+        let media_type = self.value.get("mediaType").and_then(|value| value.as_str());
+
+        MediaType::from(content_type.or(media_type).unwrap_or_default())
+    }
+}
+
+impl ManifestResponse {
+    /// Returns the media type of this manifest.
+    pub fn media_type(&self) -> MediaType {
+        match self {
+            Self::List(list) => list.media_type_or(MediaType::OciImageIndex),
+            Self::Image(manifest) => manifest.media_type_or(MediaType::OciManifest),
+            Self::Schema1(_) => MediaType::DockerManifestV1Signed,
+            Self::Raw(raw) => raw.media_type(),
+        }
+    }
+
+    /// Parse a manifest `body` according to its `content_type`.
+    ///
+    /// When the content type is missing or generic, the `mediaType` field
+    /// (or the shape of the document) is used instead.
+    pub(crate) fn from_slice(
+        content_type: Option<&str>,
+        body: &[u8],
+    ) -> Result<Self, ErrorResponse> {
+        let content_type = content_type
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim())
+            .unwrap_or_default();
+
+        match content_type {
+            MEDIA_TYPE_MANIFEST_LIST_V2 | MEDIA_TYPE_OCI_IMAGE_INDEX_V1 => {
+                Ok(Self::List(serde_json::from_slice(body)?))
+            }
+            MEDIA_TYPE_MANIFEST_V2 | MEDIA_TYPE_OCI_MANIFEST_V1 => {
+                Ok(Self::Image(serde_json::from_slice(body)?))
+            }
+            MEDIA_TYPE_MANIFEST_V1 | MEDIA_TYPE_MANIFEST_V1_SIGNED => {
+                Ok(Self::Schema1(serde_json::from_slice(body)?))
+            }
+            "" | "application/json" | "application/octet-stream" | "text/plain" => {
+                let value: serde_json::Value = serde_json::from_slice(body)?;
+                let media_type = value.get("mediaType").and_then(|v| v.as_str());
+
+                match media_type {
+                    Some(MEDIA_TYPE_MANIFEST_LIST_V2 | MEDIA_TYPE_OCI_IMAGE_INDEX_V1) => {
+                        Ok(Self::List(serde_json::from_value(value)?))
+                    }
+                    Some(MEDIA_TYPE_MANIFEST_V2 | MEDIA_TYPE_OCI_MANIFEST_V1) => {
+                        Ok(Self::Image(serde_json::from_value(value)?))
+                    }
+                    Some(media_type) => {
+                        Err(ErrorResponse::unsupported_media_type(media_type.to_owned()))
+                    }
+                    None if value.get("manifests").is_some() => {
+                        Ok(Self::List(serde_json::from_value(value)?))
+                    }
+                    None if value.get("schemaVersion").and_then(|v| v.as_i64()) == Some(1) => {
+                        Ok(Self::Schema1(serde_json::from_value(value)?))
+                    }
+                    None => Ok(Self::Image(serde_json::from_value(value)?)),
+                }
+            }
+            media_type => Err(ErrorResponse::unsupported_media_type(media_type.to_owned())),
+        }
+    }
+}
+
+/// A legacy [Image Manifest V2, Schema
+/// 1](https://docs.docker.com/registry/spec/manifest-v2-1/).
+///
+/// Layers are listed from the top-most to the base layer, each with the
+/// matching v1 image JSON in `history`.
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
-pub struct Layer {
-    pub media_type: String,
-    pub size: usize,
-    pub digest: Digest,
+pub struct Schema1Manifest {
+    pub schema_version: i32,
+    pub name: String,
+    pub tag: String,
+    pub architecture: String,
+    pub fs_layers: Vec<FsLayer>,
+    pub history: Vec<V1History>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signatures: Option<Vec<serde_json::Value>>,
+}
+
+/// A layer of a [`Schema1Manifest`].
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FsLayer {
+    pub blob_sum: Digest,
+}
+
+/// A history entry of a [`Schema1Manifest`], holding the v1 image JSON as a
+/// string.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct V1History {
+    pub v1_compatibility: String,
+}
+
+impl V1History {
+    /// Parse the v1 image JSON.
+    pub fn parse(&self) -> Result<V1Compatibility, ErrorResponse> {
+        Ok(serde_json::from_str(&self.v1_compatibility)?)
+    }
+}
+
+/// The v1 image JSON of a [`Schema1Manifest`] layer.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct V1Compatibility {
+    pub id: String,
+    pub parent: Option<String>,
+    pub created: Option<String>,
+    pub author: Option<String>,
+    pub comment: Option<String>,
+    pub architecture: Option<String>,
+    pub os: Option<String>,
+    pub config: Option<ImageConfig>,
+    pub container_config: Option<V1ContainerConfig>,
+    /// `true` if the layer is empty (metadata only).
+    #[serde(default)]
+    pub throwaway: bool,
+}
+
+/// Container configuration of a [`V1Compatibility`], used to describe how a
+/// layer was created.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct V1ContainerConfig {
+    pub cmd: Option<Vec<String>>,
+}
+
+impl Schema1Manifest {
+    /// Convert this manifest to a schema 2 like [`Manifest`] and its
+    /// [`Image`] configuration.
+    ///
+    /// Layers are ordered from the base to the top-most layer and empty
+    /// (throwaway) layers are dropped. Schema 1 does not record layer sizes
+    /// nor uncompressed digests, so layer `size` is `0` and
+    /// `rootfs.diff_ids` is empty. The config is not stored in the registry,
+    /// its digest is computed over its JSON serialization.
+    ///
+    /// ```
+    /// # use oci_registry_client::manifest::Schema1Manifest;
+    /// let manifest: Schema1Manifest = serde_json::from_str(r#"{
+    ///     "schemaVersion": 1, "name": "library/hello", "tag": "latest", "architecture": "amd64",
+    ///     "fsLayers": [
+    ///         {"blobSum": "sha256:a3ed95caeb02ffe68cdd9fd84406680ae93d633cb16422d00e8a7c22955b46d4"},
+    ///         {"blobSum": "sha256:2b3b2f1d3f37bb8a4b8b37b0fa6b8c1a0b8b1c2d3e4f5a6b7c8d9e0f1a2b3c4d"}
+    ///     ],
+    ///     "history": [
+    ///         {"v1Compatibility": "{\"id\":\"b\",\"parent\":\"a\",\"throwaway\":true}"},
+    ///         {"v1Compatibility": "{\"id\":\"a\",\"os\":\"linux\"}"}
+    ///     ]
+    /// }"#).unwrap();
+    ///
+    /// let (manifest, image) = manifest.to_schema2().unwrap();
+    /// assert_eq!(manifest.layers.len(), 1);
+    /// assert_eq!(image.history.unwrap().len(), 2);
+    /// ```
+    pub fn to_schema2(&self) -> Result<(Manifest, Image), ErrorResponse> {
+        let history = self
+            .history
+            .iter()
+            .map(V1History::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut layers = vec![];
+        let mut layer_history = vec![];
+        for (fs_layer, v1) in self.fs_layers.iter().zip(history.iter()).rev() {
+            if !v1.throwaway {
+                layers.push(Layer::new(
+                    MediaType::DockerLayerGzip,
+                    fs_layer.blob_sum.clone(),
+                    0,
+                ));
+            }
+
+            layer_history.push(LayerHistory {
+                created: v1.created.clone(),
+                author: v1.author.clone(),
+                created_by: v1
+                    .container_config
+                    .as_ref()
+                    .and_then(|config| config.cmd.as_ref())
+                    .map(|cmd| cmd.join(" ")),
+                comment: v1.comment.clone(),
+                empty_layer: Some(v1.throwaway),
+            });
+        }
+
+        let top = history.first();
+        let image = Image {
+            architecture: top
+                .and_then(|v1| v1.architecture.clone())
+                .unwrap_or_else(|| self.architecture.clone()),
+            os: top
+                .and_then(|v1| v1.os.clone())
+                .unwrap_or_else(|| "linux".to_owned()),
+            created: top.and_then(|v1| v1.created.clone()),
+            author: top.and_then(|v1| v1.author.clone()),
+            config: top.and_then(|v1| v1.config.clone()),
+            rootfs: RootFS {
+                r#type: "layers".to_owned(),
+                diff_ids: vec![],
+            },
+            history: Some(layer_history),
+        };
+
+        let config = serde_json::to_vec(&image)?;
+        let manifest = Manifest {
+            schema_version: 2,
+            media_type: Some(MediaType::DockerManifest),
+            config: ManifestConfig::new(
+                MediaType::DockerImageConfig,
+                Digest::from_sha256(Sha256::digest(&config)),
+                config.len(),
+            ),
+            layers,
+            artifact_type: None,
+            subject: None,
+            annotations: None,
+        };
+
+        Ok((manifest, image))
+    }
+}
+
+/// (De)serialize the `data` of descriptors as base64.
+mod base64_data {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use bytes::Bytes;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        data: &Option<Bytes>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match data {
+            Some(data) => serializer.serialize_str(&STANDARD.encode(data)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Bytes>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(data) => STANDARD
+                .decode(data)
+                .map(|data| Some(data.into()))
+                .map_err(de::Error::custom),
+            None => Ok(None),
+        }
+    }
 }
 
+/// Media type of the empty config of OCI artifact manifests.
+pub const MEDIA_TYPE_OCI_EMPTY_V1: &str = "application/vnd.oci.empty.v1+json";
+
+/// The [`ManifestConfig`] references a configuration object for a container.
+pub type ManifestConfig = Descriptor;
+
+/// The [`Layer`] references a [`crate::blob::Blob`] by digest.
+pub type Layer = Descriptor;
+
 /// Image configuration.
 ///
 /// Describes some basic information about the image such as date
@@ -86,6 +812,57 @@ pub struct Image {
     pub history: Option<Vec<LayerHistory>>,
 }
 
+impl Image {
+    /// Returns the chain ID of every layer of this image, see [`chain_ids`].
+    pub fn chain_ids(&self) -> Vec<Digest> {
+        chain_ids(self)
+    }
+}
+
+/// Returns the [chain
+/// ID](https://github.com/opencontainers/image-spec/blob/main/config.md#layer-chainid)
+/// of every layer of `image`, from the base to the top-most layer.
+///
+/// The chain ID at index `n` identifies the filesystem obtained by applying
+/// layers `0..=n`, it is the key containerd uses for the matching snapshot.
+pub fn chain_ids(image: &Image) -> Vec<Digest> {
+    let mut chain_ids: Vec<Digest> = Vec::with_capacity(image.rootfs.diff_ids.len());
+    for diff_id in &image.rootfs.diff_ids {
+        let chain_id = match chain_ids.last() {
+            Some(parent) => chain(parent, diff_id),
+            None => diff_id.clone(),
+        };
+        chain_ids.push(chain_id);
+    }
+    chain_ids
+}
+
+/// Returns the chain ID of the layers identified by `diff_ids` (base layer
+/// first), or `None` if `diff_ids` is empty.
+///
+/// ```
+/// # use oci_registry_client::manifest::{chain_id, Digest};
+/// let diff_ids: Vec<Digest> = vec![
+///     "sha256:a3ed95caeb02ffe68cdd9fd84406680ae93d633cb16422d00e8a7c22955b46d4".parse().unwrap(),
+///     "sha256:5f70bf18a086007016e948b04aed3b82103a36bea41755b6cddfaf10ace3c6ef".parse().unwrap(),
+/// ];
+/// assert_eq!(chain_id(&diff_ids[..1]), Some(diff_ids[0].clone()));
+/// assert_ne!(chain_id(&diff_ids), Some(diff_ids[1].clone()));
+/// assert_eq!(chain_id(&[]), None);
+/// ```
+pub fn chain_id(diff_ids: &[Digest]) -> Option<Digest> {
+    let (base, rest) = diff_ids.split_first()?;
+    Some(
+        rest.iter()
+            .fold(base.clone(), |parent, diff_id| chain(&parent, diff_id)),
+    )
+}
+
+/// `ChainID(L₀|…|Lₙ) = SHA256(ChainID(L₀|…|Lₙ₋₁) + " " + DiffID(Lₙ))`
+fn chain(parent: &Digest, diff_id: &Digest) -> Digest {
+    Digest::from_sha256(Sha256::digest(format!("{} {}", parent, diff_id).as_bytes()))
+}
+
 /// Image execution default parameters.
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 #[serde(rename_all = "PascalCase")]
@@ -104,7 +881,9 @@ pub struct ImageConfig {
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct RootFS {
     pub r#type: String,
-    diff_ids: Vec<String>,
+    /// Digests of the uncompressed layers, from the base to the top-most
+    /// layer.
+    pub diff_ids: Vec<Digest>,
 }
 
 /// Describe the history of a layer.
@@ -118,7 +897,7 @@ pub struct LayerHistory {
 }
 
 /// Content identifier.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Digest {
     pub algorithm: String,
     pub hash: String,
@@ -180,7 +959,221 @@ impl ser::Serialize for Digest {
     where
         S: ser::Serializer,
     {
-        let val = format!("{}:{}", &self.hash, &self.algorithm);
-        serializer.serialize_str(val.as_str())
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// Media type of a manifest, config or layer.
+///
+/// Known media types are parsed to their own variant, any other one is kept
+/// as [`MediaType::Unknown`]. Media types should be built by parsing (or
+/// converting) their string form, so that known ones get their variant.
+///
+/// ```
+/// # use oci_registry_client::manifest::MediaType;
+/// let media_type: MediaType = "application/vnd.oci.image.layer.v1.tar+zstd".parse().unwrap();
+/// assert_eq!(media_type, MediaType::OciLayerZstd);
+/// assert_eq!(media_type.to_string(), "application/vnd.oci.image.layer.v1.tar+zstd");
+///
+/// let media_type = MediaType::from("application/vnd.example+json");
+/// assert_eq!(media_type, MediaType::Unknown("application/vnd.example+json".to_owned()));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum MediaType {
+    /// `application/vnd.docker.distribution.manifest.list.v2+json`
+    DockerManifestList,
+    /// `application/vnd.docker.distribution.manifest.v2+json`
+    DockerManifest,
+    /// `application/vnd.docker.distribution.manifest.v1+json`
+    DockerManifestV1,
+    /// `application/vnd.docker.distribution.manifest.v1+prettyjws`
+    DockerManifestV1Signed,
+    /// `application/vnd.docker.container.image.v1+json`
+    DockerImageConfig,
+    /// `application/vnd.docker.image.rootfs.diff.tar`
+    DockerLayer,
+    /// `application/vnd.docker.image.rootfs.diff.tar.gzip`
+    DockerLayerGzip,
+    /// `application/vnd.docker.image.rootfs.foreign.diff.tar.gzip`
+    DockerForeignLayerGzip,
+    /// `application/vnd.oci.image.index.v1+json`
+    OciImageIndex,
+    /// `application/vnd.oci.image.manifest.v1+json`
+    OciManifest,
+    /// `application/vnd.oci.image.config.v1+json`
+    OciImageConfig,
+    /// `application/vnd.oci.empty.v1+json`
+    OciEmpty,
+    /// `application/vnd.oci.image.layer.v1.tar`
+    OciLayer,
+    /// `application/vnd.oci.image.layer.v1.tar+gzip`
+    OciLayerGzip,
+    /// `application/vnd.oci.image.layer.v1.tar+zstd`
+    OciLayerZstd,
+    /// `application/vnd.oci.image.layer.nondistributable.v1.tar`
+    OciForeignLayer,
+    /// `application/vnd.oci.image.layer.nondistributable.v1.tar+gzip`
+    OciForeignLayerGzip,
+    /// `application/vnd.oci.image.layer.nondistributable.v1.tar+zstd`
+    OciForeignLayerZstd,
+    /// Any other media type.
+    Unknown(String),
+}
+
+impl MediaType {
+    /// Returns the string form of this media type.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::DockerManifestList => MEDIA_TYPE_MANIFEST_LIST_V2,
+            Self::DockerManifest => MEDIA_TYPE_MANIFEST_V2,
+            Self::DockerManifestV1 => MEDIA_TYPE_MANIFEST_V1,
+            Self::DockerManifestV1Signed => MEDIA_TYPE_MANIFEST_V1_SIGNED,
+            Self::DockerImageConfig => MEDIA_TYPE_IMAGE_CONFIG,
+            Self::DockerLayer => "application/vnd.docker.image.rootfs.diff.tar",
+            Self::DockerLayerGzip => "application/vnd.docker.image.rootfs.diff.tar.gzip",
+            Self::DockerForeignLayerGzip => {
+                "application/vnd.docker.image.rootfs.foreign.diff.tar.gzip"
+            }
+            Self::OciImageIndex => MEDIA_TYPE_OCI_IMAGE_INDEX_V1,
+            Self::OciManifest => MEDIA_TYPE_OCI_MANIFEST_V1,
+            Self::OciImageConfig => MEDIA_TYPE_OCI_IMAGE_CONFIG,
+            Self::OciEmpty => MEDIA_TYPE_OCI_EMPTY_V1,
+            Self::OciLayer => "application/vnd.oci.image.layer.v1.tar",
+            Self::OciLayerGzip => "application/vnd.oci.image.layer.v1.tar+gzip",
+            Self::OciLayerZstd => "application/vnd.oci.image.layer.v1.tar+zstd",
+            Self::OciForeignLayer => "application/vnd.oci.image.layer.nondistributable.v1.tar",
+            Self::OciForeignLayerGzip => {
+                "application/vnd.oci.image.layer.nondistributable.v1.tar+gzip"
+            }
+            Self::OciForeignLayerZstd => {
+                "application/vnd.oci.image.layer.nondistributable.v1.tar+zstd"
+            }
+            Self::Unknown(media_type) => media_type,
+        }
+    }
+
+    /// Returns the OCI equivalent of this Docker media type.
+    ///
+    /// Media types without equivalent are returned unchanged.
+    pub fn to_oci(&self) -> MediaType {
+        match self {
+            Self::DockerManifestList => Self::OciImageIndex,
+            Self::DockerManifest => Self::OciManifest,
+            Self::DockerImageConfig => Self::OciImageConfig,
+            Self::DockerLayer => Self::OciLayer,
+            Self::DockerLayerGzip => Self::OciLayerGzip,
+            Self::DockerForeignLayerGzip => Self::OciForeignLayerGzip,
+            other => other.clone(),
+        }
+    }
+
+    /// Returns the Docker equivalent of this OCI media type.
+    ///
+    /// Media types without equivalent (zstd layers for instance) are
+    /// returned unchanged.
+    pub fn to_docker(&self) -> MediaType {
+        match self {
+            Self::OciImageIndex => Self::DockerManifestList,
+            Self::OciManifest => Self::DockerManifest,
+            Self::OciImageConfig => Self::DockerImageConfig,
+            Self::OciLayer => Self::DockerLayer,
+            Self::OciLayerGzip => Self::DockerLayerGzip,
+            Self::OciForeignLayerGzip => Self::DockerForeignLayerGzip,
+            other => other.clone(),
+        }
+    }
+
+    /// Returns `true` for manifest lists and image indexes.
+    pub fn is_index(&self) -> bool {
+        matches!(self, Self::DockerManifestList | Self::OciImageIndex)
+    }
+
+    /// Returns `true` for (schema 2 and OCI) image manifests.
+    pub fn is_manifest(&self) -> bool {
+        matches!(self, Self::DockerManifest | Self::OciManifest)
+    }
+
+    /// Returns `true` for foreign (non-distributable) layers.
+    pub fn is_foreign(&self) -> bool {
+        match self {
+            Self::DockerForeignLayerGzip
+            | Self::OciForeignLayer
+            | Self::OciForeignLayerGzip
+            | Self::OciForeignLayerZstd => true,
+            Self::Unknown(media_type) => {
+                media_type.contains(".foreign.") || media_type.contains(".nondistributable.")
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for MediaType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl str::FromStr for MediaType {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const KNOWN: [MediaType; 18] = [
+            MediaType::DockerManifestList,
+            MediaType::DockerManifest,
+            MediaType::DockerManifestV1,
+            MediaType::DockerManifestV1Signed,
+            MediaType::DockerImageConfig,
+            MediaType::DockerLayer,
+            MediaType::DockerLayerGzip,
+            MediaType::DockerForeignLayerGzip,
+            MediaType::OciImageIndex,
+            MediaType::OciManifest,
+            MediaType::OciImageConfig,
+            MediaType::OciEmpty,
+            MediaType::OciLayer,
+            MediaType::OciLayerGzip,
+            MediaType::OciLayerZstd,
+            MediaType::OciForeignLayer,
+            MediaType::OciForeignLayerGzip,
+            MediaType::OciForeignLayerZstd,
+        ];
+
+        Ok(KNOWN
+            .into_iter()
+            .find(|media_type| media_type.as_str() == s)
+            .unwrap_or_else(|| MediaType::Unknown(s.to_owned())))
+    }
+}
+
+impl From<&str> for MediaType {
+    fn from(media_type: &str) -> Self {
+        let Ok(media_type) = media_type.parse();
+        media_type
+    }
+}
+
+impl From<String> for MediaType {
+    fn from(media_type: String) -> Self {
+        media_type.as_str().into()
+    }
+}
+
+impl<'de> de::Deserialize<'de> for MediaType {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        Ok(String::deserialize(deserializer)?.into())
+    }
+}
+
+impl ser::Serialize for MediaType {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        serializer.serialize_str(self.as_str())
     }
 }
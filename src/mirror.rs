@@ -0,0 +1,57 @@
+//! Registry mirrors.
+//!
+//! Like the `registry-mirrors` of dockerd, mirrors are tried in order before
+//! the registry when fetching manifests, configs and blobs. The registry is
+//! used when every mirror fails: content missing from the mirror, errors,
+//! manifests not matching their digest. A mirror is a client of its own,
+//! with its own endpoints, authentication and options.
+//!
+//! Mirrors serve the same repositories as the registry. Blobs are checked
+//! against their digest once read, a blob corrupted by a mirror fails
+//! instead of falling back to the registry: the
+//! [`ErrorResponse::DigestMismatch`] error holds the URL it was downloaded
+//! from, to evict it from the mirror and retry.
+//!
+//! ```no_run
+//! # use oci_registry_client::{auth::Credentials, DockerRegistryClientV2};
+//! # fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let mirror = DockerRegistryClientV2::builder("https://mirror.corp.example.com")
+//!     .credentials(Credentials::new("ci", "s3cr3t"))
+//!     .auth_url("https://mirror.corp.example.com/token")
+//!     .build()?;
+//! let client = DockerRegistryClientV2::builder("https://registry-1.docker.io")
+//!     .service("registry.docker.io")
+//!     .auth_url("https://auth.docker.io/token")
+//!     .mirror(mirror)
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{errors::ErrorResponse, DockerRegistryClientV2};
+use std::sync::Arc;
+
+impl DockerRegistryClientV2 {
+    /// Set the mirrors tried before this registry, in order, see
+    /// [`crate::mirror`]. The mirrors of the mirrors are not used.
+    pub fn set_mirrors(&mut self, mirrors: Vec<DockerRegistryClientV2>) {
+        self.mirrors = Arc::new(mirrors);
+    }
+
+    /// Returns the mirrors tried before this registry.
+    pub fn mirrors(&self) -> &[DockerRegistryClientV2] {
+        &self.mirrors
+    }
+}
+
+/// Record that `mirror` could not serve a request and is skipped.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn skipped(mirror: &DockerRegistryClientV2, err: &ErrorResponse) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        target: "oci_registry_client::mirror",
+        mirror = %mirror.api_url,
+        error = %err,
+        "mirror skipped"
+    );
+}
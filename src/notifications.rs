@@ -0,0 +1,119 @@
+//! Registry notification (webhook) payloads.
+//!
+//! See [Notifications](https://docs.docker.com/registry/notifications/) for
+//! more details.
+//!
+//! ```
+//! use oci_registry_client::notifications::{Action, Envelope};
+//!
+//! let payload = r#"{
+//!   "events": [{
+//!     "id": "320678d8-ca14-430f-8bb6-4ca139cd83f7",
+//!     "timestamp": "2016-03-09T14:44:26.402973972-08:00",
+//!     "action": "push",
+//!     "target": {
+//!       "mediaType": "application/vnd.docker.distribution.manifest.v2+json",
+//!       "size": 708,
+//!       "digest": "sha256:fea8895f450959fa676bcc1df0611ea93823a735a01205fd8622846041d0c7cf",
+//!       "length": 708,
+//!       "repository": "hello-world",
+//!       "url": "http://192.168.100.227:5000/v2/hello-world/manifests/sha256:fea8895f450959fa676bcc1df0611ea93823a735a01205fd8622846041d0c7cf",
+//!       "tag": "latest"
+//!     },
+//!     "request": {
+//!       "id": "6df24a34-0959-4923-81ca-14f09767db19",
+//!       "addr": "192.168.64.11:42961",
+//!       "host": "192.168.100.227:5000",
+//!       "method": "PUT",
+//!       "useragent": "curl/7.38.0"
+//!     },
+//!     "actor": {},
+//!     "source": {
+//!       "addr": "xtal.local:5000",
+//!       "instanceID": "a53db899-3b4b-4a62-a067-8dd013beaca4"
+//!     }
+//!   }]
+//! }"#;
+//!
+//! let envelope: Envelope = serde_json::from_str(payload).unwrap();
+//! assert_eq!(envelope.events[0].action, Action::Push);
+//! assert_eq!(envelope.events[0].target.tag.as_deref(), Some("latest"));
+//! ```
+
+use crate::manifest::{Digest, MediaType};
+
+/// Media type of a notification [`Envelope`].
+pub const MEDIA_TYPE_EVENTS_V1: &str = "application/vnd.docker.distribution.events.v1+json";
+
+/// A batch of events sent by the registry in a single request.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Envelope {
+    pub events: Vec<Event>,
+}
+
+/// An event triggered by an action on the registry.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Event {
+    pub id: String,
+    pub timestamp: String,
+    pub action: Action,
+    pub target: Target,
+    #[serde(default)]
+    pub request: Request,
+    #[serde(default)]
+    pub actor: Actor,
+    #[serde(default)]
+    pub source: Source,
+}
+
+/// The action that triggered an [`Event`].
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Pull,
+    Push,
+    Mount,
+    Delete,
+    #[serde(other)]
+    Unknown,
+}
+
+/// The content (blob or manifest) affected by an [`Event`].
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Target {
+    pub media_type: Option<MediaType>,
+    pub size: Option<usize>,
+    pub digest: Option<Digest>,
+    pub length: Option<usize>,
+    pub repository: String,
+    pub url: Option<String>,
+    pub tag: Option<String>,
+    /// Repository the blob was mounted from (`mount` events).
+    pub from_repository: Option<String>,
+}
+
+/// The request that generated an [`Event`].
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct Request {
+    pub id: Option<String>,
+    pub addr: Option<String>,
+    pub host: Option<String>,
+    pub method: Option<String>,
+    pub useragent: Option<String>,
+}
+
+/// The agent that initiated an [`Event`].
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct Actor {
+    pub name: Option<String>,
+}
+
+/// The registry instance that generated an [`Event`].
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Source {
+    pub addr: Option<String>,
+    #[serde(rename = "instanceID")]
+    pub instance_id: Option<String>,
+}
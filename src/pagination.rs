@@ -0,0 +1,92 @@
+//! Pagination helpers.
+//!
+//! Paginated endpoints (tags, catalog, referrers) return the URL of the next
+//! page in a [RFC 5988](https://tools.ietf.org/html/rfc5988) `Link` header:
+//!
+//! ```text
+//! Link: </v2/_catalog?last=b&n=2>; rel="next"
+//! ```
+
+use reqwest::{header::HeaderMap, Url};
+
+/// A link parsed from a `Link` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Link {
+    pub url: String,
+    pub rel: Option<String>,
+}
+
+/// Parse all links from the value of a `Link` header.
+///
+/// ```
+/// # use oci_registry_client::pagination::parse_link_header;
+/// let links = parse_link_header(r#"</v2/_catalog?last=b&n=2>; rel="next""#);
+/// assert_eq!(links[0].url, "/v2/_catalog?last=b&n=2");
+/// assert_eq!(links[0].rel.as_deref(), Some("next"));
+/// ```
+pub fn parse_link_header(value: &str) -> Vec<Link> {
+    let mut links = vec![];
+    let mut rest = value;
+
+    while let Some(start) = rest.find('<') {
+        let end = match rest[start..].find('>') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let url = rest[start + 1..end].trim().to_owned();
+        rest = &rest[end + 1..];
+
+        let params_end = rest.find('<').unwrap_or(rest.len());
+        let rel = rest[..params_end]
+            .split(';')
+            .filter_map(|param| param.split_once('='))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case("rel"))
+            .map(|(_, value)| {
+                value
+                    .trim()
+                    .trim_end_matches(',')
+                    .trim()
+                    .trim_matches('"')
+                    .to_owned()
+            });
+
+        links.push(Link { url, rel });
+        rest = &rest[params_end..];
+    }
+
+    links
+}
+
+/// Returns the `rel="next"` URL from the value of a `Link` header.
+pub fn next_link(value: &str) -> Option<String> {
+    parse_link_header(value)
+        .into_iter()
+        .find(|link| link.rel.as_deref() == Some("next"))
+        .map(|link| link.url)
+}
+
+/// Returns the absolute URL of the next page announced in `headers`.
+///
+/// Relative links are resolved against `base` (the URL of the current page).
+///
+/// ```
+/// # use oci_registry_client::pagination::next_page_url;
+/// let mut headers = reqwest::header::HeaderMap::new();
+/// headers.insert("link", r#"</v2/_catalog?last=b&n=2>; rel="next""#.parse().unwrap());
+/// assert_eq!(
+///     next_page_url("https://registry.example.com/v2/_catalog?n=2", &headers).as_deref(),
+///     Some("https://registry.example.com/v2/_catalog?last=b&n=2"),
+/// );
+/// ```
+pub fn next_page_url(base: &str, headers: &HeaderMap) -> Option<String> {
+    let link = headers
+        .get_all(reqwest::header::LINK)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find_map(next_link)?;
+
+    match Url::parse(base).and_then(|base| base.join(&link)) {
+        Ok(url) => Some(url.to_string()),
+        Err(_) => Some(link),
+    }
+}
@@ -0,0 +1,98 @@
+//! Proxy configuration.
+//!
+//! By default requests go through the proxies of the `HTTP_PROXY`,
+//! `HTTPS_PROXY` and `NO_PROXY` environment variables. Configuring a
+//! [`Proxy`] with [`ClientBuilder::proxy`] replaces them.
+//!
+//! ```no_run
+//! # use oci_registry_client::{auth::Credentials, proxy::Proxy, DockerRegistryClientV2};
+//! # fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let proxy = Proxy::all("http://proxy.corp.example.com:3128")
+//!     .credentials(Credentials::new("alice", "s3cr3t"))
+//!     .no_proxy(["localhost", "10.0.0.0/8", ".corp.example.com"]);
+//! let client = DockerRegistryClientV2::builder("https://registry.corp.example.com")
+//!     .proxy(proxy)
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! `socks5://` and `socks5h://` proxies require the `socks` feature of
+//! `reqwest` to be enabled by the application.
+//!
+//! [`ClientBuilder::proxy`]: crate::builder::ClientBuilder::proxy
+
+use crate::{auth::Credentials, errors::ErrorResponse};
+
+/// Requests sent through a proxy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Intercept {
+    All,
+    Http,
+    Https,
+}
+
+/// A proxy requests are sent through.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Proxy {
+    url: String,
+    intercept: Intercept,
+    credentials: Option<Credentials>,
+    no_proxy: Vec<String>,
+}
+
+impl Proxy {
+    fn new<T: Into<String>>(url: T, intercept: Intercept) -> Self {
+        Self {
+            url: url.into(),
+            intercept,
+            credentials: None,
+            no_proxy: vec![],
+        }
+    }
+
+    /// Send all requests through the proxy at `url`.
+    pub fn all<T: Into<String>>(url: T) -> Self {
+        Self::new(url, Intercept::All)
+    }
+
+    /// Send `http://` requests through the proxy at `url`.
+    pub fn http<T: Into<String>>(url: T) -> Self {
+        Self::new(url, Intercept::Http)
+    }
+
+    /// Send `https://` requests through the proxy at `url`.
+    pub fn https<T: Into<String>>(url: T) -> Self {
+        Self::new(url, Intercept::Https)
+    }
+
+    /// Authenticate to the proxy with `credentials` (HTTP basic
+    /// authentication).
+    pub fn credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    /// Do not proxy requests to `hosts`: host names (a leading `.` matches
+    /// subdomains), IP addresses or CIDR ranges, as in `NO_PROXY`.
+    pub fn no_proxy<I, T>(mut self, hosts: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.no_proxy.extend(hosts.into_iter().map(Into::into));
+        self
+    }
+
+    pub(crate) fn to_reqwest(&self) -> Result<reqwest::Proxy, ErrorResponse> {
+        let mut proxy = match self.intercept {
+            Intercept::All => reqwest::Proxy::all(&self.url)?,
+            Intercept::Http => reqwest::Proxy::http(&self.url)?,
+            Intercept::Https => reqwest::Proxy::https(&self.url)?,
+        };
+        if let Some(credentials) = &self.credentials {
+            proxy = proxy.basic_auth(&credentials.username, &credentials.password);
+        }
+        Ok(proxy.no_proxy(reqwest::NoProxy::from_string(&self.no_proxy.join(","))))
+    }
+}
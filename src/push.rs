@@ -0,0 +1,682 @@
+//! Upload blobs and manifests to a registry.
+//!
+//! Pushing an image means uploading every blob it references (config and
+//! layers) and then the manifest itself:
+//!
+//! ```no_run
+//! use oci_registry_client::{push::BlobSource, DockerRegistryClientV2};
+//! # use oci_registry_client::manifest::Manifest;
+//!
+//! # async fn example(client: DockerRegistryClientV2, manifest: Manifest, blobs: Vec<BlobSource>) -> Result<(), Box<dyn std::error::Error>> {
+//! for blob in &blobs {
+//!     client.push_blob("my/app", blob).await?;
+//! }
+//! let digest = client.push_manifest("my/app", "latest", &manifest).await?;
+//! println!("pushed my/app@{}", digest);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    audit::Operation,
+    auth::Scope,
+    bandwidth::BandwidthLimiter,
+    errors::{ErrorContext, ErrorResponse},
+    in_context,
+    manifest::{
+        Descriptor, Digest, ImageIndex, Layer, Manifest, ManifestConfig, ManifestFormat,
+        ManifestList, ManifestResponse, MediaType,
+    },
+    reference::{AsRepository, AsTagOrDigest},
+    retry,
+    timeout::{self, PhaseTimeouts},
+    DockerRegistryClientV2,
+};
+use bytes::Bytes;
+use futures_util::stream;
+use reqwest::{header, Body, Method, RequestBuilder, Response, StatusCode, Url};
+use sha2::{Digest as Sha256Digest, Sha256};
+use std::{
+    fs::File,
+    future::Future,
+    io::{Read, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::Semaphore;
+use tokio_util::io::ReaderStream;
+
+/// A blob to be uploaded to a registry.
+#[derive(Clone, Debug)]
+pub struct BlobSource {
+    pub media_type: MediaType,
+    pub digest: Digest,
+    pub size: usize,
+    content: BlobContent,
+}
+
+#[derive(Clone, Debug)]
+enum BlobContent {
+    Bytes(Bytes),
+    File {
+        path: PathBuf,
+        offset: u64,
+        len: u64,
+    },
+}
+
+impl BlobSource {
+    /// Returns a blob with the given in-memory contents.
+    pub fn from_bytes<T: Into<MediaType>, B: Into<Bytes>>(media_type: T, content: B) -> Self {
+        let content = content.into();
+        Self {
+            media_type: media_type.into(),
+            digest: Digest::from_sha256(Sha256::digest(&content)),
+            size: content.len(),
+            content: BlobContent::Bytes(content),
+        }
+    }
+
+    /// Returns a blob with the contents of the file at `path`.
+    ///
+    /// The file is read once to compute its digest, this function blocks.
+    pub fn from_file<T: Into<MediaType>, P: AsRef<Path>>(
+        media_type: T,
+        path: P,
+    ) -> Result<Self, ErrorResponse> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = File::open(&path)?;
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut len = 0u64;
+
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.input(&buf[..n]);
+            len += n as u64;
+        }
+
+        Ok(Self::from_file_range(
+            media_type,
+            Digest::from_sha256(hasher.result()),
+            path,
+            0,
+            len,
+        ))
+    }
+
+    /// Returns a blob stored at `offset..offset + len` of the file at `path`
+    /// with a known digest.
+    pub(crate) fn from_file_range<T: Into<MediaType>>(
+        media_type: T,
+        digest: Digest,
+        path: PathBuf,
+        offset: u64,
+        len: u64,
+    ) -> Self {
+        Self {
+            media_type: media_type.into(),
+            digest,
+            size: len as usize,
+            content: BlobContent::File { path, offset, len },
+        }
+    }
+
+    /// Returns a [`Layer`] descriptor referencing this blob.
+    pub fn to_layer(&self) -> Layer {
+        self.to_descriptor()
+    }
+
+    /// Returns a [`ManifestConfig`] descriptor referencing this blob.
+    pub fn to_config(&self) -> ManifestConfig {
+        self.to_descriptor()
+    }
+
+    /// Returns a [`Descriptor`] referencing this blob.
+    pub fn to_descriptor(&self) -> Descriptor {
+        Descriptor::new(self.media_type.clone(), self.digest.clone(), self.size)
+    }
+
+    /// Returns the contents as a request body, paced by `limiter` if any.
+    async fn body(&self, limiter: Option<&BandwidthLimiter>) -> Result<Body, ErrorResponse> {
+        match (&self.content, limiter) {
+            (BlobContent::Bytes(bytes), None) => Ok(Body::from(bytes.clone())),
+            (BlobContent::Bytes(bytes), Some(limiter)) => {
+                let bytes = bytes.clone();
+                let chunks = stream::once(async move { Ok::<_, std::io::Error>(bytes) });
+                Ok(Body::wrap_stream(limiter.throttle(chunks)))
+            }
+            (BlobContent::File { path, offset, len }, limiter) => {
+                let mut file = tokio::fs::File::open(path).await?;
+                file.seek(SeekFrom::Start(*offset)).await?;
+                let chunks = ReaderStream::new(file.take(*len));
+                Ok(match limiter {
+                    Some(limiter) => Body::wrap_stream(limiter.throttle(chunks)),
+                    None => Body::wrap_stream(chunks),
+                })
+            }
+        }
+    }
+}
+
+/// A reference of a manifest missing from a repository.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MissingReference {
+    /// A config or layer blob.
+    Blob(Digest),
+    /// A manifest of a manifest list.
+    Manifest(Digest),
+}
+
+/// Result of [`DockerRegistryClientV2::preflight`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PreflightReport {
+    pub missing: Vec<MissingReference>,
+}
+
+impl PreflightReport {
+    /// Returns `true` if every reference exists.
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+/// Limits applied to write requests, to stay below registry rate limits.
+///
+/// ```
+/// # use oci_registry_client::{push::PushLimits, DockerRegistryClientV2};
+/// # let mut client = DockerRegistryClientV2::try_new("ghcr.io", "https://ghcr.io", "https://ghcr.io/token").unwrap();
+/// client.set_push_limits(PushLimits {
+///     requests_per_second: Some(5.0),
+///     max_concurrent_uploads: Some(2),
+///     ..Default::default()
+/// });
+/// ```
+#[derive(Clone, Debug)]
+pub struct PushLimits {
+    /// Maximum number of write requests sent per second.
+    pub requests_per_second: Option<f64>,
+    /// Maximum number of blob uploads in progress at the same time.
+    pub max_concurrent_uploads: Option<usize>,
+    /// Number of times a request rejected with `429 Too Many Requests` is
+    /// retried. Blob contents are read again from their source on every
+    /// attempt.
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled on every attempt. A
+    /// `Retry-After` header (in seconds or as an HTTP date) takes precedence.
+    pub initial_backoff: Duration,
+    /// Maximum delay between two attempts, when the registry does not send a
+    /// `Retry-After` header.
+    pub max_backoff: Duration,
+}
+
+impl Default for PushLimits {
+    fn default() -> Self {
+        Self {
+            requests_per_second: None,
+            max_concurrent_uploads: None,
+            max_retries: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Shared state enforcing [`PushLimits`] across clones of a client.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PushThrottle {
+    limits: PushLimits,
+    uploads: Option<Arc<Semaphore>>,
+    next_request: Arc<Mutex<Option<Instant>>>,
+}
+
+impl PushThrottle {
+    pub(crate) fn new(limits: PushLimits) -> Self {
+        Self {
+            uploads: limits
+                .max_concurrent_uploads
+                .map(|max| Arc::new(Semaphore::new(max.max(1)))),
+            limits,
+            next_request: Arc::default(),
+        }
+    }
+
+    /// Wait for the next request slot.
+    async fn pace(&self) {
+        let interval = match self.limits.requests_per_second {
+            Some(rps) if rps > 0.0 => Duration::from_secs_f64(1.0 / rps),
+            _ => return,
+        };
+
+        let wait = {
+            let mut next_request = self.next_request.lock().unwrap();
+            let now = Instant::now();
+            let at = next_request.map_or(now, |next| next.max(now));
+            *next_request = Some(at + interval);
+            at - now
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Send the write request returned by `build` (see [`timeout::send`]),
+    /// building and sending it again when the registry throttles it.
+    async fn send<F, Fut>(
+        &self,
+        timeouts: &PhaseTimeouts,
+        mut build: F,
+    ) -> Result<Response, ErrorResponse>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<RequestBuilder, ErrorResponse>>,
+    {
+        let mut backoff = self.limits.initial_backoff.min(self.limits.max_backoff);
+        let mut attempt = 0;
+
+        loop {
+            let request = build().await?;
+            self.pace().await;
+            let response = timeout::send(request, timeouts).await?;
+            match attempt < self.limits.max_retries {
+                true if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    tokio::time::sleep(retry::retry_after(&response).unwrap_or(backoff)).await;
+                    backoff = backoff.saturating_mul(2).min(self.limits.max_backoff);
+                    attempt += 1;
+                }
+                _ => return Ok(response),
+            }
+        }
+    }
+}
+
+impl DockerRegistryClientV2 {
+    /// Set the limits applied to write requests (uploads, mounts and
+    /// manifest pushes).
+    ///
+    /// By default requests are not limited and throttled requests are retried
+    /// up to 3 times.
+    pub fn set_push_limits(&mut self, limits: PushLimits) {
+        self.push_throttle = PushThrottle::new(limits);
+    }
+
+    /// Returns `true` if the blob identified by `digest` exists in `image`.
+    pub async fn blob_exists<I: AsRepository + ?Sized>(
+        &self,
+        image: &I,
+        digest: &Digest,
+    ) -> Result<bool, ErrorResponse> {
+        let image = image.as_repository();
+        let url = format!("{}/v2/{}/blobs/{}", &self.api_url, image, digest);
+        self.exists(&url, image, &[]).await
+    }
+
+    /// Upload `blob` to `image`.
+    ///
+    /// Blobs already present in the repository are not uploaded again.
+    pub async fn push_blob<I: AsRepository + ?Sized>(
+        &self,
+        image: &I,
+        blob: &BlobSource,
+    ) -> Result<(), ErrorResponse> {
+        let image = image.as_repository();
+        let digest = blob.digest.to_string();
+        let operation = Operation::start("push_blob", image, &digest);
+        let result = self.upload_blob_if_missing(image, blob, &operation).await;
+        operation.finish(result.as_ref().map(|_| &blob.digest));
+        result
+    }
+
+    async fn upload_blob_if_missing(
+        &self,
+        image: &str,
+        blob: &BlobSource,
+        operation: &Operation,
+    ) -> Result<(), ErrorResponse> {
+        if self.blob_exists(image, &blob.digest).await? {
+            operation.cache_hit();
+            return Ok(());
+        }
+
+        let limiter = self.bandwidth.upload.as_ref();
+        self.upload_blob(image, &blob.digest, blob.size, || blob.body(limiter))
+            .await?;
+        operation.transferred(blob.size as u64);
+        Ok(())
+    }
+
+    /// Mount the blob identified by `digest` from repository `from` into
+    /// `image`, without transferring its content.
+    ///
+    /// Returns `false` if the registry did not mount the blob (unsupported or
+    /// unknown in `from`), the blob must then be uploaded.
+    pub async fn mount_blob<I: AsRepository + ?Sized, F: AsRepository + ?Sized>(
+        &self,
+        image: &I,
+        digest: &Digest,
+        from: &F,
+    ) -> Result<bool, ErrorResponse> {
+        let image = image.as_repository();
+        let from = from.as_repository();
+        let scope = Scope::repository(image, "pull,push");
+        let url = format!("{}/v2/{}/blobs/uploads/", &self.api_url, image);
+        let mount = digest.to_string();
+        let context = || ErrorContext::new(Method::POST, &url, Some(image), Some(&mount));
+        in_context(context, async {
+            let response = self
+                .push_throttle
+                .send(&self.timeouts.manifests, || {
+                    let request = self
+                        .client
+                        .post(&url)
+                        .query(&[("mount", mount.as_str()), ("from", from)]);
+                    self.authorize_scope(request, Some(&scope))
+                })
+                .await?;
+
+            match response.status() {
+                StatusCode::CREATED => Ok(true),
+                StatusCode::ACCEPTED => Ok(false),
+                _ => Err(ErrorResponse::from_response(response).await),
+            }
+        })
+        .await
+    }
+
+    /// Upload `size` bytes of the body returned by `body` as the blob
+    /// identified by `digest`, using a monolithic upload.
+    ///
+    /// `body` is called again when a throttled upload is retried.
+    pub(crate) async fn upload_blob<F, Fut>(
+        &self,
+        image: &str,
+        digest: &Digest,
+        size: usize,
+        mut body: F,
+    ) -> Result<(), ErrorResponse>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<Body, ErrorResponse>>,
+    {
+        let _permit = match &self.push_throttle.uploads {
+            Some(uploads) => Some(uploads.acquire().await.expect("semaphore closed")),
+            None => None,
+        };
+        let scope = Scope::repository(image, "pull,push");
+
+        let reference = digest.to_string();
+
+        let url = format!("{}/v2/{}/blobs/uploads/", &self.api_url, image);
+        let context = || ErrorContext::new(Method::POST, &url, Some(image), Some(&reference));
+        let upload_url = in_context(context, async {
+            let response = self
+                .push_throttle
+                .send(&self.timeouts.manifests, || {
+                    self.authorize_scope(self.client.post(&url), Some(&scope))
+                })
+                .await?;
+            if response.status() != StatusCode::ACCEPTED {
+                return Err(ErrorResponse::from_response(response).await);
+            }
+
+            let location = response
+                .headers()
+                .get(header::LOCATION)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default();
+            let mut upload_url = Url::parse(&url)
+                .and_then(|url| url.join(location))
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+            upload_url
+                .query_pairs_mut()
+                .append_pair("digest", &reference);
+            Ok(upload_url)
+        })
+        .await?;
+
+        let context = || {
+            ErrorContext::new(
+                Method::PUT,
+                upload_url.as_str(),
+                Some(image),
+                Some(&reference),
+            )
+        };
+        in_context(context, async {
+            let response = self
+                .push_throttle
+                .send(&self.timeouts.blobs, || {
+                    let body = body();
+                    let request = self
+                        .client
+                        .put(upload_url.clone())
+                        .header(header::CONTENT_TYPE, "application/octet-stream")
+                        .header(header::CONTENT_LENGTH, size);
+                    async {
+                        let request = request.body(body.await?);
+                        self.authorize_scope(request, Some(&scope)).await
+                    }
+                })
+                .await?;
+
+            match response.status() {
+                StatusCode::CREATED => Ok(()),
+                _ => Err(ErrorResponse::from_response(response).await),
+            }
+        })
+        .await
+    }
+
+    /// Upload `manifest` to `image` as `reference` (a tag or digest).
+    ///
+    /// Returns the digest of the uploaded manifest.
+    pub async fn push_manifest<I: AsRepository + ?Sized, T: AsTagOrDigest + ?Sized>(
+        &self,
+        image: &I,
+        reference: &T,
+        manifest: &Manifest,
+    ) -> Result<Digest, ErrorResponse> {
+        let image = image.as_repository();
+        let reference = &*reference.as_tag_or_digest();
+        let content_type = manifest.media_type_or(MediaType::OciManifest);
+        let body = serde_json::to_vec(manifest)?;
+        self.push_manifest_raw(image, reference, content_type.as_str(), body.into())
+            .await
+    }
+
+    /// Upload `index` to `image` as `reference` (a tag or digest), as an OCI
+    /// image index or a Docker manifest list depending on `format`.
+    ///
+    /// The manifests of the index must already be pushed, in the same
+    /// format. Returns the digest of the uploaded manifest.
+    ///
+    /// ```no_run
+    /// # use oci_registry_client::{manifest::{Descriptor, ImageIndex, ManifestFormat}, DockerRegistryClientV2};
+    /// # async fn example(client: DockerRegistryClientV2, amd64: Descriptor, arm64: Descriptor) -> Result<(), Box<dyn std::error::Error>> {
+    /// let mut index = ImageIndex::new();
+    /// index.manifests = vec![amd64, arm64];
+    /// client
+    ///     .push_index("my/app", "latest", &index, ManifestFormat::Docker)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn push_index<I: AsRepository + ?Sized, T: AsTagOrDigest + ?Sized>(
+        &self,
+        image: &I,
+        reference: &T,
+        index: &ImageIndex,
+        format: ManifestFormat,
+    ) -> Result<Digest, ErrorResponse> {
+        let image = image.as_repository();
+        let reference = &*reference.as_tag_or_digest();
+        let (content_type, body) = match format {
+            ManifestFormat::Oci => (index.media_type.clone(), serde_json::to_vec(index)?),
+            ManifestFormat::Docker => {
+                let list = ManifestList::from(index.clone()).to_docker();
+                let content_type = list.media_type_or(MediaType::DockerManifestList);
+                (content_type, serde_json::to_vec(&list)?)
+            }
+        };
+        self.push_manifest_raw(image, reference, content_type.as_str(), body.into())
+            .await
+    }
+
+    /// Check that every blob and manifest referenced by `manifest` exists in
+    /// `image`, using `HEAD` requests.
+    ///
+    /// ```no_run
+    /// # use oci_registry_client::{manifest::{Manifest, ManifestResponse}, DockerRegistryClientV2};
+    /// # async fn example(client: DockerRegistryClientV2, manifest: Manifest) -> Result<(), Box<dyn std::error::Error>> {
+    /// let report = client
+    ///     .preflight("my/app", &ManifestResponse::Image(manifest))
+    ///     .await?;
+    /// for missing in &report.missing {
+    ///     eprintln!("missing {:?}", missing);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn preflight<I: AsRepository + ?Sized>(
+        &self,
+        image: &I,
+        manifest: &ManifestResponse,
+    ) -> Result<PreflightReport, ErrorResponse> {
+        let image = image.as_repository();
+        let mut report = PreflightReport::default();
+
+        match manifest {
+            ManifestResponse::Image(manifest) => {
+                // Foreign layers are not stored by registries.
+                let blobs = std::iter::once(&manifest.config.digest).chain(
+                    manifest
+                        .layers
+                        .iter()
+                        .filter(|layer| !layer.is_foreign())
+                        .map(|layer| &layer.digest),
+                );
+                for digest in blobs {
+                    if !self.blob_exists(image, digest).await? {
+                        report.missing.push(MissingReference::Blob(digest.clone()));
+                    }
+                }
+            }
+            ManifestResponse::List(list) => {
+                for item in &list.manifests {
+                    if !self
+                        .manifest_exists(image, &item.digest.to_string())
+                        .await?
+                    {
+                        report
+                            .missing
+                            .push(MissingReference::Manifest(item.digest.clone()));
+                    }
+                }
+            }
+            ManifestResponse::Schema1(manifest) => {
+                for layer in &manifest.fs_layers {
+                    if !self.blob_exists(image, &layer.blob_sum).await? {
+                        report
+                            .missing
+                            .push(MissingReference::Blob(layer.blob_sum.clone()));
+                    }
+                }
+            }
+            // References of unknown manifests can't be found.
+            ManifestResponse::Raw(_) => {}
+        }
+
+        Ok(report)
+    }
+
+    /// Check references of manifests before pushing them, see
+    /// [`DockerRegistryClientV2::preflight`].
+    ///
+    /// When enabled, pushing a manifest with missing references fails with
+    /// [`ErrorResponse::MissingReferences`] instead of the registry
+    /// `MANIFEST_BLOB_UNKNOWN` error. Disabled by default.
+    pub fn set_push_preflight(&mut self, enabled: bool) {
+        self.push_preflight = enabled;
+    }
+
+    /// Upload the manifest `body` of `content_type` to `image` as `reference`
+    /// (a tag or digest), byte for byte.
+    ///
+    /// Returns the digest of the uploaded manifest.
+    pub async fn push_manifest_raw<I: AsRepository + ?Sized, T: AsTagOrDigest + ?Sized>(
+        &self,
+        image: &I,
+        reference: &T,
+        content_type: &str,
+        body: Bytes,
+    ) -> Result<Digest, ErrorResponse> {
+        let image = image.as_repository();
+        let reference = &*reference.as_tag_or_digest();
+        let operation = Operation::start("push_manifest", image, reference);
+        let size = body.len() as u64;
+        let url = format!("{}/v2/{}/manifests/{}", &self.api_url, image, reference);
+        let context = || ErrorContext::new(Method::PUT, &url, Some(image), Some(reference));
+        let result = in_context(context, async {
+            if self.push_preflight {
+                self.check_references(image, content_type, &body).await?;
+            }
+            self.put_manifest(&url, image, content_type, body).await
+        })
+        .await;
+        if result.is_ok() {
+            operation.transferred(size);
+        }
+        operation.finish(result.as_ref());
+        result
+    }
+
+    async fn check_references(
+        &self,
+        image: &str,
+        content_type: &str,
+        body: &[u8],
+    ) -> Result<(), ErrorResponse> {
+        let manifest = ManifestResponse::from_slice(Some(content_type), body)?;
+        let report = self.preflight(image, &manifest).await?;
+        match report.missing.is_empty() {
+            true => Ok(()),
+            false => Err(ErrorResponse::MissingReferences {
+                missing: report.missing,
+                context: None,
+            }),
+        }
+    }
+
+    async fn put_manifest(
+        &self,
+        url: &str,
+        image: &str,
+        content_type: &str,
+        body: Bytes,
+    ) -> Result<Digest, ErrorResponse> {
+        let digest = Digest::from_sha256(Sha256::digest(&body));
+
+        let scope = Scope::repository(image, "pull,push");
+        let response = self
+            .push_throttle
+            .send(&self.timeouts.manifests, || {
+                let request = self
+                    .client
+                    .put(url)
+                    .header(header::CONTENT_TYPE, content_type)
+                    .body(body.clone());
+                self.authorize_scope(request, Some(&scope))
+            })
+            .await?;
+
+        match response.status() {
+            StatusCode::CREATED => Ok(digest),
+            _ => Err(ErrorResponse::from_response(response).await),
+        }
+    }
+}
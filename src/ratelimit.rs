@@ -0,0 +1,98 @@
+//! Pull rate limits.
+//!
+//! Docker Hub limits the number of manifest pulls per window, and reports
+//! the limit in `RateLimit-Limit` and `RateLimit-Remaining` headers (for
+//! instance `100;w=21600`: 100 pulls per 6 hours). A `HEAD` request on its
+//! `ratelimitpreview/test` repository does not count as a pull:
+//!
+//! ```no_run
+//! # use oci_registry_client::DockerRegistryClientV2;
+//! # async fn example(client: DockerRegistryClientV2) -> Result<(), Box<dyn std::error::Error>> {
+//! match client.rate_limit_status().await? {
+//!     Some(status) => println!("you have {} pulls left", status.remaining),
+//!     None => println!("no pull rate limit"),
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{errors::ErrorResponse, timeout, DockerRegistryClientV2};
+use reqwest::{header::HeaderMap, StatusCode};
+use std::time::Duration;
+
+/// Repository whose manifests can be queried without counting as pulls.
+const RATE_LIMIT_REPOSITORY: &str = "ratelimitpreview/test";
+
+/// Pull rate limit of the current client (user or IP address).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimitStatus {
+    /// Number of pulls allowed per window.
+    pub limit: u64,
+    /// Number of pulls left in the current window.
+    pub remaining: u64,
+    pub window: Duration,
+}
+
+impl RateLimitStatus {
+    /// Parse the rate limit headers of a response, `None` if they are
+    /// missing.
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let (limit, window) = parse_header(headers, "ratelimit-limit")?;
+        let (remaining, _) = parse_header(headers, "ratelimit-remaining")?;
+
+        Some(Self {
+            limit,
+            remaining,
+            window: window.unwrap_or_default(),
+        })
+    }
+}
+
+/// Parse a `<count>;w=<seconds>` header.
+fn parse_header(headers: &HeaderMap, name: &str) -> Option<(u64, Option<Duration>)> {
+    let value = headers.get(name)?.to_str().ok()?;
+    let mut parts = value.split(';');
+    let count = parts.next()?.trim().parse().ok()?;
+    let window = parts
+        .filter_map(|part| part.trim().strip_prefix("w="))
+        .find_map(|seconds| seconds.parse().ok())
+        .map(Duration::from_secs);
+    Some((count, window))
+}
+
+impl DockerRegistryClientV2 {
+    /// Returns the pull rate limit announced by the registry, or `None` if
+    /// it does not report one.
+    ///
+    /// Without configured credentials, an anonymous token is requested, as
+    /// for anonymous pulls.
+    pub async fn rate_limit_status(&self) -> Result<Option<RateLimitStatus>, ErrorResponse> {
+        let url = format!(
+            "{}/v2/{}/manifests/latest",
+            self.api_url, RATE_LIMIT_REPOSITORY
+        );
+        let request = self
+            .client
+            .head(&url)
+            .header(reqwest::header::ACCEPT, &self.manifest_accept);
+        let request = match (&self.token_provider, &self.auth_token) {
+            (None, None) => {
+                let token = self
+                    .auth("repository", RATE_LIMIT_REPOSITORY, "pull")
+                    .await?;
+                request.bearer_auth(token.token())
+            }
+            _ => self.authorize(request, Some(RATE_LIMIT_REPOSITORY)).await?,
+        };
+        let response = timeout::send(request, &self.timeouts.manifests).await?;
+
+        match response.status() {
+            StatusCode::OK | StatusCode::TOO_MANY_REQUESTS => {
+                Ok(RateLimitStatus::from_headers(response.headers()))
+            }
+            // Registries other than Docker Hub do not have the repository.
+            StatusCode::NOT_FOUND => Ok(None),
+            _ => Err(ErrorResponse::from_response(response).await),
+        }
+    }
+}
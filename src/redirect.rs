@@ -0,0 +1,89 @@
+//! Redirect handling.
+//!
+//! Registries commonly redirect blob downloads to presigned object storage
+//! URLs (S3, GCS, CDNs). Such locations authenticate the request with the
+//! URL itself: forwarding the registry token leaks it to a third party, and
+//! some storage services reject requests carrying an unexpected
+//! `Authorization` header.
+//!
+//! Redirects within the registry origin are followed as usual. Redirects to
+//! another origin are followed by the client itself, without the
+//! `Authorization` header (other headers, such as `Range`, are kept). The
+//! location a blob was downloaded from is available with
+//! [`crate::blob::Blob::url`].
+
+use crate::{
+    errors::ErrorResponse,
+    timeout::{self, PhaseTimeouts},
+};
+use reqwest::{header, redirect::Policy, Method, Response, Url};
+
+/// Maximum number of redirects followed for a request.
+const MAX_REDIRECTS: usize = 10;
+
+/// Returns the redirect policy of the HTTP client: redirects to another
+/// origin are not followed, see [`send`].
+pub(crate) fn policy() -> Policy {
+    Policy::custom(|attempt| {
+        let same_origin = attempt
+            .previous()
+            .last()
+            .is_none_or(|previous| previous.origin() == attempt.url().origin());
+        if attempt.previous().len() > MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else if same_origin {
+            attempt.follow()
+        } else {
+            attempt.stop()
+        }
+    })
+}
+
+/// Send `request`, following redirects to other origins without
+/// credentials.
+///
+/// Only `GET` and `HEAD` requests are redirected, the response of other
+/// requests is returned as is.
+pub(crate) async fn send(
+    request: reqwest::RequestBuilder,
+    timeouts: &PhaseTimeouts,
+) -> Result<Response, ErrorResponse> {
+    let (client, request) = request.build_split();
+    let request = request?;
+    let method = request.method().clone();
+    let mut headers = request.headers().clone();
+    headers.remove(header::AUTHORIZATION);
+    headers.remove(header::COOKIE);
+
+    let mut response = timeout::execute(&client, request, timeouts).await?;
+    for _ in 0..MAX_REDIRECTS {
+        let location = match location(&response) {
+            Some(location) if method == Method::GET || method == Method::HEAD => location,
+            _ => return Ok(response),
+        };
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            target: "oci_registry_client::redirect",
+            from = %response.url(),
+            to = %location,
+            status = response.status().as_u16(),
+            "following redirect without credentials"
+        );
+        let request = client
+            .request(method.clone(), location)
+            .headers(headers.clone())
+            .build()?;
+        response = timeout::execute(&client, request, timeouts).await?;
+    }
+    Ok(response)
+}
+
+/// Returns the target of a redirect response.
+fn location(response: &Response) -> Option<Url> {
+    if !response.status().is_redirection() {
+        return None;
+    }
+    let location = response.headers().get(header::LOCATION)?.to_str().ok()?;
+    response.url().join(location).ok()
+}
@@ -0,0 +1,528 @@
+//! Image references.
+//!
+//! References name an image as `[host[:port]/]repository[:tag][@digest]`,
+//! like `ghcr.io/org/app:1.2` or `ubuntu@sha256:...`. References without a
+//! host are Docker Hub images, and Docker Hub images without a namespace
+//! are official images (`ubuntu` is `docker.io/library/ubuntu`).
+//!
+//! References are validated against the [distribution
+//! grammar](https://github.com/distribution/reference/blob/main/reference.go):
+//! lowercase repository path components separated by `.`, `_`, `__` or
+//! dashes, tags of up to 128 word characters, dots and dashes. A
+//! [`Reference`] is displayed in its normalized form
+//! (`docker.io/library/ubuntu:latest`).
+//!
+//! Like the registry API, client methods take the repository and the tag or
+//! digest of an image separately. A [`Reference`] can be passed for both
+//! ([`AsRepository`] and [`AsTagOrDigest`]), to a client for the registry of
+//! the reference ([`DockerRegistryClientV2::for_reference`]).
+//!
+//! ```no_run
+//! # use oci_registry_client::DockerRegistryClientV2;
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let (client, reference) = DockerRegistryClientV2::from_reference("ghcr.io/org/app:1.2")?;
+//! let manifest = client
+//!     .manifest(&reference, &reference)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{errors::ErrorResponse, manifest::Digest, registries, DockerRegistryClientV2};
+use std::{borrow::Cow, error::Error, fmt, net::Ipv6Addr, str};
+
+/// Host of the images of references without a host.
+const DEFAULT_HOST: &str = "docker.io";
+
+/// Namespace of Docker Hub official images.
+const OFFICIAL_NAMESPACE: &str = "library";
+
+/// Tag of references with neither tag nor digest.
+const DEFAULT_TAG: &str = "latest";
+
+/// Maximum length of the name (host and repository) of a reference.
+const MAX_NAME_LENGTH: usize = 255;
+
+/// Maximum length of a tag.
+const MAX_TAG_LENGTH: usize = 128;
+
+/// A normalized image reference.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Reference {
+    /// Host (and port) of the registry, `docker.io` for Docker Hub.
+    pub host: String,
+    /// Repository in the registry, `library/ubuntu` for `ubuntu`.
+    pub repository: String,
+    /// Tag of the image, `latest` when the reference has neither tag nor
+    /// digest.
+    pub tag: Option<String>,
+    /// Digest of the image manifest.
+    pub digest: Option<Digest>,
+}
+
+impl Reference {
+    /// Returns the digest of the manifest if the reference has one, its tag
+    /// otherwise, as expected by [`DockerRegistryClientV2::manifest`].
+    pub fn tag_or_digest(&self) -> String {
+        match (&self.digest, &self.tag) {
+            (Some(digest), _) => digest.to_string(),
+            (None, Some(tag)) => tag.clone(),
+            (None, None) => DEFAULT_TAG.to_owned(),
+        }
+    }
+}
+
+impl str::FromStr for Reference {
+    type Err = ParseReferenceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = |reason| ParseReferenceError {
+            reference: s.to_owned(),
+            reason,
+        };
+
+        let (name, digest) = match s.split_once('@') {
+            Some((name, digest)) => {
+                let digest = digest
+                    .parse()
+                    .ok()
+                    .filter(valid_digest)
+                    .ok_or_else(|| error("invalid digest format"))?;
+                (name, Some(digest))
+            }
+            None => (s, None),
+        };
+
+        // A `:` after the last `/` separates the tag, others the port.
+        let (name, tag) = match name.rsplit_once(':') {
+            Some((name, tag)) if !tag.contains('/') => (name, Some(tag.to_owned())),
+            _ => (name, None),
+        };
+
+        // The first component is a host if it looks like one.
+        let (host, repository) = match name.split_once('/') {
+            Some((host, repository))
+                if host.contains(['.', ':'])
+                    || host == "localhost"
+                    || host.chars().any(|c| c.is_ascii_uppercase()) =>
+            {
+                if !valid_host(host) {
+                    return Err(error("invalid registry host"));
+                }
+                let host = registries::well_known(host)
+                    .map_or_else(|| host.to_owned(), |known| known.hosts[0].to_owned());
+                (host, repository)
+            }
+            _ => (DEFAULT_HOST.to_owned(), name),
+        };
+
+        if repository.is_empty() {
+            return Err(error("missing repository"));
+        }
+        if repository.chars().any(|c| c.is_ascii_uppercase()) {
+            return Err(error("repository name must be lowercase"));
+        }
+        if !repository.split('/').all(valid_path_component) {
+            return Err(error("invalid repository name"));
+        }
+        if let Some(tag) = &tag {
+            if !valid_tag(tag) {
+                return Err(error("invalid tag"));
+            }
+        }
+        let repository = match host == DEFAULT_HOST && !repository.contains('/') {
+            true => format!("{}/{}", OFFICIAL_NAMESPACE, repository),
+            false => repository.to_owned(),
+        };
+        if host.len() + 1 + repository.len() > MAX_NAME_LENGTH {
+            return Err(error("repository name too long"));
+        }
+        let tag = match (tag, &digest) {
+            (None, None) => Some(DEFAULT_TAG.to_owned()),
+            (tag, _) => tag,
+        };
+
+        Ok(Reference {
+            host,
+            repository,
+            tag,
+            digest,
+        })
+    }
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.host, self.repository)?;
+        if let Some(tag) = &self.tag {
+            write!(f, ":{}", tag)?;
+        }
+        if let Some(digest) = &self.digest {
+            write!(f, "@{}", digest)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns `true` if `component` is a valid repository path component:
+/// lowercase alphanumeric runs separated by `.`, `_`, `__` or dashes.
+fn valid_path_component(component: &str) -> bool {
+    let alphanumeric = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit();
+    let separator = |separator: &str| {
+        matches!(separator, "." | "_" | "__") || separator.chars().all(|c| c == '-')
+    };
+    component.starts_with(alphanumeric)
+        && component.ends_with(alphanumeric)
+        && component
+            .split(alphanumeric)
+            .filter(|run| !run.is_empty())
+            .all(separator)
+}
+
+/// Returns `true` if `host` is a valid registry host: a domain name or a
+/// bracketed IPv6 address, with an optional port.
+fn valid_host(host: &str) -> bool {
+    let (domain, port) = match host.strip_prefix('[') {
+        Some(rest) => match rest.split_once(']') {
+            Some((address, port)) if address.parse::<Ipv6Addr>().is_ok() => (None, port),
+            _ => return false,
+        },
+        None => match host.find(':') {
+            Some(n) => (Some(&host[..n]), &host[n..]),
+            None => (Some(host), ""),
+        },
+    };
+    let label = |label: &str| {
+        !label.is_empty()
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    let valid_port = port.is_empty()
+        || port
+            .strip_prefix(':')
+            .is_some_and(|port| !port.is_empty() && port.chars().all(|c| c.is_ascii_digit()));
+    valid_port && domain.is_none_or(|domain| domain.split('.').all(label))
+}
+
+/// Returns `true` if `digest` is a valid digest: a lowercase algorithm and
+/// an encoded hash, of the expected length for `sha256` and `sha512`.
+fn valid_digest(digest: &Digest) -> bool {
+    let hex = |length| {
+        digest.hash.len() == length
+            && digest
+                .hash
+                .chars()
+                .all(|c| matches!(c, '0'..='9' | 'a'..='f'))
+    };
+    match digest.algorithm.as_str() {
+        "sha256" => hex(64),
+        "sha512" => hex(128),
+        algorithm => {
+            valid_path_component(algorithm)
+                && digest.hash.len() >= 32
+                && digest
+                    .hash
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '=' | '_' | '-'))
+        }
+    }
+}
+
+/// Returns `true` if `tag` is a valid tag: up to 128 word characters, dots
+/// and dashes, not starting with a dot or a dash.
+fn valid_tag(tag: &str) -> bool {
+    let word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    tag.len() <= MAX_TAG_LENGTH
+        && tag.starts_with(word)
+        && tag.chars().all(|c| word(c) || c == '.' || c == '-')
+}
+
+/// Error parsing a [`Reference`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseReferenceError {
+    reference: String,
+    reason: &'static str,
+}
+
+impl fmt::Display for ParseReferenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid reference \"{}\": {}",
+            self.reference, self.reason
+        )
+    }
+}
+
+impl Error for ParseReferenceError {}
+
+/// Repository of an image, the `image` parameter of client methods:
+/// a repository name or a [`Reference`].
+pub trait AsRepository {
+    /// Returns the repository name.
+    fn as_repository(&self) -> &str;
+}
+
+impl AsRepository for str {
+    fn as_repository(&self) -> &str {
+        self
+    }
+}
+
+impl AsRepository for String {
+    fn as_repository(&self) -> &str {
+        self
+    }
+}
+
+impl AsRepository for Reference {
+    fn as_repository(&self) -> &str {
+        &self.repository
+    }
+}
+
+/// Manifest of a repository, the `reference` parameter of client methods:
+/// a tag, a digest or a [`Reference`] (see [`Reference::tag_or_digest`]).
+pub trait AsTagOrDigest {
+    /// Returns the tag or digest.
+    fn as_tag_or_digest(&self) -> Cow<'_, str>;
+}
+
+impl AsTagOrDigest for str {
+    fn as_tag_or_digest(&self) -> Cow<'_, str> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl AsTagOrDigest for String {
+    fn as_tag_or_digest(&self) -> Cow<'_, str> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl AsTagOrDigest for Digest {
+    fn as_tag_or_digest(&self) -> Cow<'_, str> {
+        Cow::Owned(self.to_string())
+    }
+}
+
+impl AsTagOrDigest for Reference {
+    fn as_tag_or_digest(&self) -> Cow<'_, str> {
+        match (&self.digest, &self.tag) {
+            (None, Some(tag)) => Cow::Borrowed(tag),
+            _ => Cow::Owned(self.tag_or_digest()),
+        }
+    }
+}
+
+impl DockerRegistryClientV2 {
+    /// Returns a client for the registry of the image `reference` and the
+    /// parsed reference, see [`crate::reference`].
+    ///
+    /// The endpoints of [well-known registries](crate::registries) are used,
+    /// other registries are reached with HTTPS, except on `localhost`.
+    pub fn from_reference(reference: &str) -> Result<(Self, Reference), ErrorResponse> {
+        let reference: Reference = reference.parse()?;
+        Ok((Self::for_reference(&reference)?, reference))
+    }
+
+    /// Returns a client for the registry of `reference`, see
+    /// [`DockerRegistryClientV2::from_reference`].
+    pub fn for_reference(reference: &Reference) -> Result<Self, ErrorResponse> {
+        let builder = match registries::well_known(&reference.host) {
+            Some(known) => Self::builder(known.api_url),
+            None => {
+                let local = ["localhost", "127.0.0.1", "[::1]"].iter().any(|local| {
+                    reference.host == *local || reference.host.starts_with(&format!("{}:", local))
+                });
+                Self::builder(reference.host.clone()).insecure_http(local)
+            }
+        };
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIGEST: &str = "sha256:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+    fn parse(reference: &str) -> Reference {
+        reference.parse().unwrap()
+    }
+
+    #[test]
+    fn normalizes_docker_hub_references() {
+        assert_eq!(
+            parse("ubuntu").to_string(),
+            "docker.io/library/ubuntu:latest"
+        );
+        assert_eq!(
+            parse("ubuntu:22.04").to_string(),
+            "docker.io/library/ubuntu:22.04"
+        );
+        assert_eq!(
+            parse("library/ubuntu").to_string(),
+            "docker.io/library/ubuntu:latest"
+        );
+        assert_eq!(parse("org/app").to_string(), "docker.io/org/app:latest");
+        assert_eq!(
+            parse("index.docker.io/ubuntu").to_string(),
+            "docker.io/library/ubuntu:latest"
+        );
+        assert_eq!(
+            parse("registry-1.docker.io/org/app:1").to_string(),
+            "docker.io/org/app:1"
+        );
+    }
+
+    #[test]
+    fn detects_hosts() {
+        let reference = parse("ghcr.io/org/app:1.2");
+        assert_eq!(reference.host, "ghcr.io");
+        assert_eq!(reference.repository, "org/app");
+        assert_eq!(reference.tag.as_deref(), Some("1.2"));
+
+        assert_eq!(parse("localhost/app").host, "localhost");
+        assert_eq!(parse("Registry/app").host, "Registry");
+        // Without a `.`, a `:` or uppercase letters, the first component is a
+        // namespace.
+        let reference = parse("registry/app");
+        assert_eq!(reference.host, "docker.io");
+        assert_eq!(reference.repository, "registry/app");
+    }
+
+    #[test]
+    fn parses_ports_and_ipv6_hosts() {
+        let reference = parse("localhost:5000/org/app:v1");
+        assert_eq!(reference.host, "localhost:5000");
+        assert_eq!(reference.repository, "org/app");
+        assert_eq!(reference.tag.as_deref(), Some("v1"));
+
+        let reference = parse("registry.example.com:443/app");
+        assert_eq!(reference.host, "registry.example.com:443");
+        assert_eq!(reference.tag.as_deref(), Some("latest"));
+
+        assert_eq!(parse("[::1]/app").host, "[::1]");
+        assert_eq!(
+            parse("[2001:db8::1]:5000/app:v1").host,
+            "[2001:db8::1]:5000"
+        );
+    }
+
+    #[test]
+    fn parses_digests() {
+        let reference = parse(&format!("ubuntu@{}", DIGEST));
+        assert_eq!(reference.tag, None);
+        assert_eq!(reference.digest, Some(DIGEST.parse().unwrap()));
+        assert_eq!(reference.tag_or_digest(), DIGEST);
+
+        let reference = parse(&format!("ghcr.io/org/app:1.2@{}", DIGEST));
+        assert_eq!(reference.tag.as_deref(), Some("1.2"));
+        assert_eq!(reference.tag_or_digest(), DIGEST);
+    }
+
+    #[test]
+    fn accepts_valid_names() {
+        for reference in [
+            "a",
+            "a0/b1",
+            "a.b/c_d",
+            "a__b",
+            "a-b--c---d",
+            "org/sub/group/app",
+            "app:v1.0_rc-1",
+            "app:_",
+            "127.0.0.1:5000/app",
+            "my-registry.example.com/app",
+        ] {
+            assert!(reference.parse::<Reference>().is_ok(), "{}", reference);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_names() {
+        for reference in [
+            "",
+            "Ubuntu",
+            "org/App",
+            "a//b",
+            "a/",
+            "-a",
+            "a-",
+            "a_",
+            "a___b",
+            "a..b",
+            "a._b",
+            "app:",
+            "app:.tag",
+            "app:-tag",
+            "app:t@g",
+            "ghcr.io/",
+            "my_registry.io/app",
+            "-registry.io/app",
+            "registry.io:/app",
+            "registry.io:port/app",
+            "[::1/app",
+            "[not-ipv6]:5000/app",
+            "app@sha256:abc",
+        ] {
+            assert!(reference.parse::<Reference>().is_err(), "{}", reference);
+        }
+    }
+
+    #[test]
+    fn limits_name_and_tag_lengths() {
+        // `docker.io/` and `library/` count in the name.
+        let name = "a".repeat(MAX_NAME_LENGTH - "docker.io/library/".len());
+        assert!(name.parse::<Reference>().is_ok());
+        assert!(format!("{}a", name).parse::<Reference>().is_err());
+
+        let tag = "t".repeat(MAX_TAG_LENGTH);
+        assert!(format!("app:{}", tag).parse::<Reference>().is_ok());
+        assert!(format!("app:{}t", tag).parse::<Reference>().is_err());
+    }
+
+    #[test]
+    fn round_trips_through_display() {
+        for reference in [
+            "docker.io/library/ubuntu:latest",
+            "ghcr.io/org/app:1.2",
+            "localhost:5000/org/app:v1",
+            "[::1]:5000/app:v1",
+            &format!("docker.io/library/ubuntu@{}", DIGEST),
+            &format!("quay.io/org/app:1@{}", DIGEST),
+        ] {
+            let parsed = parse(reference);
+            assert_eq!(parsed.to_string(), reference);
+            assert_eq!(parse(&parsed.to_string()), parsed);
+        }
+    }
+
+    #[test]
+    fn names_repository_and_manifest() {
+        let tagged = parse("ghcr.io/org/app:1.2");
+        assert_eq!(tagged.as_repository(), "org/app");
+        assert_eq!(tagged.as_tag_or_digest(), "1.2");
+
+        let pinned = parse(&format!("ubuntu:22.04@{}", DIGEST));
+        assert_eq!(pinned.as_repository(), "library/ubuntu");
+        assert_eq!(pinned.as_tag_or_digest(), DIGEST);
+        assert_eq!(parse("ubuntu").as_tag_or_digest(), "latest");
+
+        let digest: Digest = DIGEST.parse().unwrap();
+        assert_eq!(digest.as_tag_or_digest(), DIGEST);
+        assert_eq!("org/app".as_repository(), "org/app");
+    }
+
+    #[test]
+    fn reports_the_invalid_reference() {
+        let err = "Ubuntu".parse::<Reference>().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid reference \"Ubuntu\": repository name must be lowercase"
+        );
+    }
+}
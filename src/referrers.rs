@@ -0,0 +1,141 @@
+//! OCI 1.1 referrers API.
+//!
+//! Artifacts (signatures, SBOMs, attestations) are attached to an image by
+//! pushing a manifest whose `subject` is the image manifest.
+//! [`DockerRegistryClientV2::referrers`] lists them:
+//!
+//! ```no_run
+//! # use oci_registry_client::{manifest::Digest, DockerRegistryClientV2};
+//! # async fn example(client: DockerRegistryClientV2, digest: Digest) -> Result<(), Box<dyn std::error::Error>> {
+//! let index = client
+//!     .referrers("my/app", &digest, Some("application/spdx+json"))
+//!     .await?;
+//! for sbom in &index.manifests {
+//!     println!("{} ({:?})", sbom.digest, sbom.annotations);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    errors::{reject_html, ErrorResponse},
+    limits,
+    manifest::{Digest, ManifestList, MediaType},
+    pagination::next_page_url,
+    reference::AsRepository,
+    DockerRegistryClientV2, MEDIA_TYPE_OCI_IMAGE_INDEX_V1,
+};
+use reqwest::{header, StatusCode, Url};
+
+impl DockerRegistryClientV2 {
+    /// Returns an image index listing the manifests of `image` whose
+    /// `subject` is `digest`, optionally restricted to `artifact_type`.
+    ///
+    /// All pages are fetched. Registries without the referrers API are
+    /// queried using the fallback tag schema (`<alg>-<hash>` tag), an empty
+    /// index is returned if no referrer exists.
+    pub async fn referrers<I: AsRepository + ?Sized>(
+        &self,
+        image: &I,
+        digest: &Digest,
+        artifact_type: Option<&str>,
+    ) -> Result<ManifestList, ErrorResponse> {
+        let image = image.as_repository();
+        let mut url = format!("{}/v2/{}/referrers/{}", &self.api_url, image, digest);
+        if let Some(artifact_type) = artifact_type {
+            if let Ok(mut parsed) = Url::parse(&url) {
+                parsed
+                    .query_pairs_mut()
+                    .append_pair("artifactType", artifact_type);
+                url = parsed.to_string();
+            }
+        }
+
+        let mut index: Option<ManifestList> = None;
+        let mut next = Some(url);
+        while let Some(url) = next {
+            let request = self
+                .client
+                .get(&url)
+                .header(header::ACCEPT, MEDIA_TYPE_OCI_IMAGE_INDEX_V1);
+            let request = self.authorize(request, Some(image)).await?;
+            let response = self
+                .send_with_retries(request, &self.timeouts.manifests)
+                .await?;
+
+            match response.status() {
+                StatusCode::OK => {}
+                StatusCode::NOT_FOUND if index.is_none() => {
+                    return self.referrers_tag(image, digest, artifact_type).await;
+                }
+                _ => return Err(ErrorResponse::from_response(response).await),
+            }
+
+            let filtered = response
+                .headers()
+                .get("oci-filters-applied")
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.split(',').any(|f| f.trim() == "artifactType"))
+                .unwrap_or(false);
+            next = next_page_url(&url, response.headers());
+
+            let response = reject_html(response).await?;
+            let body = limits::read_body(
+                response,
+                self.size_limits.max_manifest_size,
+                self.timeouts.manifests.idle,
+            )
+            .await?;
+            let mut page: ManifestList = serde_json::from_slice(&body)?;
+            if !filtered {
+                retain_artifact_type(&mut page, artifact_type);
+            }
+            match &mut index {
+                Some(index) => index.manifests.extend(page.manifests),
+                None => index = Some(page),
+            }
+        }
+
+        Ok(index.unwrap_or_else(empty_index))
+    }
+
+    /// Returns the referrers of `digest` from the fallback tag.
+    async fn referrers_tag(
+        &self,
+        image: &str,
+        digest: &Digest,
+        artifact_type: Option<&str>,
+    ) -> Result<ManifestList, ErrorResponse> {
+        let tag = format!("{}-{}", digest.algorithm, digest.hash);
+        let url = format!("{}/v2/{}/manifests/{}", &self.api_url, image, tag);
+        if !self
+            .exists(&url, image, &[self.manifest_accept.as_str()])
+            .await?
+        {
+            return Ok(empty_index());
+        }
+
+        let mut index = self.list_manifests(image, &tag).await?;
+        retain_artifact_type(&mut index, artifact_type);
+        Ok(index)
+    }
+}
+
+fn retain_artifact_type(index: &mut ManifestList, artifact_type: Option<&str>) {
+    if let Some(artifact_type) = artifact_type {
+        index
+            .manifests
+            .retain(|item| item.artifact_type.as_deref() == Some(artifact_type));
+    }
+}
+
+fn empty_index() -> ManifestList {
+    ManifestList {
+        schema_version: 2,
+        media_type: Some(MediaType::OciImageIndex),
+        manifests: vec![],
+        artifact_type: None,
+        subject: None,
+        annotations: None,
+    }
+}
@@ -0,0 +1,111 @@
+//! Well-known registries.
+//!
+//! Public registries do not follow a single convention for their API and
+//! token endpoints. The constructors of this module configure them:
+//!
+//! ```no_run
+//! # use oci_registry_client::DockerRegistryClientV2;
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = DockerRegistryClientV2::docker_hub()?;
+//! let manifest = client.manifest("library/alpine", "latest").await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The [builder](crate::builder) of a client for one of these registries
+//! defaults to its service and token endpoint.
+
+use crate::{errors::ErrorResponse, DockerRegistryClientV2};
+
+/// Endpoints of a registry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Endpoints {
+    /// Host names of the registry in image references, the canonical one
+    /// first.
+    pub(crate) hosts: &'static [&'static str],
+    pub(crate) service: &'static str,
+    pub(crate) api_url: &'static str,
+    pub(crate) auth_url: &'static str,
+}
+
+const DOCKER_HUB: Endpoints = Endpoints {
+    hosts: &[
+        "docker.io",
+        "index.docker.io",
+        "registry-1.docker.io",
+        "registry.hub.docker.com",
+    ],
+    service: "registry.docker.io",
+    api_url: "https://registry-1.docker.io",
+    auth_url: "https://auth.docker.io/token",
+};
+
+const GHCR: Endpoints = Endpoints {
+    hosts: &["ghcr.io"],
+    service: "ghcr.io",
+    api_url: "https://ghcr.io",
+    auth_url: "https://ghcr.io/token",
+};
+
+const QUAY: Endpoints = Endpoints {
+    hosts: &["quay.io"],
+    service: "quay.io",
+    api_url: "https://quay.io",
+    auth_url: "https://quay.io/v2/auth",
+};
+
+const GCR: Endpoints = Endpoints {
+    hosts: &["gcr.io"],
+    service: "gcr.io",
+    api_url: "https://gcr.io",
+    auth_url: "https://gcr.io/v2/token",
+};
+
+const ECR_PUBLIC: Endpoints = Endpoints {
+    hosts: &["public.ecr.aws"],
+    service: "public.ecr.aws",
+    api_url: "https://public.ecr.aws",
+    auth_url: "https://public.ecr.aws/token",
+};
+
+const WELL_KNOWN: [Endpoints; 5] = [DOCKER_HUB, GHCR, QUAY, GCR, ECR_PUBLIC];
+
+/// Returns the endpoints of the well-known registry at `host`, if any.
+pub(crate) fn well_known(host: &str) -> Option<Endpoints> {
+    let host = host.to_ascii_lowercase();
+    WELL_KNOWN
+        .into_iter()
+        .find(|endpoints| endpoints.hosts.contains(&host.as_str()))
+}
+
+impl DockerRegistryClientV2 {
+    fn from_endpoints(endpoints: Endpoints) -> Result<Self, ErrorResponse> {
+        Self::try_new(endpoints.service, endpoints.api_url, endpoints.auth_url)
+    }
+
+    /// Returns a client for Docker Hub (`docker.io`).
+    pub fn docker_hub() -> Result<Self, ErrorResponse> {
+        Self::from_endpoints(DOCKER_HUB)
+    }
+
+    /// Returns a client for the GitHub Container Registry (`ghcr.io`).
+    pub fn ghcr() -> Result<Self, ErrorResponse> {
+        Self::from_endpoints(GHCR)
+    }
+
+    /// Returns a client for Quay (`quay.io`).
+    pub fn quay() -> Result<Self, ErrorResponse> {
+        Self::from_endpoints(QUAY)
+    }
+
+    /// Returns a client for the Google Container Registry (`gcr.io`).
+    pub fn gcr() -> Result<Self, ErrorResponse> {
+        Self::from_endpoints(GCR)
+    }
+
+    /// Returns a client for the Amazon ECR Public Gallery
+    /// (`public.ecr.aws`).
+    pub fn ecr_public() -> Result<Self, ErrorResponse> {
+        Self::from_endpoints(ECR_PUBLIC)
+    }
+}
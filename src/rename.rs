@@ -0,0 +1,147 @@
+//! Repository name transformation rules.
+//!
+//! Destination registries often have a different namespace layout than the
+//! source. [`NameRules`] rewrite repository names when copying or mirroring
+//! images. Rules are applied in order, each to the output of the previous
+//! one, and can be loaded from configuration files:
+//!
+//! ```
+//! use oci_registry_client::rename::{NameRule, NameRules};
+//!
+//! let rules: Vec<NameRule> = serde_json::from_str(r#"[
+//!     {"type": "prefix", "from": "library", "to": "mirror/dockerhub"},
+//!     {"type": "flatten", "separator": "-"}
+//! ]"#).unwrap();
+//! let rules = NameRules::new(rules).unwrap();
+//!
+//! assert_eq!(rules.apply("library/alpine"), "mirror-dockerhub-alpine");
+//! assert_eq!(rules.apply("other/app"), "other-app");
+//! ```
+
+use std::{error::Error, fmt};
+
+/// A single name transformation.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NameRule {
+    /// Replace the leading path components `from` with `to`.
+    ///
+    /// Only whole components match: `library` matches `library/alpine` but
+    /// not `libraryx/alpine`.
+    Prefix { from: String, to: String },
+    /// Replace every match of `pattern` with `replacement` (which may refer
+    /// to capture groups, example: `$1`).
+    #[cfg(feature = "regex")]
+    Regex {
+        pattern: String,
+        replacement: String,
+    },
+    /// Flatten nested repositories into a single path component.
+    ///
+    /// Components are joined with `separator`, without a separator only the
+    /// last component is kept.
+    Flatten {
+        #[serde(default)]
+        separator: Option<String>,
+    },
+}
+
+/// An ordered list of [`NameRule`]s.
+#[derive(Clone, Debug, Default)]
+pub struct NameRules {
+    rules: Vec<CompiledRule>,
+}
+
+#[derive(Clone, Debug)]
+enum CompiledRule {
+    Prefix {
+        from: String,
+        to: String,
+    },
+    #[cfg(feature = "regex")]
+    Regex {
+        regex: regex::Regex,
+        replacement: String,
+    },
+    Flatten {
+        separator: Option<String>,
+    },
+}
+
+impl NameRules {
+    /// Returns a `NameRules` applying `rules` in order.
+    pub fn new<I: IntoIterator<Item = NameRule>>(rules: I) -> Result<Self, InvalidNameRule> {
+        let rules = rules
+            .into_iter()
+            .map(|rule| match rule {
+                NameRule::Prefix { from, to } => Ok(CompiledRule::Prefix {
+                    from: from.trim_matches('/').to_owned(),
+                    to: to.trim_matches('/').to_owned(),
+                }),
+                #[cfg(feature = "regex")]
+                NameRule::Regex {
+                    pattern,
+                    replacement,
+                } => Ok(CompiledRule::Regex {
+                    regex: regex::Regex::new(&pattern)
+                        .map_err(|err| InvalidNameRule(err.to_string()))?,
+                    replacement,
+                }),
+                NameRule::Flatten { separator } => Ok(CompiledRule::Flatten { separator }),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { rules })
+    }
+
+    /// Returns `name` transformed by every rule.
+    pub fn apply(&self, name: &str) -> String {
+        self.rules
+            .iter()
+            .fold(name.to_owned(), |name, rule| rule.apply(&name))
+    }
+}
+
+impl CompiledRule {
+    fn apply(&self, name: &str) -> String {
+        match self {
+            Self::Prefix { from, to } => {
+                let rest = if from.is_empty() {
+                    Some(name)
+                } else if name == from {
+                    Some("")
+                } else {
+                    name.strip_prefix(from.as_str())
+                        .and_then(|rest| rest.strip_prefix('/'))
+                };
+
+                match rest {
+                    Some("") => to.clone(),
+                    Some(rest) if to.is_empty() => rest.to_owned(),
+                    Some(rest) => format!("{}/{}", to, rest),
+                    None => name.to_owned(),
+                }
+            }
+            #[cfg(feature = "regex")]
+            Self::Regex { regex, replacement } => {
+                regex.replace_all(name, replacement.as_str()).into_owned()
+            }
+            Self::Flatten { separator } => match separator {
+                Some(separator) => name.split('/').collect::<Vec<_>>().join(separator),
+                None => name.rsplit('/').next().unwrap_or(name).to_owned(),
+            },
+        }
+    }
+}
+
+/// Error returned when a [`NameRule`] is invalid.
+#[derive(Debug, PartialEq)]
+pub struct InvalidNameRule(pub String);
+
+impl fmt::Display for InvalidNameRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid name rule: {}", self.0)
+    }
+}
+
+impl Error for InvalidNameRule {}
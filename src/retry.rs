@@ -0,0 +1,153 @@
+//! Retries of throttled and failed requests.
+//!
+//! Registries answer `429 Too Many Requests` when rate limits are hit and
+//! occasionally fail with `5xx` errors. With a [`RetryPolicy`], manifest,
+//! token and blob requests answered with one of its statuses are sent
+//! again after an exponential backoff, or after the delay announced by the
+//! `Retry-After` header of the response. Only the start of blob downloads is
+//! retried, see [`DockerRegistryClientV2::set_chunk_retries`] to resume
+//! interrupted downloads.
+//!
+//! ```
+//! # use oci_registry_client::{retry::RetryPolicy, DockerRegistryClientV2};
+//! # use std::time::Duration;
+//! # let mut client = DockerRegistryClientV2::try_new("registry.docker.io", "https://registry-1.docker.io", "https://auth.docker.io/token").unwrap();
+//! client.set_retry_policy(Some(RetryPolicy {
+//!     max_retries: 5,
+//!     initial_backoff: Duration::from_millis(500),
+//!     ..RetryPolicy::default()
+//! }));
+//! ```
+
+use crate::{errors::ErrorResponse, timeout, timeout::PhaseTimeouts, DockerRegistryClientV2};
+use reqwest::{header, RequestBuilder, Response, StatusCode};
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::{Duration, SystemTime},
+};
+
+/// Policy of request retries, see the [module](self) documentation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Number of times a request is retried.
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled on every attempt.
+    pub initial_backoff: Duration,
+    /// Maximum delay between two attempts. Responses asking (with a
+    /// `Retry-After` header) to wait longer are not retried.
+    pub max_backoff: Duration,
+    /// Randomize delays (between half and all of the backoff), so clients
+    /// throttled at the same time do not retry at the same time.
+    pub jitter: bool,
+    /// Response statuses retried.
+    pub statuses: Vec<StatusCode>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            jitter: true,
+            statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the delay before the retry following `attempt` (starting at
+    /// 0).
+    fn backoff(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff);
+        match self.jitter {
+            true => backoff.mul_f64(0.5 + random() * 0.5),
+            false => backoff,
+        }
+    }
+}
+
+/// Returns the delay announced by the `Retry-After` header of `response`,
+/// in seconds or as an HTTP date.
+pub(crate) fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response
+        .headers()
+        .get(header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+    match value.parse() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => {
+            let date = httpdate::parse_http_date(value).ok()?;
+            Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+        }
+    }
+}
+
+/// Returns a pseudo-random number in `[0, 1)`.
+fn random() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+impl DockerRegistryClientV2 {
+    /// Set the policy of request retries, see [`crate::retry`]. Requests are
+    /// not retried by default.
+    pub fn set_retry_policy(&mut self, policy: Option<RetryPolicy>) {
+        self.retry = policy;
+    }
+
+    /// Send `request` (see [`timeout::send`]), retrying it according to the
+    /// retry policy of the client.
+    pub(crate) async fn send_with_retries(
+        &self,
+        mut request: RequestBuilder,
+        timeouts: &PhaseTimeouts,
+    ) -> Result<Response, ErrorResponse> {
+        let policy = match &self.retry {
+            Some(policy) => policy,
+            None => return timeout::send(request, timeouts).await,
+        };
+
+        let mut attempt = 0;
+        loop {
+            let retry = match attempt < policy.max_retries {
+                true => request.try_clone(),
+                false => None,
+            };
+            let response = timeout::send(request, timeouts).await?;
+            match retry {
+                Some(retry) if policy.statuses.contains(&response.status()) => {
+                    let delay = match retry_after(&response) {
+                        Some(delay) if delay > policy.max_backoff => return Ok(response),
+                        Some(delay) => delay,
+                        None => policy.backoff(attempt),
+                    };
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(
+                        target: "oci_registry_client::retry",
+                        url = %response.url(),
+                        status = response.status().as_u16(),
+                        attempt,
+                        "retrying request"
+                    );
+                    tokio::time::sleep(delay).await;
+                    request = retry;
+                    attempt += 1;
+                }
+                _ => return Ok(response),
+            }
+        }
+    }
+}
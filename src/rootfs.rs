@@ -0,0 +1,791 @@
+//! Root filesystem extraction.
+//!
+//! [`DockerRegistryClientV2::unpack_rootfs`] applies the layers of an image
+//! in order into a directory, producing the root filesystem of the image.
+//! [OCI whiteouts](https://github.com/opencontainers/image-spec/blob/main/layer.md#whiteouts)
+//! are applied as layers are unpacked: a `.wh.<name>` entry removes `<name>`
+//! from the lower layers and a `.wh..wh..opq` entry removes all lower layer
+//! contents of its directory. Whiteout files themselves are not unpacked.
+//!
+//! ```no_run
+//! # use oci_registry_client::{manifest::host_architecture, DockerRegistryClientV2};
+//! # async fn example(client: DockerRegistryClientV2) -> Result<(), Box<dyn std::error::Error>> {
+//! let manifest = client
+//!     .manifest_for_platform("library/alpine", "latest", "linux", host_architecture())
+//!     .await?;
+//! client
+//!     .unpack_rootfs("library/alpine", &manifest, "/tmp/alpine")
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Unpacking follows the rules of [`DecompressedBlob::untar_to`], no entry
+//! (or whiteout) touches files outside of the target directory. File
+//! ownership is not restored.
+//!
+//! [`DockerRegistryClientV2::export_rootfs`] streams the root filesystem as
+//! a single tarball instead, keeping file ownership, to import it as a
+//! `scratch` image or as the root of a container:
+//!
+//! ```no_run
+//! # use oci_registry_client::{manifest::Manifest, DockerRegistryClientV2};
+//! # async fn example(client: DockerRegistryClientV2, manifest: Manifest) -> Result<(), Box<dyn std::error::Error>> {
+//! let mut file = tokio::fs::File::create("alpine-rootfs.tar").await?;
+//! client
+//!     .export_rootfs("library/alpine", &manifest, &mut file)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    audit::Operation,
+    blob::{self, DecompressedBlob},
+    errors::ErrorResponse,
+    manifest::Manifest,
+    reference::AsRepository,
+    store, DockerRegistryClientV2,
+};
+use bytes::Bytes;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::{self, Read, Write},
+    path::{Component, Path, PathBuf},
+};
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+};
+use tokio_util::io::SyncIoBridge;
+
+const WHITEOUT_PREFIX: &str = ".wh.";
+const WHITEOUT_OPAQUE: &str = ".wh..wh..opq";
+
+/// Size of the chunks of the tarball written by
+/// [`DockerRegistryClientV2::export_rootfs`].
+const EXPORT_CHUNK_SIZE: usize = 64 * 1024;
+
+impl DockerRegistryClientV2 {
+    /// Unpack the layers of `manifest` into the directory `path`, creating
+    /// it if needed, see [`crate::rootfs`].
+    ///
+    /// Layers are checked against the `diff_ids` of the image config if the
+    /// [`crate::verify::VerificationPolicy`] of the client verifies them.
+    pub async fn unpack_rootfs<I: AsRepository + ?Sized, P: AsRef<Path>>(
+        &self,
+        image: &I,
+        manifest: &Manifest,
+        path: P,
+    ) -> Result<(), ErrorResponse> {
+        let image = image.as_repository();
+        let operation =
+            Operation::start("unpack_rootfs", image, &manifest.config.digest.to_string());
+        let result = self
+            .unpack_layers(&operation, image, manifest, path.as_ref())
+            .await;
+        operation.finish(result.as_ref().map(|_| &manifest.config.digest));
+        result
+    }
+
+    async fn unpack_layers(
+        &self,
+        operation: &Operation,
+        image: &str,
+        manifest: &Manifest,
+        path: &Path,
+    ) -> Result<(), ErrorResponse> {
+        tokio::fs::create_dir_all(path).await?;
+
+        let diff_ids = match self.verification.verifies_diff_ids() {
+            true => self.manifest_config(image, manifest).await?.rootfs.diff_ids,
+            false => vec![],
+        };
+        for (n, layer) in manifest.layers.iter().enumerate() {
+            let mut contents = self.layer_blob(image, layer).await?.decompress().await?;
+            if let Some(diff_id) = diff_ids.get(n) {
+                contents = contents.verify(diff_id.clone(), self.verification);
+            }
+            apply_layer(contents, path).await?;
+            operation.transferred(layer.size as u64);
+        }
+        Ok(())
+    }
+
+    /// Write the root filesystem of `manifest` to `writer` as a tarball,
+    /// see [`crate::rootfs`].
+    ///
+    /// Layers are read once, from the top one down: entries hidden by upper
+    /// layers or removed by whiteouts are skipped, so the tarball has a
+    /// single entry per file, in no particular order (hard links come last,
+    /// after their targets, or written as a copy of their target if an
+    /// upper layer removes or replaces it). Layers are checked against the `diff_ids` of the
+    /// image config if the [`crate::verify::VerificationPolicy`] of the
+    /// client verifies them.
+    pub async fn export_rootfs<I: AsRepository + ?Sized, W>(
+        &self,
+        image: &I,
+        manifest: &Manifest,
+        writer: &mut W,
+    ) -> Result<(), ErrorResponse>
+    where
+        W: AsyncWrite + Unpin + ?Sized,
+    {
+        let image = image.as_repository();
+        let operation =
+            Operation::start("export_rootfs", image, &manifest.config.digest.to_string());
+        let result = self
+            .export_layers(&operation, image, manifest, writer)
+            .await;
+        operation.finish(result.as_ref().map(|_| &manifest.config.digest));
+        result
+    }
+
+    async fn export_layers<W>(
+        &self,
+        operation: &Operation,
+        image: &str,
+        manifest: &Manifest,
+        writer: &mut W,
+    ) -> Result<(), ErrorResponse>
+    where
+        W: AsyncWrite + Unpin + ?Sized,
+    {
+        let diff_ids = match self.verification.verifies_diff_ids() {
+            true => self.manifest_config(image, manifest).await?.rootfs.diff_ids,
+            false => vec![],
+        };
+
+        let (sender, mut receiver) = mpsc::channel(16);
+        let export = async move {
+            let writer = io::BufWriter::with_capacity(EXPORT_CHUNK_SIZE, ChannelWriter(sender));
+            let mut builder = tar::Builder::new(writer);
+            let mut flattened = Flattened::default();
+            for (n, layer) in manifest.layers.iter().enumerate().rev() {
+                let mut contents = self.layer_blob(image, layer).await?.decompress().await?;
+                if let Some(diff_id) = diff_ids.get(n) {
+                    contents = contents.verify(diff_id.clone(), self.verification);
+                }
+                (builder, flattened) = tokio::task::spawn_blocking(move || {
+                    let mut archive = tar::Archive::new(SyncIoBridge::new(contents));
+                    flattened.add_layer(&mut archive, &mut builder)?;
+                    // Read to the end to verify the contents.
+                    io::copy(&mut archive.into_inner(), &mut io::sink())?;
+                    Ok((builder, flattened))
+                })
+                .await
+                .map_err(io::Error::other)?
+                .map_err(blob::into_error_response)?;
+                operation.transferred(layer.size as u64);
+            }
+            tokio::task::spawn_blocking(move || {
+                flattened.add_links(&mut builder)?;
+                builder.into_inner()?.flush()
+            })
+            .await
+            .map_err(io::Error::other)??;
+            Ok::<_, ErrorResponse>(())
+        };
+        // Dropping the receiver once writing fails stops the export.
+        let write = async move {
+            while let Some(chunk) = receiver.recv().await {
+                writer.write_all(&chunk).await?;
+            }
+            writer.flush().await
+        };
+
+        let (exported, written) = tokio::join!(export, write);
+        // A failed write makes the export fail as well, report its cause.
+        written?;
+        exported
+    }
+}
+
+/// Entries of the layers exported so far, from the top layer down.
+#[derive(Default)]
+struct Flattened {
+    /// Paths of the entries, mapped to whether they are directories.
+    entries: HashMap<PathBuf, bool>,
+    /// Paths removed by whiteouts.
+    whiteouts: HashSet<PathBuf>,
+    /// Directories hiding the contents of the lower layers.
+    opaque: HashSet<PathBuf>,
+    /// Hard links, written once all files are.
+    links: Vec<(tar::Header, PathBuf, PathBuf)>,
+}
+
+impl Flattened {
+    /// Returns `true` if the entry at `path` of a lower layer is hidden by
+    /// the layers exported so far.
+    fn hides(&self, path: &Path) -> bool {
+        if self.entries.contains_key(path) || self.whiteouts.contains(path) {
+            return true;
+        }
+        path.ancestors().skip(1).any(|parent| {
+            self.whiteouts.contains(parent)
+                || self.opaque.contains(parent)
+                || self.entries.get(parent) == Some(&false)
+        })
+    }
+
+    /// Write the entries of the layer `archive` not hidden by the upper
+    /// layers to `builder`.
+    fn add_layer<R: Read, W: io::Write>(
+        &mut self,
+        archive: &mut tar::Archive<R>,
+        builder: &mut tar::Builder<W>,
+    ) -> io::Result<()> {
+        // The entries of a layer only hide the lower layers.
+        let mut layer = Flattened::default();
+        let mut stash = Stash::new();
+        // Targets hidden by the upper layers, mapped to the first hard link
+        // to them, written as a copy of the target.
+        let mut copies = HashMap::new();
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = match normalize(&entry.path()?) {
+                Some(path) => path,
+                None => continue,
+            };
+            let name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or_default();
+
+            if name == WHITEOUT_OPAQUE {
+                let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+                layer.opaque.insert(dir);
+                continue;
+            }
+            if let Some(hidden) = name.strip_prefix(WHITEOUT_PREFIX) {
+                layer.whiteouts.insert(path.with_file_name(hidden));
+                continue;
+            }
+            if self.hides(&path) {
+                if entry.header().entry_type().is_file() {
+                    stash.insert(path, &mut entry)?;
+                }
+                continue;
+            }
+
+            let mut header = entry.header().clone();
+            let entry_type = header.entry_type();
+            layer.entries.insert(path.clone(), entry_type.is_dir());
+            match entry_type {
+                tar::EntryType::Link => {
+                    let target = match entry.link_name()?.as_deref().and_then(normalize) {
+                        Some(target) => target,
+                        None => continue,
+                    };
+                    if !self.hides(&target) {
+                        layer.links.push((header, path, target));
+                    } else if let Some(copy) = copies.get(&target) {
+                        layer.links.push((header, path, PathBuf::clone(copy)));
+                    } else if let Some(stashed) = stash.get(&target) {
+                        // The target is removed or replaced by the upper
+                        // layers, the link keeps the contents of this layer.
+                        let mut file = fs::File::open(stashed)?;
+                        header.set_entry_type(tar::EntryType::Regular);
+                        header.set_size(file.metadata()?.len());
+                        header.as_old_mut().linkname = [0; 100];
+                        builder.append_data(&mut header, &path, &mut file)?;
+                        copies.insert(target, path);
+                    }
+                }
+                tar::EntryType::Symlink => {
+                    if let Some(target) = entry.link_name()? {
+                        builder.append_link(&mut header, &path, target)?;
+                    }
+                }
+                tar::EntryType::XGlobalHeader => {}
+                _ => builder.append_data(&mut header, &path, &mut entry)?,
+            }
+        }
+
+        self.entries.extend(layer.entries);
+        self.whiteouts.extend(layer.whiteouts);
+        self.opaque.extend(layer.opaque);
+        self.links.extend(layer.links);
+        Ok(())
+    }
+
+    /// Write the hard links of the exported layers to `builder`.
+    fn add_links<W: io::Write>(&mut self, builder: &mut tar::Builder<W>) -> io::Result<()> {
+        for (header, path, target) in &mut self.links {
+            builder.append_link(header, &*path, &*target)?;
+        }
+        Ok(())
+    }
+}
+
+/// Contents of the files of a layer hidden by the upper layers, kept while
+/// the layer is exported for the hard links pointing to them.
+struct Stash {
+    dir: PathBuf,
+    files: HashMap<PathBuf, PathBuf>,
+}
+
+impl Stash {
+    fn new() -> Self {
+        Self {
+            dir: std::env::temp_dir().join(format!("rootfs-export-{}", store::unique_suffix())),
+            files: HashMap::new(),
+        }
+    }
+
+    /// Keep the `contents` of the file at `path`.
+    fn insert<R: Read>(&mut self, path: PathBuf, contents: &mut R) -> io::Result<()> {
+        if self.files.is_empty() {
+            fs::create_dir_all(&self.dir)?;
+        }
+        let file = self.dir.join(self.files.len().to_string());
+        io::copy(contents, &mut fs::File::create(&file)?)?;
+        self.files.insert(path, file);
+        Ok(())
+    }
+
+    /// Returns the file holding the contents kept for `path`.
+    fn get(&self, path: &Path) -> Option<&Path> {
+        self.files.get(path).map(PathBuf::as_path)
+    }
+}
+
+impl Drop for Stash {
+    fn drop(&mut self) {
+        if !self.files.is_empty() {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+/// Writer sending the exported tarball to the task writing it.
+struct ChannelWriter(mpsc::Sender<Bytes>);
+
+impl io::Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .blocking_send(Bytes::copy_from_slice(buf))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Apply the (decompressed) layer `contents` on top of the root filesystem
+/// in the directory `root`, handling whiteouts.
+pub async fn apply_layer<P: AsRef<Path>>(
+    contents: DecompressedBlob,
+    root: P,
+) -> Result<(), ErrorResponse> {
+    let root = root.as_ref().to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut archive = tar::Archive::new(SyncIoBridge::new(contents));
+        archive.set_preserve_permissions(true);
+        apply_entries(&mut archive, &root)?;
+        // Read to the end to verify the contents.
+        io::copy(&mut archive.into_inner(), &mut io::sink())?;
+        Ok(())
+    })
+    .await
+    .map_err(io::Error::other)?
+    .map_err(blob::into_error_response)
+}
+
+fn apply_entries<R: io::Read>(archive: &mut tar::Archive<R>, root: &Path) -> io::Result<()> {
+    let root = root.canonicalize()?;
+    // Paths (and their parents) added by this layer, opaque whiteouts only
+    // hide the lower layers.
+    let mut added = HashSet::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = match normalize(&entry.path()?) {
+            Some(path) => path,
+            None => continue,
+        };
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+
+        if name == WHITEOUT_OPAQUE {
+            let dir = path.parent().unwrap_or(Path::new(""));
+            if let Some(target) = resolve(&root, dir) {
+                clear_dir(&target, dir, &added)?;
+            }
+            continue;
+        }
+        if let Some(hidden) = name.strip_prefix(WHITEOUT_PREFIX) {
+            if let Some(target) = resolve(&root, &path.with_file_name(hidden)) {
+                remove(&target)?;
+            }
+            continue;
+        }
+
+        // A directory replaces a file of a lower layer and conversely.
+        if let Some(target) = resolve(&root, &path) {
+            if let Ok(metadata) = target.symlink_metadata() {
+                let is_dir = entry.header().entry_type().is_dir();
+                if metadata.is_dir() != is_dir {
+                    remove(&target)?;
+                }
+            }
+        }
+
+        entry.unpack_in(&root)?;
+        added.extend(path.ancestors().map(Path::to_path_buf));
+    }
+    Ok(())
+}
+
+/// Returns `path` relative to the root of the layer, or `None` if it is the
+/// root itself or escapes it.
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+            Component::ParentDir => return None,
+        }
+    }
+    match normalized.as_os_str().is_empty() {
+        true => None,
+        false => Some(normalized),
+    }
+}
+
+/// Returns the location of `path` under `root`, or `None` if its parent does
+/// not exist or is outside of `root` (through symlinks).
+fn resolve(root: &Path, path: &Path) -> Option<PathBuf> {
+    if path.as_os_str().is_empty() {
+        return Some(root.to_path_buf());
+    }
+    let parent = root.join(path.parent()?).canonicalize().ok()?;
+    if !parent.starts_with(root) {
+        return None;
+    }
+    match path.file_name() {
+        Some(name) => Some(parent.join(name)),
+        None => Some(parent),
+    }
+}
+
+/// Remove the contents of `dir` (at `relative` in the layer) not added by
+/// the current layer.
+fn clear_dir(dir: &Path, relative: &Path, added: &HashSet<PathBuf>) -> io::Result<()> {
+    // Never follow a symlink out of the root.
+    match dir.symlink_metadata() {
+        Ok(metadata) if metadata.is_dir() => {}
+        _ => return Ok(()),
+    }
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !added.contains(&relative.join(entry.file_name())) {
+            remove(&entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove the file, symlink or directory at `path`, if any.
+fn remove(path: &Path) -> io::Result<()> {
+    let result = match path.symlink_metadata() {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(err) => Err(err),
+    };
+    match result {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    enum Entry<'a> {
+        File(&'a str, &'a str),
+        Dir(&'a str),
+        Symlink(&'a str, &'a str),
+        Link(&'a str, &'a str),
+    }
+    use Entry::*;
+
+    /// Returns an uncompressed layer holding `entries`.
+    fn layer(entries: &[Entry]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(vec![]);
+        for entry in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_mode(0o644);
+            match entry {
+                File(path, contents) => {
+                    header.set_size(contents.len() as u64);
+                    builder
+                        .append_data(&mut header, path, contents.as_bytes())
+                        .unwrap();
+                }
+                Dir(path) => {
+                    header.set_entry_type(tar::EntryType::Directory);
+                    header.set_mode(0o755);
+                    header.set_size(0);
+                    builder.append_data(&mut header, path, io::empty()).unwrap();
+                }
+                Symlink(path, target) => {
+                    header.set_entry_type(tar::EntryType::Symlink);
+                    header.set_size(0);
+                    builder.append_link(&mut header, path, target).unwrap();
+                }
+                Link(path, target) => {
+                    header.set_entry_type(tar::EntryType::Link);
+                    header.set_size(0);
+                    builder.append_link(&mut header, path, target).unwrap();
+                }
+            }
+        }
+        builder.into_inner().unwrap()
+    }
+
+    /// Returns the entries (path and contents or link target) of the
+    /// tarball exporting `layers` (base layer first).
+    fn export(layers: &[Vec<u8>]) -> Vec<(String, String)> {
+        let mut builder = tar::Builder::new(vec![]);
+        let mut flattened = Flattened::default();
+        for layer in layers.iter().rev() {
+            let mut archive = tar::Archive::new(&layer[..]);
+            flattened.add_layer(&mut archive, &mut builder).unwrap();
+        }
+        flattened.add_links(&mut builder).unwrap();
+
+        let tarball = builder.into_inner().unwrap();
+        let mut archive = tar::Archive::new(&tarball[..]);
+        archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let path = entry.path().unwrap().display().to_string();
+                let mut contents = String::new();
+                match entry.link_name().unwrap() {
+                    Some(target) => contents = format!("-> {}", target.display()),
+                    None => {
+                        entry.read_to_string(&mut contents).unwrap();
+                    }
+                }
+                (path, contents)
+            })
+            .collect()
+    }
+
+    fn paths(entries: &[(String, String)]) -> Vec<&str> {
+        let mut paths: Vec<_> = entries.iter().map(|(path, _)| path.as_str()).collect();
+        paths.sort();
+        paths
+    }
+
+    /// A temporary directory, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let path = std::env::temp_dir().join(format!("rootfs-{}", store::unique_suffix()));
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Unpack `layers` (base layer first) into a new directory.
+    fn unpack(layers: &[Vec<u8>]) -> TempDir {
+        let root = TempDir::new();
+        for layer in layers {
+            apply_entries(&mut tar::Archive::new(&layer[..]), &root.0).unwrap();
+        }
+        root
+    }
+
+    /// Returns the paths of the files under `root`, sorted.
+    fn tree(root: &Path) -> Vec<String> {
+        fn walk(dir: &Path, root: &Path, paths: &mut Vec<String>) {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                paths.push(path.strip_prefix(root).unwrap().display().to_string());
+                if path.symlink_metadata().unwrap().is_dir() {
+                    walk(&path, root, paths);
+                }
+            }
+        }
+        let mut paths = vec![];
+        walk(root, root, &mut paths);
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn flattened_hides_entries() {
+        let flattened = Flattened {
+            entries: HashMap::from([
+                (PathBuf::from("etc"), true),
+                (PathBuf::from("etc/hostname"), false),
+                (PathBuf::from("usr/lib"), false),
+            ]),
+            whiteouts: HashSet::from([PathBuf::from("var"), PathBuf::from("etc/passwd")]),
+            opaque: HashSet::from([PathBuf::from("opt")]),
+            links: vec![],
+        };
+
+        for hidden in [
+            "etc/hostname",
+            "etc/passwd",
+            "var",
+            "var/cache/apk",
+            "opt/app",
+            "opt/app/bin",
+            // A file of an upper layer replaces a directory.
+            "usr/lib/libc.so",
+        ] {
+            assert!(flattened.hides(Path::new(hidden)), "{} is visible", hidden);
+        }
+        for visible in ["etc/group", "etc/passwd-", "opt", "usr", "variable"] {
+            assert!(
+                !flattened.hides(Path::new(visible)),
+                "{} is hidden",
+                visible
+            );
+        }
+    }
+
+    #[test]
+    fn export_applies_whiteouts() {
+        let lower = layer(&[
+            Dir("etc"),
+            File("etc/passwd", "root"),
+            File("etc/group", "root"),
+            File("etc/hostname", "old"),
+            Dir("var"),
+            File("var/cache", "cache"),
+        ]);
+        let upper = layer(&[
+            File("etc/.wh.passwd", ""),
+            File("etc/hostname", "new"),
+            File(".wh.var", ""),
+        ]);
+
+        let entries = export(&[lower, upper]);
+        assert_eq!(paths(&entries), vec!["etc", "etc/group", "etc/hostname"]);
+        assert!(entries.contains(&("etc/hostname".to_owned(), "new".to_owned())));
+    }
+
+    #[test]
+    fn export_applies_opaque_whiteouts() {
+        let lower = layer(&[
+            Dir("etc"),
+            File("etc/a", "a"),
+            Dir("etc/sub"),
+            File("etc/sub/b", "b"),
+            File("keep", "keep"),
+        ]);
+        let upper = layer(&[Dir("etc"), File("etc/.wh..wh..opq", ""), File("etc/c", "c")]);
+
+        let entries = export(&[lower, upper]);
+        assert_eq!(paths(&entries), vec!["etc", "etc/c", "keep"]);
+    }
+
+    #[test]
+    fn export_writes_links_last() {
+        let lower = layer(&[
+            Link("bin/sh", "bin/busybox"),
+            File("bin/busybox", "busybox"),
+            Symlink("bin/ls", "busybox"),
+        ]);
+        let upper = layer(&[File("bin/.wh.ls", "")]);
+
+        let entries = export(&[lower, upper]);
+        assert_eq!(paths(&entries), vec!["bin/busybox", "bin/sh"]);
+        assert_eq!(
+            entries.last(),
+            Some(&("bin/sh".to_owned(), "-> bin/busybox".to_owned()))
+        );
+    }
+
+    #[test]
+    fn export_copies_links_to_hidden_targets() {
+        let lower = layer(&[
+            File("bin/busybox", "busybox"),
+            Link("bin/sh", "bin/busybox"),
+            Link("bin/ash", "bin/busybox"),
+        ]);
+
+        let removed = export(&[lower.clone(), layer(&[File("bin/.wh.busybox", "")])]);
+        assert_eq!(paths(&removed), vec!["bin/ash", "bin/sh"]);
+        assert!(removed.contains(&("bin/sh".to_owned(), "busybox".to_owned())));
+        assert!(removed.contains(&("bin/ash".to_owned(), "-> bin/sh".to_owned())));
+
+        let replaced = export(&[lower, layer(&[File("bin/busybox", "new")])]);
+        assert_eq!(paths(&replaced), vec!["bin/ash", "bin/busybox", "bin/sh"]);
+        assert!(replaced.contains(&("bin/busybox".to_owned(), "new".to_owned())));
+        assert!(replaced.contains(&("bin/sh".to_owned(), "busybox".to_owned())));
+    }
+
+    #[test]
+    fn unpack_applies_whiteouts() {
+        let lower = layer(&[
+            Dir("etc"),
+            File("etc/a", "a"),
+            File("etc/b", "b"),
+            Dir("var"),
+            File("var/x", "x"),
+            File("keep", "keep"),
+        ]);
+        let upper = layer(&[
+            File("etc/.wh..wh..opq", ""),
+            File("etc/c", "c"),
+            File(".wh.var", ""),
+            File("keep", "new"),
+        ]);
+
+        let root = unpack(&[lower, upper]);
+        assert_eq!(tree(&root.0), vec!["etc", "etc/c", "keep"]);
+        assert_eq!(fs::read_to_string(root.0.join("keep")).unwrap(), "new");
+    }
+
+    #[test]
+    fn unpack_replaces_files_and_directories() {
+        let lower = layer(&[Dir("a"), File("a/x", "x"), File("b", "b")]);
+        let upper = layer(&[File("a", "file"), Dir("b"), File("b/y", "y")]);
+
+        let root = unpack(&[lower, upper]);
+        assert_eq!(tree(&root.0), vec!["a", "b", "b/y"]);
+        assert_eq!(fs::read_to_string(root.0.join("a")).unwrap(), "file");
+    }
+
+    #[test]
+    fn unpack_stays_in_root() {
+        let outside = TempDir::new();
+        fs::write(outside.0.join("victim"), "safe").unwrap();
+        let target = outside.0.display().to_string();
+
+        let lower = layer(&[Symlink("escape", &target)]);
+        let upper = layer(&[
+            File("escape/.wh.victim", ""),
+            File("escape/.wh..wh..opq", ""),
+        ]);
+        unpack(&[lower, upper]);
+
+        assert_eq!(
+            fs::read_to_string(outside.0.join("victim")).unwrap(),
+            "safe"
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! Request prioritization.
+//!
+//! When a maximum number of concurrent requests is set (see
+//! [`DockerRegistryClientV2::set_max_concurrent_requests`]), requests wait
+//! for a free slot. Manifest, config and `HEAD` requests have
+//! [`Priority::High`] and get the next free slot before blob downloads, so
+//! tag resolution stays fast while layers saturate the client. A blob
+//! download holds its slot until its body is consumed (or dropped).
+
+use crate::DockerRegistryClientV2;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::oneshot;
+
+/// Priority of a request waiting for a free slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+    /// Small requests (manifests, configs, `HEAD`).
+    High,
+    /// Blob downloads.
+    Low,
+}
+
+/// Shared limit on the number of requests in progress.
+#[derive(Clone, Debug)]
+pub(crate) struct Scheduler {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    max: Option<usize>,
+    in_progress: usize,
+    high: VecDeque<oneshot::Sender<Permit>>,
+    low: VecDeque<oneshot::Sender<Permit>>,
+}
+
+/// Requests in progress and waiting for a slot, see
+/// [`crate::diagnostics`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SchedulerState {
+    /// Maximum number of requests in progress, `None` for no limit.
+    pub max_concurrent_requests: Option<usize>,
+    /// Requests holding a slot (blob downloads until their body is
+    /// consumed).
+    pub in_progress: usize,
+    /// [`Priority::High`] requests waiting for a slot.
+    pub queued_high: usize,
+    /// [`Priority::Low`] requests waiting for a slot.
+    pub queued_low: usize,
+}
+
+/// A request slot, released when dropped.
+#[derive(Debug)]
+pub(crate) struct Permit {
+    state: Option<Arc<Mutex<State>>>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl Scheduler {
+    pub(crate) fn new(max_concurrent_requests: Option<usize>) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                max: max_concurrent_requests.map(|max| max.max(1)),
+                in_progress: 0,
+                high: VecDeque::new(),
+                low: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// Wait for a request slot.
+    pub(crate) async fn acquire(&self, priority: Priority) -> Permit {
+        let state = &self.state;
+
+        let receiver = {
+            let mut guard = state.lock().unwrap();
+            if guard.max.is_none_or(|max| guard.in_progress < max) {
+                guard.in_progress += 1;
+                return Permit {
+                    state: Some(state.clone()),
+                };
+            }
+
+            let (sender, receiver) = oneshot::channel();
+            match priority {
+                Priority::High => guard.high.push_back(sender),
+                Priority::Low => guard.low.push_back(sender),
+            }
+            receiver
+        };
+
+        // Senders are only dropped with the scheduler, which outlives this
+        // call.
+        receiver.await.unwrap_or(Permit { state: None })
+    }
+
+    /// Returns the current number of requests in progress and waiting.
+    pub(crate) fn state(&self) -> SchedulerState {
+        let guard = self.state.lock().unwrap();
+        SchedulerState {
+            max_concurrent_requests: guard.max,
+            in_progress: guard.in_progress,
+            // Waiters that gave up are removed when a slot is released.
+            queued_high: guard.high.iter().filter(|w| !w.is_closed()).count(),
+            queued_low: guard.low.iter().filter(|w| !w.is_closed()).count(),
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let state = match self.state.take() {
+            Some(state) => state,
+            None => return,
+        };
+
+        let mut guard = state.lock().unwrap();
+        loop {
+            let waiter = match guard.high.pop_front() {
+                Some(waiter) => waiter,
+                None => match guard.low.pop_front() {
+                    Some(waiter) => waiter,
+                    None => break,
+                },
+            };
+
+            // Hand the slot over, unless the waiter gave up.
+            let permit = Permit {
+                state: Some(state.clone()),
+            };
+            match waiter.send(permit) {
+                Ok(()) => return,
+                Err(mut permit) => permit.state = None,
+            }
+        }
+        guard.in_progress -= 1;
+    }
+}
+
+impl DockerRegistryClientV2 {
+    /// Set the maximum number of registry requests in progress at the same
+    /// time, `None` (the default) for no limit.
+    ///
+    /// See [`crate::scheduler`] for how waiting requests are prioritized.
+    /// Clones of the client share the limit.
+    pub fn set_max_concurrent_requests(&mut self, max: Option<usize>) {
+        self.scheduler = Scheduler::new(max);
+    }
+}
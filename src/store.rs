@@ -0,0 +1,424 @@
+//! Local content store.
+//!
+//! A [`ContentStore`] keeps blobs on disk, addressed by digest, following
+//! either the [OCI image
+//! layout](https://github.com/opencontainers/image-spec/blob/main/image-layout.md)
+//! or the layout of containerd's local content store, so pulled content can
+//! be adopted by containerd without downloading it again:
+//!
+//! ```text
+//! <root>/blobs/sha256/<hash>     committed blobs (both layouts)
+//! <root>/ingest/<key>/data       in-progress writes, one per writer
+//!                                (containerd: with ref, startedat,
+//!                                updatedat and total files)
+//! <root>/labels/sha256/<hash>    blob labels, as a JSON object (containerd)
+//! ```
+//!
+//! ```no_run
+//! use oci_registry_client::{
+//!     store::{ContentStore, StoreLayout},
+//!     DockerRegistryClientV2,
+//! };
+//! # use oci_registry_client::manifest::Digest;
+//!
+//! # async fn example(mut client: DockerRegistryClientV2, digest: Digest) -> Result<(), Box<dyn std::error::Error>> {
+//! client.set_content_store(Some(ContentStore::new(
+//!     "/var/lib/containerd/io.containerd.content.v1.content",
+//!     StoreLayout::Containerd,
+//! )));
+//!
+//! let path = client.store_blob("library/alpine", &digest).await?;
+//! println!("stored at {}", path.display());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{blob::Blob, errors::ErrorResponse, manifest::Digest, verify::VerificationPolicy};
+use bytes::Bytes;
+use sha2::{Digest as Sha256Digest, Sha256};
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{fs, io::AsyncWriteExt};
+
+/// Label containerd uses to record the repository a blob was pulled from,
+/// suffixed by the registry host.
+pub const LABEL_DISTRIBUTION_SOURCE: &str = "containerd.io/distribution.source";
+
+/// On-disk layout of a [`ContentStore`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StoreLayout {
+    /// OCI image layout (`oci-layout` and `blobs/`).
+    #[default]
+    Oci,
+    /// containerd local content store (`ingest/`, `blobs/` and labels).
+    Containerd,
+}
+
+/// A directory of blobs addressed by digest.
+#[derive(Clone, Debug)]
+pub struct ContentStore {
+    root: PathBuf,
+    layout: StoreLayout,
+}
+
+impl ContentStore {
+    /// Returns a store rooted at `root`, the directory is created on the
+    /// first write.
+    pub fn new<P: Into<PathBuf>>(root: P, layout: StoreLayout) -> Self {
+        Self {
+            root: root.into(),
+            layout,
+        }
+    }
+
+    /// Returns the root directory of this store.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the layout of this store.
+    pub fn layout(&self) -> StoreLayout {
+        self.layout
+    }
+
+    /// Returns the path of the blob identified by `digest`.
+    pub fn blob_path(&self, digest: &Digest) -> PathBuf {
+        self.root
+            .join("blobs")
+            .join(&digest.algorithm)
+            .join(&digest.hash)
+    }
+
+    fn labels_path(&self, digest: &Digest) -> PathBuf {
+        self.root
+            .join("labels")
+            .join(&digest.algorithm)
+            .join(&digest.hash)
+    }
+
+    /// Returns `true` if the blob identified by `digest` is stored.
+    pub async fn contains(&self, digest: &Digest) -> bool {
+        fs::metadata(self.blob_path(digest)).await.is_ok()
+    }
+
+    /// Store `content` as the blob identified by `digest`.
+    ///
+    /// `reference` identifies the write, containerd uses keys such as
+    /// `layer-sha256:...` or `manifest-sha256:...`.
+    pub async fn write(
+        &self,
+        reference: &str,
+        digest: &Digest,
+        content: &[u8],
+    ) -> Result<PathBuf, ErrorResponse> {
+        let mut ingest = self.begin(reference, Some(content.len() as u64)).await?;
+        ingest.write(content).await?;
+        ingest
+            .commit(self, digest, VerificationPolicy::default())
+            .await
+    }
+
+    /// Download `blob` into the store as the blob identified by `digest`.
+    ///
+    /// Content is written to the ingest directory and moved into `blobs/`
+    /// once its digest has been verified.
+    pub async fn write_blob(
+        &self,
+        reference: &str,
+        digest: &Digest,
+        blob: Blob,
+    ) -> Result<PathBuf, ErrorResponse> {
+        self.ingest_blob(reference, digest, blob, VerificationPolicy::default())
+            .await
+    }
+
+    /// Download `blob` into the store, verifying it according to `policy`.
+    pub(crate) async fn ingest_blob(
+        &self,
+        reference: &str,
+        digest: &Digest,
+        mut blob: Blob,
+        policy: VerificationPolicy,
+    ) -> Result<PathBuf, ErrorResponse> {
+        let mut ingest = self
+            .begin(reference, blob.len().map(|len| len as u64))
+            .await?;
+        while let Some(chunk) = blob.chunk().await? {
+            ingest.write(&chunk).await?;
+        }
+        ingest.commit(self, digest, policy).await
+    }
+
+    /// Returns the labels of the blob identified by `digest`.
+    pub async fn labels(&self, digest: &Digest) -> Result<HashMap<String, String>, ErrorResponse> {
+        match fs::read(self.labels_path(digest)).await {
+            Ok(content) => Ok(serde_json::from_slice(&content)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Merge `labels` into the labels of the blob identified by `digest`.
+    ///
+    /// Labels are only kept by the [`StoreLayout::Containerd`] layout, this
+    /// is a no-op otherwise.
+    pub async fn set_labels(
+        &self,
+        digest: &Digest,
+        labels: &HashMap<String, String>,
+    ) -> Result<(), ErrorResponse> {
+        if self.layout != StoreLayout::Containerd || labels.is_empty() {
+            return Ok(());
+        }
+
+        let mut current = self.labels(digest).await?;
+        current.extend(labels.clone());
+
+        let path = self.labels_path(digest);
+        fs::create_dir_all(path.parent().unwrap()).await?;
+        write_atomic(&path, &serde_json::to_vec(&current)?).await?;
+        Ok(())
+    }
+
+    /// Returns a blob that writes `blob` into the store as it is read.
+    pub(crate) async fn tee(
+        &self,
+        reference: &str,
+        digest: &Digest,
+        blob: Blob,
+        labels: HashMap<String, String>,
+        policy: VerificationPolicy,
+    ) -> Result<TeeBlob, ErrorResponse> {
+        let ingest = self
+            .begin(reference, blob.len().map(|len| len as u64))
+            .await?;
+        Ok(TeeBlob {
+            blob,
+            store: self.clone(),
+            digest: digest.clone(),
+            labels,
+            policy,
+            ingest: Some(ingest),
+            path: None,
+        })
+    }
+
+    async fn begin(&self, reference: &str, total: Option<u64>) -> Result<Ingest, ErrorResponse> {
+        if self.layout == StoreLayout::Oci {
+            let layout = self.root.join("oci-layout");
+            if fs::metadata(&layout).await.is_err() {
+                fs::create_dir_all(&self.root).await?;
+                write_atomic(&layout, br#"{"imageLayoutVersion":"1.0.0"}"#).await?;
+            }
+        }
+
+        // containerd keys ingests by the digest of their reference, writers
+        // of the same reference (concurrent pulls of a blob) get their own.
+        let key = format!(
+            "{:x}",
+            Sha256::digest(format!("{}-{}", reference, unique_suffix()).as_bytes())
+        );
+        let dir = self.root.join("ingest").join(key);
+        fs::create_dir_all(&dir).await?;
+
+        if self.layout == StoreLayout::Containerd {
+            let now = rfc3339(SystemTime::now());
+            fs::write(dir.join("ref"), reference).await?;
+            fs::write(dir.join("startedat"), &now).await?;
+            fs::write(dir.join("updatedat"), &now).await?;
+            if let Some(total) = total {
+                fs::write(dir.join("total"), total.to_string()).await?;
+            }
+        }
+
+        let data = dir.join("data");
+        Ok(Ingest {
+            file: fs::File::create(&data).await?,
+            dir,
+            data,
+            hasher: Sha256::new(),
+            total,
+            written: 0,
+        })
+    }
+}
+
+/// A blob read from a [`ContentStore`] or from the registry.
+pub enum CachedBlob {
+    /// The blob is stored at this path.
+    Hit(PathBuf),
+    /// The blob is downloaded, and stored as it is read.
+    Miss(Box<TeeBlob>),
+}
+
+/// A blob being downloaded into a [`ContentStore`].
+///
+/// Content is written to the store as chunks are read, the blob is committed
+/// (after verifying its digest) once the last chunk has been read.
+pub struct TeeBlob {
+    blob: Blob,
+    store: ContentStore,
+    digest: Digest,
+    labels: HashMap<String, String>,
+    policy: VerificationPolicy,
+    ingest: Option<Ingest>,
+    path: Option<PathBuf>,
+}
+
+impl TeeBlob {
+    /// Returns the total length of this blob.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> Option<usize> {
+        self.blob.len()
+    }
+
+    /// Stream a chunk of the blob contents.
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, ErrorResponse> {
+        let ingest = match &mut self.ingest {
+            Some(ingest) => ingest,
+            None => return Ok(None),
+        };
+
+        match self.blob.chunk().await? {
+            Some(chunk) => {
+                ingest.write(&chunk).await?;
+                Ok(Some(chunk))
+            }
+            None => {
+                let ingest = self.ingest.take().unwrap();
+                let path = ingest
+                    .commit(&self.store, &self.digest, self.policy)
+                    .await?;
+                self.store.set_labels(&self.digest, &self.labels).await?;
+                self.path = Some(path);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Returns the path of the stored blob, once it has been read entirely.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+}
+
+/// An in-progress write.
+struct Ingest {
+    dir: PathBuf,
+    data: PathBuf,
+    file: fs::File,
+    hasher: Sha256,
+    total: Option<u64>,
+    written: u64,
+}
+
+impl Ingest {
+    async fn write(&mut self, chunk: &[u8]) -> Result<(), ErrorResponse> {
+        self.hasher.input(chunk);
+        self.file.write_all(chunk).await?;
+        self.written += chunk.len() as u64;
+        Ok(())
+    }
+
+    async fn commit(
+        mut self,
+        store: &ContentStore,
+        digest: &Digest,
+        policy: VerificationPolicy,
+    ) -> Result<PathBuf, ErrorResponse> {
+        self.file.flush().await?;
+        self.file.sync_all().await?;
+        drop(self.file);
+
+        let actual = Digest::from_sha256(self.hasher.result());
+        let verified = policy
+            .check_size(self.total, self.written)
+            .and_then(|_| policy.check_digest(digest, &actual));
+        if let Err(err) = verified {
+            let _ = fs::remove_dir_all(&self.dir).await;
+            return Err(err);
+        }
+
+        // Unverified content is stored under the digest it actually has, so
+        // a mismatch allowed by the policy never ends up under `digest`.
+        let stored = match actual.algorithm == digest.algorithm {
+            true => &actual,
+            false => digest,
+        };
+        // Another writer may have committed the blob meanwhile, the content
+        // is the same whichever stays.
+        let path = store.blob_path(stored);
+        fs::create_dir_all(path.parent().unwrap()).await?;
+        if !store.contains(stored).await {
+            fs::rename(&self.data, &path).await?;
+        }
+        fs::remove_dir_all(&self.dir).await?;
+        if store.layout == StoreLayout::Oci {
+            // Keep the OCI layout clean, fails if other writes are in progress.
+            let _ = fs::remove_dir(store.root.join("ingest")).await;
+        }
+        Ok(path)
+    }
+}
+
+/// Write `content` to `path` through a temporary file, concurrent writers
+/// each write their own.
+pub(crate) async fn write_atomic(path: &Path, content: &[u8]) -> io::Result<()> {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.tmp", unique_suffix()));
+    let tmp = path.with_file_name(name);
+    let result = async {
+        fs::write(&tmp, content).await?;
+        fs::rename(&tmp, path).await
+    }
+    .await;
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp).await;
+    }
+    result
+}
+
+/// Returns a suffix unique to this call among the processes of the host.
+pub(crate) fn unique_suffix() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    format!(
+        "{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// Format `time` as a RFC 3339 UTC timestamp, the format containerd uses in
+/// ingest files.
+fn rfc3339(time: SystemTime) -> String {
+    let elapsed = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = elapsed.as_secs();
+    let (days, rem) = (secs / 86400, secs % 86400);
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm).
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:09}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60,
+        elapsed.subsec_nanos()
+    )
+}
@@ -0,0 +1,154 @@
+//! Request timeouts.
+//!
+//! A single total timeout does not fit registry traffic: large layers
+//! legitimately take minutes to download, while a stalled stream should fail
+//! fast. [`Timeouts`] bounds each phase of a request instead, per kind of
+//! request:
+//!
+//! - `connect`: establishing the connection (and TLS handshake),
+//! - `first_byte`: from sending the request to receiving the response
+//!   headers,
+//! - `idle`: between two chunks of the response body,
+//! - `total`: the whole request, reading the response body included.
+//!
+//! Expired timeouts fail with [`ErrorResponse::Timeout`]. Blob downloads
+//! have no total timeout by default, only stalled downloads fail.
+//!
+//! ```
+//! # use oci_registry_client::{timeout::{PhaseTimeouts, Timeouts}, DockerRegistryClientV2};
+//! # use std::time::Duration;
+//! # fn example(mut client: DockerRegistryClientV2) -> Result<(), Box<dyn std::error::Error>> {
+//! client.set_timeouts(Timeouts {
+//!     connect: Some(Duration::from_secs(5)),
+//!     manifests: PhaseTimeouts {
+//!         first_byte: Some(Duration::from_secs(10)),
+//!         idle: Some(Duration::from_secs(10)),
+//!         total: Some(Duration::from_secs(60)),
+//!     },
+//!     blobs: PhaseTimeouts {
+//!         first_byte: Some(Duration::from_secs(30)),
+//!         idle: Some(Duration::from_secs(20)),
+//!         total: None,
+//!     },
+//! })?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{errors::ErrorResponse, redirect};
+use std::{fmt, future::Future, time::Duration};
+
+/// Timeouts of the phases of requests, see the [module](self) documentation.
+///
+/// By default, connecting times out after 30 seconds, manifest requests
+/// after 30 seconds without data or 2 minutes in total, and blob downloads
+/// after 60 seconds without data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timeouts {
+    pub connect: Option<Duration>,
+    /// Manifest, config, token and `HEAD` requests.
+    pub manifests: PhaseTimeouts,
+    /// Blob downloads.
+    pub blobs: PhaseTimeouts,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            connect: Some(Duration::from_secs(30)),
+            manifests: PhaseTimeouts {
+                first_byte: Some(Duration::from_secs(30)),
+                idle: Some(Duration::from_secs(30)),
+                total: Some(Duration::from_secs(120)),
+            },
+            blobs: PhaseTimeouts {
+                first_byte: Some(Duration::from_secs(60)),
+                idle: Some(Duration::from_secs(60)),
+                total: None,
+            },
+        }
+    }
+}
+
+impl Timeouts {
+    /// Returns timeouts never expiring.
+    pub fn none() -> Self {
+        Self {
+            connect: None,
+            manifests: PhaseTimeouts::default(),
+            blobs: PhaseTimeouts::default(),
+        }
+    }
+}
+
+/// Timeouts of a kind of request, once connected, none by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PhaseTimeouts {
+    /// Maximum time to receive the response headers.
+    pub first_byte: Option<Duration>,
+    /// Maximum time between two chunks of the response body.
+    pub idle: Option<Duration>,
+    /// Maximum time of the whole request, reading the response body
+    /// included.
+    pub total: Option<Duration>,
+}
+
+/// The phase of a request that timed out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeoutPhase {
+    Connect,
+    FirstByte,
+    Idle,
+    Total,
+}
+
+impl fmt::Display for TimeoutPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Connect => write!(f, "connect"),
+            Self::FirstByte => write!(f, "time to first byte"),
+            Self::Idle => write!(f, "idle"),
+            Self::Total => write!(f, "total"),
+        }
+    }
+}
+
+/// Run `future`, failing with a `phase` timeout after `timeout`.
+pub(crate) async fn within<F, T>(
+    timeout: Option<Duration>,
+    phase: TimeoutPhase,
+    future: F,
+) -> Result<T, ErrorResponse>
+where
+    F: Future<Output = T>,
+{
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future)
+            .await
+            .map_err(|_| ErrorResponse::timeout(phase)),
+        None => Ok(future.await),
+    }
+}
+
+/// Send `request`, applying the `first_byte` timeout of `timeouts` to every
+/// request sent (see [`crate::redirect`]).
+pub(crate) async fn send(
+    request: reqwest::RequestBuilder,
+    timeouts: &PhaseTimeouts,
+) -> Result<reqwest::Response, ErrorResponse> {
+    redirect::send(request, timeouts).await
+}
+
+/// Execute `request`, applying the `first_byte` and `total` timeouts of
+/// `timeouts`.
+pub(crate) async fn execute(
+    client: &reqwest::Client,
+    mut request: reqwest::Request,
+    timeouts: &PhaseTimeouts,
+) -> Result<reqwest::Response, ErrorResponse> {
+    if request.timeout().is_none() {
+        *request.timeout_mut() = timeouts.total;
+    }
+    let response = client.execute(request);
+    Ok(within(timeouts.first_byte, TimeoutPhase::FirstByte, response).await??)
+}
@@ -0,0 +1,144 @@
+//! TLS configuration.
+//!
+//! ```no_run
+//! use oci_registry_client::{tls::TlsConfig, DockerRegistryClientV2};
+//!
+//! # fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let tls = TlsConfig::new().identity_pem(
+//!     &std::fs::read("client.crt")?,
+//!     &std::fs::read("client.key")?,
+//! )?;
+//! let client = DockerRegistryClientV2::with_tls(
+//!     "registry.example.com",
+//!     "https://registry.example.com",
+//!     "https://registry.example.com/token",
+//!     tls,
+//! )?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::errors::ErrorResponse;
+use reqwest::{Certificate, ClientBuilder, Identity};
+use std::net::SocketAddr;
+
+/// TLS options used when building the underlying HTTP client.
+#[derive(Clone, Debug, Default)]
+pub struct TlsConfig {
+    identity: Option<Identity>,
+    root_certificates: Vec<Certificate>,
+    accept_invalid_certs: bool,
+    resolve: Vec<(String, SocketAddr)>,
+    disable_sni: bool,
+}
+
+impl TlsConfig {
+    /// Returns a `TlsConfig` using the system defaults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Present a client certificate during the TLS handshake.
+    ///
+    /// # Arguments
+    ///
+    /// * `cert` - PEM encoded certificate chain.
+    /// * `key` - PEM encoded PKCS#8 private key.
+    pub fn identity_pem(mut self, cert: &[u8], key: &[u8]) -> Result<Self, ErrorResponse> {
+        self.identity = Some(Identity::from_pkcs8_pem(cert, key)?);
+        Ok(self)
+    }
+
+    /// Present a client certificate during the TLS handshake.
+    ///
+    /// # Arguments
+    ///
+    /// * `der` - DER encoded PKCS#12 archive with the certificate and key.
+    /// * `password` - Password to decrypt the archive.
+    pub fn identity_pkcs12(mut self, der: &[u8], password: &str) -> Result<Self, ErrorResponse> {
+        self.identity = Some(Identity::from_pkcs12_der(der, password)?);
+        Ok(self)
+    }
+
+    /// Trust the certificates in a PEM encoded CA bundle, in addition to the
+    /// system roots.
+    pub fn root_certificates_pem(mut self, pem: &[u8]) -> Result<Self, ErrorResponse> {
+        self.root_certificates
+            .extend(Certificate::from_pem_bundle(pem)?);
+        Ok(self)
+    }
+
+    /// Trust a DER encoded CA certificate, in addition to the system roots.
+    pub fn root_certificate_der(mut self, der: &[u8]) -> Result<Self, ErrorResponse> {
+        self.root_certificates.push(Certificate::from_der(der)?);
+        Ok(self)
+    }
+
+    /// Accept invalid certificates (self-signed, expired, wrong hostname).
+    ///
+    /// # Warning
+    ///
+    /// This disables certificate validation entirely and makes connections
+    /// vulnerable to man-in-the-middle attacks. Only use it for local testing.
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.accept_invalid_certs = accept;
+        self
+    }
+
+    /// Connect to `addr` when talking to `host`, can be called several times
+    /// to connect to one of several addresses.
+    ///
+    /// The request URL keeps `host`, so it is still used for the `Host`
+    /// header, TLS SNI and certificate validation. Useful for registries
+    /// fronted by shared load balancers or only reachable by IP address.
+    /// The port of `addr` is ignored, the port of the URL is used.
+    ///
+    /// To keep the API URL of a client pointing to the connect address, see
+    /// [`crate::builder::ClientBuilder::server_name`].
+    ///
+    /// ```
+    /// # use oci_registry_client::tls::TlsConfig;
+    /// let tls = TlsConfig::new().resolve("registry.example.com", "10.0.0.12:443".parse().unwrap());
+    /// ```
+    pub fn resolve<T: Into<String>>(mut self, host: T, addr: SocketAddr) -> Self {
+        self.resolve.push((host.into(), addr));
+        self
+    }
+
+    /// Send the TLS Server Name Indication extension (enabled by default).
+    pub fn sni(mut self, enabled: bool) -> Self {
+        self.disable_sni = !enabled;
+        self
+    }
+
+    pub(crate) fn apply(&self, mut builder: ClientBuilder) -> ClientBuilder {
+        if let Some(identity) = &self.identity {
+            builder = builder.identity(identity.clone());
+        }
+
+        for certificate in &self.root_certificates {
+            builder = builder.add_root_certificate(certificate.clone());
+        }
+
+        if self.accept_invalid_certs {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+
+        let mut resolve: Vec<(&str, Vec<SocketAddr>)> = vec![];
+        for (host, addr) in &self.resolve {
+            match resolve.iter_mut().find(|(name, _)| name == host) {
+                Some((_, addrs)) => addrs.push(*addr),
+                None => resolve.push((host, vec![*addr])),
+            }
+        }
+        for (host, addrs) in resolve {
+            builder = builder.resolve_to_addrs(host, &addrs);
+        }
+
+        if self.disable_sni {
+            builder = builder.tls_sni(false);
+        }
+
+        builder
+    }
+}
@@ -0,0 +1,99 @@
+//! Content verification.
+//!
+//! The [`VerificationPolicy`] of a client selects the checks applied to
+//! fetched manifests and blobs, trading CPU for assurance:
+//!
+//! ```
+//! # use oci_registry_client::{verify::VerificationPolicy, DockerRegistryClientV2};
+//! # let mut client = DockerRegistryClientV2::try_new("registry.docker.io", "https://registry-1.docker.io", "https://auth.docker.io/token").unwrap();
+//! client.set_verification_policy(VerificationPolicy::SizesOnly);
+//! ```
+
+use crate::{errors::ErrorResponse, manifest::Digest};
+
+/// Checks applied to content fetched from a registry.
+///
+/// Policies are ordered, each level includes the checks of the previous
+/// ones.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum VerificationPolicy {
+    /// No verification.
+    Off,
+    /// Check that the length of the content matches the announced size.
+    SizesOnly,
+    /// Check that the content matches its digest, skipping digests of
+    /// algorithms this build can't compute.
+    SupportedDigests,
+    /// Check that the content matches its digest (default), content with
+    /// a digest of an unsupported algorithm is rejected.
+    #[default]
+    Digests,
+    /// Also check that uncompressed layers match the `diff_ids` of the image
+    /// config, when layers are unpacked.
+    DigestsAndDiffIds,
+}
+
+impl VerificationPolicy {
+    /// Returns `true` if content sizes are checked.
+    pub fn verifies_sizes(self) -> bool {
+        self >= Self::SizesOnly
+    }
+
+    /// Returns `true` if content digests are checked.
+    pub fn verifies_digests(self) -> bool {
+        self >= Self::SupportedDigests
+    }
+
+    /// Returns `true` if uncompressed layer digests are checked.
+    pub fn verifies_diff_ids(self) -> bool {
+        self >= Self::DigestsAndDiffIds
+    }
+
+    /// Check that `actual` bytes were received, if `expected` is known.
+    pub(crate) fn check_size(
+        self,
+        expected: Option<u64>,
+        actual: u64,
+    ) -> Result<(), ErrorResponse> {
+        match expected {
+            Some(expected) if self.verifies_sizes() && expected != actual => {
+                Err(ErrorResponse::size_mismatch(expected, actual))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that content hashed to `actual` matches `expected`.
+    ///
+    /// Digests of different algorithms can't be compared: `expected` uses an
+    /// algorithm this build does not support, which fails with
+    /// `UnsupportedDigestAlgorithm` unless the policy is
+    /// [`VerificationPolicy::SupportedDigests`].
+    pub(crate) fn check_digest(
+        self,
+        expected: &Digest,
+        actual: &Digest,
+    ) -> Result<(), ErrorResponse> {
+        if !self.verifies_digests() {
+            return Ok(());
+        }
+        if expected.algorithm != actual.algorithm {
+            return match self {
+                Self::SupportedDigests => Ok(()),
+                _ => Err(ErrorResponse::UnsupportedDigestAlgorithm {
+                    algorithm: expected.algorithm.clone(),
+                    context: None,
+                }),
+            };
+        }
+        if expected != actual {
+            return Err(ErrorResponse::DigestMismatch {
+                expected: expected.clone(),
+                actual: actual.clone(),
+                url: None,
+                context: None,
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+//! Tag watching.
+//!
+//! [`DockerRegistryClientV2::watch_tag`] polls a tag and emits a
+//! [`TagUpdate`] every time the digest it resolves to changes.
+//!
+//! ```no_run
+//! use futures_util::StreamExt;
+//! use std::time::Duration;
+//! # use oci_registry_client::DockerRegistryClientV2;
+//! # async fn example(client: DockerRegistryClientV2) {
+//! let updates = client.watch_tag("library/alpine", "latest", Duration::from_secs(60));
+//! futures_util::pin_mut!(updates);
+//!
+//! while let Some(update) = updates.next().await {
+//!     match update {
+//!         Ok(update) => println!("{} now points to {}", update.tag, update.digest),
+//!         Err(err) => eprintln!("poll failed; err={}", err),
+//!     }
+//! }
+//! # }
+//! ```
+
+use crate::{
+    errors::ErrorResponse, manifest::Digest, reference::AsRepository, scheduler::Priority,
+    DockerRegistryClientV2,
+};
+use futures_util::stream::{self, Stream};
+use reqwest::{header, StatusCode};
+use std::time::Duration;
+
+/// A change of the digest a tag resolves to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TagUpdate {
+    pub image: String,
+    pub tag: String,
+    pub digest: Digest,
+    /// Digest the tag resolved to before this update, `None` on the first poll.
+    pub previous: Option<Digest>,
+}
+
+struct WatchState {
+    client: DockerRegistryClientV2,
+    image: String,
+    tag: String,
+    interval: Duration,
+    current: Option<Digest>,
+    first: bool,
+}
+
+impl DockerRegistryClientV2 {
+    /// Watch `tag` of `image`, polling the registry every `interval`.
+    ///
+    /// The stream yields the resolved digest on the first poll and then an
+    /// update every time it changes. Polls use conditional requests
+    /// (`If-None-Match`) so unchanged tags are cheap. Failed polls are yielded
+    /// as errors and polling continues.
+    pub fn watch_tag<I: AsRepository + ?Sized>(
+        &self,
+        image: &I,
+        tag: &str,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<TagUpdate, ErrorResponse>> {
+        let image = image.as_repository();
+        let state = WatchState {
+            client: self.clone(),
+            image: image.to_owned(),
+            tag: tag.to_owned(),
+            interval,
+            current: None,
+            first: true,
+        };
+
+        stream::unfold(state, |mut state| async move {
+            loop {
+                if !state.first {
+                    tokio::time::sleep(state.interval).await;
+                }
+                state.first = false;
+
+                let digest = match state.poll().await {
+                    Ok(Some(digest)) => digest,
+                    Ok(None) => continue,
+                    Err(err) => return Some((Err(err), state)),
+                };
+
+                if state.current.as_ref() == Some(&digest) {
+                    continue;
+                }
+
+                let update = TagUpdate {
+                    image: state.image.clone(),
+                    tag: state.tag.clone(),
+                    digest: digest.clone(),
+                    previous: state.current.replace(digest),
+                };
+                return Some((Ok(update), state));
+            }
+        })
+    }
+}
+
+impl WatchState {
+    /// Returns the digest the tag resolves to, or `None` if it is unchanged.
+    async fn poll(&self) -> Result<Option<Digest>, ErrorResponse> {
+        let url = format!(
+            "{}/v2/{}/manifests/{}",
+            self.client.api_url, self.image, self.tag
+        );
+
+        let mut request = self
+            .client
+            .client
+            .head(&url)
+            .header(header::ACCEPT, self.client.manifest_accept.as_str());
+        if let Some(current) = &self.current {
+            request = request.header(header::IF_NONE_MATCH, format!("\"{}\"", current));
+        }
+        let request = self.client.authorize(request, Some(&self.image)).await?;
+        let permit = self.client.scheduler.acquire(Priority::High).await;
+        let response = self
+            .client
+            .send_with_retries(request, &self.client.timeouts.manifests)
+            .await?;
+        drop(permit);
+
+        match response.status() {
+            StatusCode::NOT_MODIFIED => return Ok(None),
+            StatusCode::OK => {
+                let digest = [
+                    header::HeaderName::from_static("docker-content-digest"),
+                    header::ETAG,
+                ]
+                .iter()
+                .filter_map(|name| response.headers().get(name))
+                .filter_map(|value| value.to_str().ok())
+                .find_map(|value| value.trim_matches('"').parse::<Digest>().ok());
+                if digest.is_some() {
+                    return Ok(digest);
+                }
+            }
+            _ => {}
+        }
+
+        // HEAD responses have no body: fetch the manifest for the details of
+        // the error, or to compute the digest the registry did not announce.
+        let (_, _, digest) = self.client.manifest_raw(&self.image, &self.tag).await?;
+        Ok(Some(digest))
+    }
+}